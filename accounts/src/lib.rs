@@ -1,58 +1,155 @@
 use keystore::commands::KeyTypeId;
-use sp_core::{sr25519, sr25519::Public, Pair};
+use sp_core::hashing::keccak_256;
+use sp_core::{ecdsa, ed25519, sr25519, Pair};
 use sp_keystore::SyncCryptoStore;
 use std::error::Error;
 use std::io::{Read, Write};
 use std::str;
 use std::sync::Arc;
 
-const PATH: &str = "./artifacts/account.json";
+pub mod registration;
+pub mod rotation;
+pub mod scheme;
+
+pub use registration::Registration;
+pub use scheme::{AccountKey, AccountScheme};
+
+/// public key of the current account
+pub const ACCOUNT_PATH: &str = "./artifacts/account.json";
 
 #[derive(Clone)]
 pub struct Account {
-    pub accounts: Public,
+    pub accounts: AccountKey,
 }
 
 impl Account {
-    //create a new account
-    //the account is created with a random keypair
-    pub fn new(password: &str, key_type: KeyTypeId, keystore: Arc<dyn SyncCryptoStore>) -> Result<Account, Box<dyn Error>> {
-        let key_pair = sr25519::Pair::generate_with_phrase(Some(password));
-        let pubkey =
-            match SyncCryptoStore::sr25519_generate_new(&*keystore, key_type, Some(&key_pair.1)) {
-                Ok(keypair) => keypair,
-                Err(e) => {
-                    log::error!("Error generating keypair: {:?}", e);
-                    panic!("Error generating keypair: {:?}", e);
-                }
-            };
+    //load the account of the scheme and key type from the keystore
+    //the key recorded in the account file is preferred, otherwise the keystore must hold a single key of the type
+    //returns None when the keystore has no key of the type
+    pub fn load(scheme: AccountScheme, key_type: KeyTypeId, keystore: Arc<dyn SyncCryptoStore>) -> Result<Option<Account>, Box<dyn Error>> {
+        let keys = Self::keystore_keys(scheme, key_type, keystore);
+        let recorded = Self::read_account_from_file(ACCOUNT_PATH, scheme)
+            .ok()
+            .filter(|key| keys.contains(key));
+        let pubkey = match (recorded, keys.as_slice()) {
+            (Some(key), _) => key,
+            (None, []) => return Ok(None),
+            (None, [key]) => *key,
+            (None, _) => {
+                return Err(format!(
+                    "Keystore holds {} keys of type {} and {} names none of them, import the key to use",
+                    keys.len(),
+                    String::from_utf8_lossy(&key_type.0),
+                    ACCOUNT_PATH
+                )
+                .into())
+            }
+        };
+        let acc = Account { accounts: pubkey };
+        acc.gen_key_file(ACCOUNT_PATH)?;
+        Ok(Some(acc))
+    }
+
+    //generate a new account
+    //the account is created with a random keypair and becomes the current account
+    pub fn generate(scheme: AccountScheme, password: &str, key_type: KeyTypeId, keystore: Arc<dyn SyncCryptoStore>) -> Result<Account, Box<dyn Error>> {
+        let pubkey = generate_key(scheme, password, key_type, keystore)?;
+        let acc = Account { accounts: pubkey };
+        acc.gen_key_file(ACCOUNT_PATH)?;
+        Ok(acc)
+    }
+
+    //import an account from a secret URI, i.e. a mnemonic, a hex seed or either of them with a derivation path
+    //the key is stored in the keystore and becomes the current account
+    pub fn import(scheme: AccountScheme, suri: &str, key_type: KeyTypeId, keystore: Arc<dyn SyncCryptoStore>) -> Result<Account, Box<dyn Error>> {
+        let pubkey = AccountKey::from_suri(scheme, suri)?;
+        SyncCryptoStore::insert_unknown(&*keystore, key_type, suri, &pubkey.to_raw_vec())
+            .map_err(|_| "Unable to store key in keystore")?;
         let acc = Account { accounts: pubkey };
-        acc.gen_key_file(PATH)?;
+        acc.gen_key_file(ACCOUNT_PATH)?;
         Ok(acc)
     }
 
+    //public keys of the scheme and key type in the keystore
+    //the keystore tells keys apart by their length only, so sr25519 and ed25519 keys need their own key types
+    pub fn keystore_keys(scheme: AccountScheme, key_type: KeyTypeId, keystore: Arc<dyn SyncCryptoStore>) -> Vec<AccountKey> {
+        match scheme {
+            AccountScheme::Sr25519 => SyncCryptoStore::sr25519_public_keys(&*keystore, key_type)
+                .into_iter()
+                .map(AccountKey::Sr25519)
+                .collect(),
+            AccountScheme::Ed25519 => SyncCryptoStore::ed25519_public_keys(&*keystore, key_type)
+                .into_iter()
+                .map(AccountKey::Ed25519)
+                .collect(),
+            AccountScheme::Ecdsa => SyncCryptoStore::ecdsa_public_keys(&*keystore, key_type)
+                .into_iter()
+                .map(AccountKey::Ecdsa)
+                .collect(),
+        }
+    }
+
+    //sign a message with the account key in the keystore
+    //ecdsa keys sign the keccak-256 hash of the message, as EVM contracts recover signers from it
+    pub fn sign(&self, msg: &[u8], key_type: KeyTypeId, keystore: Arc<dyn SyncCryptoStore>) -> Result<Vec<u8>, Box<dyn Error>> {
+        let signature = match self.accounts {
+            AccountKey::Ecdsa(key) => SyncCryptoStore::ecdsa_sign_prehashed(&*keystore, key_type, &key, &keccak_256(msg))
+                .map_err(|e| format!("Error signing data: {:?}", e))?
+                .map(|signature| signature.0.to_vec()),
+            key => SyncCryptoStore::sign_with(&*keystore, key_type, &key.to_public_crypto_pair(), msg)
+                .map_err(|e| format!("Error signing data: {:?}", e))?,
+        };
+        signature.ok_or_else(|| "Key doesn't exist".into())
+    }
+
     //get current account
-    pub fn get_current_account(&self) -> Public {
+    pub fn get_current_account(&self) -> AccountKey {
         return self.accounts;
     }
 
     //store account to a json file
     pub fn gen_key_file(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
         let mut file = std::fs::File::create(path)?;
-        file.write_all(&self.accounts.to_vec())?;
+        file.write_all(&self.accounts.to_raw_vec())?;
         Ok(())
     }
 
-    //load account from a json file
-    pub fn read_account_from_file(path: &str) -> Result<Public, Box<dyn Error>> {
-        let mut file = std::fs::File::open(path).unwrap();
-        let mut buffer = [0; 32];
-        file.read_exact(&mut buffer).unwrap();
-        let key = Public::from_raw(buffer);
+    //load account of the scheme from a json file
+    pub fn read_account_from_file(path: &str, scheme: AccountScheme) -> Result<AccountKey, Box<dyn Error>> {
+        let mut file = std::fs::File::open(path)?;
+        let mut buffer = vec![];
+        file.read_to_end(&mut buffer)?;
+        let key = AccountKey::from_raw(scheme, &buffer)?;
         Ok(key)
     }
 }
 
+//generate a random keypair of the scheme in the keystore
+//the key is derived from a new phrase and the password, so the phrase alone does not recover it
+fn generate_key(scheme: AccountScheme, password: &str, key_type: KeyTypeId, keystore: Arc<dyn SyncCryptoStore>) -> Result<AccountKey, Box<dyn Error>> {
+    let (_, phrase, _) = sr25519::Pair::generate_with_phrase(None);
+    let suri = seed_from_phrase(scheme, &phrase, password)?;
+    let pubkey = AccountKey::from_suri(scheme, &suri)?;
+    SyncCryptoStore::insert_unknown(&*keystore, key_type, &suri, &pubkey.to_raw_vec())
+        .map_err(|_| "Unable to store key in keystore")?;
+    Ok(pubkey)
+}
+
+//hex seed of the key of the scheme derived from a phrase and a password
+//the keystore rebuilds keys from what it stores without the password, a seed keeps the password applied
+fn seed_from_phrase(scheme: AccountScheme, phrase: &str, password: &str) -> Result<String, Box<dyn Error>> {
+    let seed = match scheme {
+        AccountScheme::Sr25519 => sr25519::Pair::from_phrase(phrase, Some(password)).map(|(_, seed)| seed.to_vec()),
+        AccountScheme::Ed25519 => ed25519::Pair::from_phrase(phrase, Some(password)).map(|(_, seed)| seed.to_vec()),
+        AccountScheme::Ecdsa => ecdsa::Pair::from_phrase(phrase, Some(password)).map(|(_, seed)| seed.to_vec()),
+    };
+    let seed = seed.map_err(|e| format!("Error generating keypair: {:?}", e))?;
+    Ok(sp_core::bytes::to_hex(&seed, false))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -61,12 +158,15 @@ mod tests {
     use sc_keystore::LocalKeystore;
     use sc_service::config::KeystoreConfig;
     use sp_keystore::SyncCryptoStorePtr;
-    use std::{convert::TryFrom, env, sync::Arc};
+    use std::{convert::TryFrom, env, sync::Arc, sync::Mutex};
 
+    //the tests share the account file
+    static ACCOUNT_FILE: Mutex<()> = Mutex::new(());
 
     //tests account creation, keypair generation and keypair storage
     #[test]
     fn test_load_pubkey_file() {
+        let _guard = ACCOUNT_FILE.lock().unwrap_or_else(|e| e.into_inner());
         let keystore_params = KeystoreParams::default();
 
         let config_dir = env::current_dir().unwrap();
@@ -80,8 +180,90 @@ mod tests {
             _ => unreachable!("keystore_config always returns path and password; qed"),
         };
         let key_type = KeyTypeId::try_from("TNGO").unwrap();
-        let acc = Account::new("123456", key_type, keystore).unwrap();
-        let account_str = Account::read_account_from_file(PATH).unwrap();
+        let acc = Account::generate(AccountScheme::Sr25519, "123456", key_type, keystore).unwrap();
+        let account_str = Account::read_account_from_file(ACCOUNT_PATH, AccountScheme::Sr25519).unwrap();
         assert_eq!(acc.accounts, account_str);
     }
+
+    //tests importing an account from a secret URI
+    #[test]
+    fn test_import_account() {
+        let _guard = ACCOUNT_FILE.lock().unwrap_or_else(|e| e.into_inner());
+        let keystore: SyncCryptoStorePtr = Arc::new(LocalKeystore::in_memory());
+        let key_type = KeyTypeId::try_from("TNGO").unwrap();
+        let acc = Account::import(AccountScheme::Sr25519, "//Alice", key_type, keystore.clone()).unwrap();
+
+        let expected = sr25519::Pair::from_string("//Alice", None).unwrap().public();
+        assert_eq!(acc.accounts, AccountKey::Sr25519(expected));
+        assert!(SyncCryptoStore::has_keys(&*keystore, &[(expected.to_vec(), key_type)]));
+        assert!(Account::import(AccountScheme::Sr25519, "not a secret", key_type, keystore).is_err());
+    }
+
+    //tests that an existing key is reused instead of generating a new one
+    #[test]
+    fn test_load_existing_account() {
+        let _guard = ACCOUNT_FILE.lock().unwrap_or_else(|e| e.into_inner());
+        let keystore: SyncCryptoStorePtr = Arc::new(LocalKeystore::in_memory());
+        let key_type = KeyTypeId::try_from("TNGO").unwrap();
+        let scheme = AccountScheme::Sr25519;
+        assert!(Account::load(scheme, key_type, keystore.clone()).unwrap().is_none());
+
+        let first = Account::generate(scheme, "123456", key_type, keystore.clone()).unwrap();
+        let loaded = Account::load(scheme, key_type, keystore.clone()).unwrap().unwrap();
+        assert_eq!(loaded.accounts, first.accounts);
+        assert_eq!(SyncCryptoStore::sr25519_public_keys(&*keystore, key_type).len(), 1);
+
+        // the account file decides between several keys
+        let second = Account::generate(scheme, "123456", key_type, keystore.clone()).unwrap();
+        let loaded = Account::load(scheme, key_type, keystore.clone()).unwrap().unwrap();
+        assert_eq!(loaded.accounts, second.accounts);
+        std::fs::remove_file(ACCOUNT_PATH).unwrap();
+        assert!(Account::load(scheme, key_type, keystore).is_err());
+    }
+
+    //tests that the password is applied to the key generated from a phrase
+    #[test]
+    fn test_password_derives_key() {
+        let (_, phrase, _) = sr25519::Pair::generate_with_phrase(None);
+        for scheme in [AccountScheme::Sr25519, AccountScheme::Ed25519, AccountScheme::Ecdsa] {
+            let first = AccountKey::from_suri(scheme, &seed_from_phrase(scheme, &phrase, "first").unwrap()).unwrap();
+            let second = AccountKey::from_suri(scheme, &seed_from_phrase(scheme, &phrase, "second").unwrap()).unwrap();
+            assert_ne!(first, second);
+        }
+        let expected = sr25519::Pair::from_phrase(&phrase, Some("first")).unwrap().0.public();
+        let key = AccountKey::from_suri(AccountScheme::Sr25519, &seed_from_phrase(AccountScheme::Sr25519, &phrase, "first").unwrap()).unwrap();
+        assert_eq!(key, AccountKey::Sr25519(expected));
+    }
+
+    //tests signing with the keystore for every scheme
+    #[test]
+    fn test_sign_with_every_scheme() {
+        let _guard = ACCOUNT_FILE.lock().unwrap_or_else(|e| e.into_inner());
+        let keystore: SyncCryptoStorePtr = Arc::new(LocalKeystore::in_memory());
+        for (scheme, key_type) in [
+            (AccountScheme::Sr25519, "TNSR"),
+            (AccountScheme::Ed25519, "TNED"),
+            (AccountScheme::Ecdsa, "TNEC"),
+        ] {
+            let key_type = KeyTypeId::try_from(key_type).unwrap();
+            let acc = Account::generate(scheme, "123456", key_type, keystore.clone()).unwrap();
+            assert_eq!(acc.accounts.scheme(), scheme);
+            let loaded = Account::load(scheme, key_type, keystore.clone()).unwrap().unwrap();
+            assert_eq!(loaded.accounts, acc.accounts);
+
+            let signature = acc.sign(b"event", key_type, keystore.clone()).unwrap();
+            assert!(acc.accounts.verify(&signature, b"event"));
+            assert!(!acc.accounts.verify(&signature, b"other event"));
+        }
+
+        // ecdsa signs the keccak-256 hash, so the signer is recoverable as on EVM chains
+        let key_type = KeyTypeId::try_from("TNEC").unwrap();
+        let acc = Account::import(AccountScheme::Ecdsa, "//Alice", key_type, keystore.clone()).unwrap();
+        let signature = acc.sign(b"event", key_type, keystore).unwrap();
+        let signature = sp_core::ecdsa::Signature::try_from(signature.as_slice()).unwrap();
+        assert_eq!(
+            signature.recover_prehashed(&keccak_256(b"event")).map(AccountKey::Ecdsa),
+            Some(acc.accounts)
+        );
+    }
 }
@@ -0,0 +1,171 @@
+use crate::{Account, AccountKey};
+use keystore::commands::KeyTypeId;
+use serde::{Deserialize, Serialize};
+use sp_core::{ed25519, Pair};
+use sp_keystore::SyncCryptoStore;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::sync::Arc;
+
+/// prefixes of the statements, so neither signature can pass for another message of its key
+const ACCOUNT_CONTEXT: &[u8] = b"tango-registration-account";
+const PEER_CONTEXT: &[u8] = b"tango-registration-peer";
+
+/// start of a peer id with an inlined ed25519 key: identity multihash of 36 bytes, then the
+/// protobuf of the libp2p public key with key type 1 and 32 key bytes
+const ED25519_PEER_ID_PREFIX: [u8; 6] = [0x00, 0x24, 0x08, 0x01, 0x12, 0x20];
+
+/// Binds the libp2p peer id of a node to the account which signs its events, each key signs the other.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Registration {
+    pub peer_id: String,
+    pub account: AccountKey,
+    /// signature of the account over the peer id
+    #[serde(with = "sp_core::bytes")]
+    pub account_signature: Vec<u8>,
+    /// signature of the libp2p identity key over the account
+    #[serde(with = "sp_core::bytes")]
+    pub peer_signature: Vec<u8>,
+}
+
+impl Registration {
+    /// Registers `peer_id` for the account, `sign_with_peer_key` signs with the libp2p identity
+    /// key of the peer.
+    pub fn new(
+        peer_id: String,
+        account: &Account,
+        key_type: KeyTypeId,
+        keystore: Arc<dyn SyncCryptoStore>,
+        sign_with_peer_key: impl FnOnce(&[u8]) -> Result<Vec<u8>, Box<dyn Error>>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let account_signature =
+            account.sign(&Self::account_statement(&peer_id), key_type, keystore)?;
+        let peer_signature = sign_with_peer_key(&Self::peer_statement(&account.accounts))?;
+        let registration = Registration {
+            peer_id,
+            account: account.accounts,
+            account_signature,
+            peer_signature,
+        };
+        registration.verify()?;
+        Ok(registration)
+    }
+
+    /// Message signed by the account.
+    pub fn account_statement(peer_id: &str) -> Vec<u8> {
+        let mut statement = ACCOUNT_CONTEXT.to_vec();
+        statement.extend_from_slice(peer_id.as_bytes());
+        statement
+    }
+
+    /// Message signed by the libp2p identity key.
+    pub fn peer_statement(account: &AccountKey) -> Vec<u8> {
+        let mut statement = PEER_CONTEXT.to_vec();
+        statement.extend_from_slice(account.scheme().to_string().as_bytes());
+        statement.extend_from_slice(&account.to_raw_vec());
+        statement
+    }
+
+    /// Checks both signatures. The key of the peer is the ed25519 key inlined in its peer id.
+    pub fn verify(&self) -> Result<(), String> {
+        if !self.account.verify(
+            &self.account_signature,
+            &Self::account_statement(&self.peer_id),
+        ) {
+            return Err(format!(
+                "account {} did not sign peer id {}",
+                self.account, self.peer_id
+            ));
+        }
+
+        let peer_key = peer_public_key(&self.peer_id)?;
+        let signed = ed25519::Signature::try_from(self.peer_signature.as_slice()).map_or(
+            false,
+            |signature| {
+                ed25519::Pair::verify(&signature, Self::peer_statement(&self.account), &peer_key)
+            },
+        );
+        if !signed {
+            return Err(format!(
+                "peer {} did not sign account {}",
+                self.peer_id, self.account
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Peer id of an ed25519 libp2p identity key, the same as libp2p derives for it.
+pub fn ed25519_peer_id(key: &ed25519::Public) -> String {
+    let mut bytes = ED25519_PEER_ID_PREFIX.to_vec();
+    bytes.extend_from_slice(key.as_ref());
+    bs58::encode(bytes).into_string()
+}
+
+/// ed25519 key of a peer id, keys of other types are hashed into their peer id
+fn peer_public_key(peer_id: &str) -> Result<ed25519::Public, String> {
+    let bytes = bs58::decode(peer_id)
+        .into_vec()
+        .map_err(|e| format!("invalid peer id {}: {}", peer_id, e))?;
+    bytes
+        .strip_prefix(&ED25519_PEER_ID_PREFIX[..])
+        .and_then(|key| ed25519::Public::try_from(key).ok())
+        .ok_or(format!("peer id {} has no inlined ed25519 key", peer_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AccountScheme;
+    use sc_keystore::LocalKeystore;
+    use sp_keystore::SyncCryptoStorePtr;
+
+    //tests that the account and the peer sign each other and a swapped peer is caught
+    #[test]
+    fn test_register_peer() {
+        let keystore: SyncCryptoStorePtr = Arc::new(LocalKeystore::in_memory());
+        let key_type = KeyTypeId::try_from("TNGO").unwrap();
+        let account = Account {
+            accounts: AccountKey::from_suri(AccountScheme::Sr25519, "//Alice").unwrap(),
+        };
+        SyncCryptoStore::insert_unknown(
+            &*keystore,
+            key_type,
+            "//Alice",
+            &account.accounts.to_raw_vec(),
+        )
+        .unwrap();
+        let peer = ed25519::Pair::from_string("//peer", None).unwrap();
+        let other = ed25519::Pair::from_string("//other", None).unwrap();
+
+        let registration = Registration::new(
+            ed25519_peer_id(&peer.public()),
+            &account,
+            key_type,
+            keystore.clone(),
+            |statement| Ok(peer.sign(statement).0.to_vec()),
+        )
+        .unwrap();
+        assert_eq!(registration.account, account.accounts);
+        assert!(registration.verify().is_ok());
+        let json = serde_json::to_string(&registration).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Registration>(&json).unwrap(),
+            registration
+        );
+
+        let mut swapped = registration.clone();
+        swapped.peer_id = ed25519_peer_id(&other.public());
+        assert!(swapped.verify().is_err());
+
+        // the peer has to sign, an account alone can't claim a peer id
+        assert!(Registration::new(
+            ed25519_peer_id(&other.public()),
+            &account,
+            key_type,
+            keystore,
+            |statement| Ok(peer.sign(statement).0.to_vec()),
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use crate::{generate_key, Account, AccountKey, ACCOUNT_PATH};
+use keystore::commands::KeyTypeId;
+use serde::{Deserialize, Serialize};
+use sp_keystore::SyncCryptoStore;
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// validity windows of the account keys, stored in the keystore directory next to the keys
+pub const KEY_VALIDITY_FILE: &str = "account_keys.json";
+
+/// prefix of the handover statement, so no other message signed by the account can pass as one
+const HANDOVER_CONTEXT: &[u8] = b"tango-account-handover";
+
+/// Handover of the account from the old key to a new one of the same scheme, signed by the old key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRotation {
+    pub key_type: String,
+    pub old_key: AccountKey,
+    pub new_key: AccountKey,
+    /// unix seconds, the old key signs until then and the new key from then on
+    pub rotated_at: u64,
+    /// signature of the old key over the handover statement
+    #[serde(with = "sp_core::bytes")]
+    pub signature: Vec<u8>,
+}
+
+/// Time in which an account key signs events, open ended for the current key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyValidity {
+    pub key: AccountKey,
+    pub valid_from: Option<u64>,
+    pub valid_until: Option<u64>,
+}
+
+impl Account {
+    //rotate the account to a new key in the keystore, the handover is signed by the current key
+    //the old key stays in the keystore and the current account is unchanged until the rotation is applied
+    pub fn rotate(
+        &self,
+        password: &str,
+        key_type: KeyTypeId,
+        keystore: Arc<dyn SyncCryptoStore>,
+    ) -> Result<KeyRotation, Box<dyn Error>> {
+        let new_key = generate_key(self.accounts.scheme(), password, key_type, keystore.clone())?;
+        let key_type_name = String::from_utf8_lossy(&key_type.0).to_string();
+        let rotated_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let statement =
+            KeyRotation::statement(&key_type_name, &self.accounts, &new_key, rotated_at);
+
+        let signature = self.sign(&statement, key_type, keystore)?;
+
+        Ok(KeyRotation {
+            key_type: key_type_name,
+            old_key: self.accounts,
+            new_key,
+            rotated_at,
+            signature,
+        })
+    }
+}
+
+impl KeyRotation {
+    /// Message signed by the old key.
+    pub fn statement(
+        key_type: &str,
+        old_key: &AccountKey,
+        new_key: &AccountKey,
+        rotated_at: u64,
+    ) -> Vec<u8> {
+        let mut statement = HANDOVER_CONTEXT.to_vec();
+        statement.extend_from_slice(key_type.as_bytes());
+        for key in [old_key, new_key] {
+            statement.extend_from_slice(key.scheme().to_string().as_bytes());
+            statement.extend_from_slice(&key.to_raw_vec());
+        }
+        statement.extend_from_slice(&rotated_at.to_be_bytes());
+        statement
+    }
+
+    /// Checks that the old key handed the account over to the new key.
+    pub fn verify(&self) -> bool {
+        let statement = Self::statement(
+            &self.key_type,
+            &self.old_key,
+            &self.new_key,
+            self.rotated_at,
+        );
+        self.old_key.scheme() == self.new_key.scheme()
+            && self.old_key.verify(&self.signature, &statement)
+    }
+
+    /// Records the validity windows of both keys in `keystore_path` and makes the new key
+    /// the current account.
+    pub fn apply(&self, keystore_path: &Path) -> Result<Account, Box<dyn Error>> {
+        let path = keystore_path.join(KEY_VALIDITY_FILE);
+        let mut windows = read_key_validity(&path)?;
+        record_rotation(&mut windows, self);
+        write_key_validity(&path, &windows)?;
+
+        let acc = Account {
+            accounts: self.new_key,
+        };
+        acc.gen_key_file(ACCOUNT_PATH)?;
+        Ok(acc)
+    }
+}
+
+/// Closes the window of the old key and opens one for the new key.
+pub fn record_rotation(windows: &mut Vec<KeyValidity>, rotation: &KeyRotation) {
+    match windows
+        .iter_mut()
+        .find(|window| window.key == rotation.old_key && window.valid_until.is_none())
+    {
+        Some(window) => window.valid_until = Some(rotation.rotated_at),
+        // the key signed since before rotations were recorded
+        None => windows.push(KeyValidity {
+            key: rotation.old_key,
+            valid_from: None,
+            valid_until: Some(rotation.rotated_at),
+        }),
+    }
+    windows.push(KeyValidity {
+        key: rotation.new_key,
+        valid_from: Some(rotation.rotated_at),
+        valid_until: None,
+    });
+}
+
+/// Validity windows stored at `path`, none before the first rotation.
+pub fn read_key_validity(path: &Path) -> Result<Vec<KeyValidity>, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+pub fn write_key_validity(path: &Path, windows: &[KeyValidity]) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_vec_pretty(windows)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AccountScheme;
+    use sc_keystore::LocalKeystore;
+    use sp_keystore::SyncCryptoStorePtr;
+    use std::convert::TryFrom;
+
+    //tests that the old key signs the handover and both keys stay in the keystore
+    #[test]
+    fn test_rotate_account() {
+        let keystore: SyncCryptoStorePtr = Arc::new(LocalKeystore::in_memory());
+        let key_type = KeyTypeId::try_from("TNGO").unwrap();
+        let old_key =
+            generate_key(AccountScheme::Sr25519, "123456", key_type, keystore.clone()).unwrap();
+        let acc = Account { accounts: old_key };
+
+        let rotation = acc.rotate("123456", key_type, keystore.clone()).unwrap();
+        assert_eq!(rotation.old_key, old_key);
+        assert_ne!(rotation.new_key, old_key);
+        assert!(rotation.verify());
+        let keys = Account::keystore_keys(AccountScheme::Sr25519, key_type, keystore.clone());
+        assert!(keys.contains(&old_key) && keys.contains(&rotation.new_key));
+
+        let mut forged = rotation.clone();
+        forged.rotated_at += 1;
+        assert!(!forged.verify());
+
+        let mut windows = vec![];
+        record_rotation(&mut windows, &rotation);
+        let next = Account {
+            accounts: rotation.new_key,
+        }
+        .rotate("123456", key_type, keystore)
+        .unwrap();
+        record_rotation(&mut windows, &next);
+        assert_eq!(
+            serde_json::from_str::<KeyRotation>(&serde_json::to_string(&next).unwrap()).unwrap(),
+            next
+        );
+        assert_eq!(
+            windows,
+            vec![
+                KeyValidity {
+                    key: old_key,
+                    valid_from: None,
+                    valid_until: Some(rotation.rotated_at)
+                },
+                KeyValidity {
+                    key: rotation.new_key,
+                    valid_from: Some(rotation.rotated_at),
+                    valid_until: Some(next.rotated_at),
+                },
+                KeyValidity {
+                    key: next.new_key,
+                    valid_from: Some(next.rotated_at),
+                    valid_until: None
+                },
+            ]
+        );
+
+        // windows are kept in the keystore directory, wherever the node runs
+        let keystore_path =
+            std::env::temp_dir().join(format!("tango-keystore-{}", std::process::id()));
+        let path = keystore_path.join(KEY_VALIDITY_FILE);
+        assert!(read_key_validity(&path).unwrap().is_empty());
+        write_key_validity(&path, &windows).unwrap();
+        assert_eq!(read_key_validity(&path).unwrap(), windows);
+        let _ = std::fs::remove_dir_all(&keystore_path);
+    }
+}
@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+use sp_core::crypto::{ByteArray, CryptoTypePublicPair, Ss58Codec};
+use sp_core::hashing::keccak_256;
+use sp_core::{ecdsa, ed25519, sr25519, Pair, Public as _};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// Signature scheme of the account key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountScheme {
+    Sr25519,
+    Ed25519,
+    /// secp256k1, signs the keccak-256 hash of a message so EVM contracts can recover the signer
+    Ecdsa,
+}
+
+impl Default for AccountScheme {
+    fn default() -> Self {
+        AccountScheme::Sr25519
+    }
+}
+
+impl FromStr for AccountScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sr25519" => Ok(AccountScheme::Sr25519),
+            "ed25519" => Ok(AccountScheme::Ed25519),
+            "ecdsa" => Ok(AccountScheme::Ecdsa),
+            _ => Err(format!(
+                "Unknown account scheme {}, expected sr25519, ed25519 or ecdsa",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for AccountScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AccountScheme::Sr25519 => write!(f, "sr25519"),
+            AccountScheme::Ed25519 => write!(f, "ed25519"),
+            AccountScheme::Ecdsa => write!(f, "ecdsa"),
+        }
+    }
+}
+
+/// Public key of an account, displayed as its SS58 address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountKey {
+    Sr25519(sr25519::Public),
+    Ed25519(ed25519::Public),
+    Ecdsa(ecdsa::Public),
+}
+
+impl AccountKey {
+    pub fn scheme(&self) -> AccountScheme {
+        match self {
+            AccountKey::Sr25519(_) => AccountScheme::Sr25519,
+            AccountKey::Ed25519(_) => AccountScheme::Ed25519,
+            AccountKey::Ecdsa(_) => AccountScheme::Ecdsa,
+        }
+    }
+
+    /// Key of `scheme` out of its raw bytes, 33 bytes compressed for ecdsa and 32 otherwise.
+    pub fn from_raw(scheme: AccountScheme, bytes: &[u8]) -> Result<Self, String> {
+        let key = match scheme {
+            AccountScheme::Sr25519 => sr25519::Public::try_from(bytes).map(AccountKey::Sr25519),
+            AccountScheme::Ed25519 => ed25519::Public::try_from(bytes).map(AccountKey::Ed25519),
+            AccountScheme::Ecdsa => ecdsa::Public::try_from(bytes).map(AccountKey::Ecdsa),
+        };
+        key.map_err(|_| format!("{} bytes are no {} public key", bytes.len(), scheme))
+    }
+
+    /// Key of `scheme` out of its SS58 address.
+    pub fn from_ss58check(scheme: AccountScheme, address: &str) -> Result<Self, String> {
+        let key = match scheme {
+            AccountScheme::Sr25519 => {
+                sr25519::Public::from_ss58check(address).map(AccountKey::Sr25519)
+            }
+            AccountScheme::Ed25519 => {
+                ed25519::Public::from_ss58check(address).map(AccountKey::Ed25519)
+            }
+            AccountScheme::Ecdsa => ecdsa::Public::from_ss58check(address).map(AccountKey::Ecdsa),
+        };
+        key.map_err(|e| format!("invalid {} address {}: {:?}", scheme, address, e))
+    }
+
+    /// Key of `scheme` behind a secret URI, i.e. a mnemonic or a hex seed, optionally with a derivation path.
+    pub fn from_suri(scheme: AccountScheme, suri: &str) -> Result<Self, String> {
+        let key = match scheme {
+            AccountScheme::Sr25519 => sr25519::Pair::from_string(suri, None)
+                .map(|pair| AccountKey::Sr25519(pair.public())),
+            AccountScheme::Ed25519 => ed25519::Pair::from_string(suri, None)
+                .map(|pair| AccountKey::Ed25519(pair.public())),
+            AccountScheme::Ecdsa => {
+                ecdsa::Pair::from_string(suri, None).map(|pair| AccountKey::Ecdsa(pair.public()))
+            }
+        };
+        key.map_err(|e| format!("Invalid secret URI: {:?}", e))
+    }
+
+    pub fn to_raw_vec(&self) -> Vec<u8> {
+        match self {
+            AccountKey::Sr25519(key) => key.to_raw_vec(),
+            AccountKey::Ed25519(key) => key.to_raw_vec(),
+            AccountKey::Ecdsa(key) => key.to_raw_vec(),
+        }
+    }
+
+    /// Key as the keystore addresses it.
+    pub fn to_public_crypto_pair(&self) -> CryptoTypePublicPair {
+        match self {
+            AccountKey::Sr25519(key) => key.to_public_crypto_pair(),
+            AccountKey::Ed25519(key) => key.to_public_crypto_pair(),
+            AccountKey::Ecdsa(key) => key.to_public_crypto_pair(),
+        }
+    }
+
+    /// Checks a signature made by `Account::sign`.
+    pub fn verify(&self, signature: &[u8], msg: &[u8]) -> bool {
+        match self {
+            AccountKey::Sr25519(key) => sr25519::Signature::try_from(signature)
+                .map_or(false, |signature| {
+                    sr25519::Pair::verify(&signature, msg, key)
+                }),
+            AccountKey::Ed25519(key) => ed25519::Signature::try_from(signature)
+                .map_or(false, |signature| {
+                    ed25519::Pair::verify(&signature, msg, key)
+                }),
+            AccountKey::Ecdsa(key) => ecdsa::Signature::try_from(signature)
+                .map_or(false, |signature| {
+                    ecdsa::Pair::verify_prehashed(&signature, &keccak_256(msg), key)
+                }),
+        }
+    }
+}
+
+impl fmt::Display for AccountKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AccountKey::Sr25519(key) => write!(f, "{}", key.to_ss58check()),
+            AccountKey::Ed25519(key) => write!(f, "{}", key.to_ss58check()),
+            AccountKey::Ecdsa(key) => write!(f, "{}", key.to_ss58check()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //tests that keys of every scheme survive their raw and ss58 encodings
+    #[test]
+    fn test_account_key_encodings() {
+        for scheme in [
+            AccountScheme::Sr25519,
+            AccountScheme::Ed25519,
+            AccountScheme::Ecdsa,
+        ] {
+            assert_eq!(scheme.to_string().parse::<AccountScheme>(), Ok(scheme));
+            let key = AccountKey::from_suri(scheme, "//Alice").unwrap();
+            assert_eq!(key.scheme(), scheme);
+            assert_eq!(AccountKey::from_raw(scheme, &key.to_raw_vec()), Ok(key));
+            assert_eq!(
+                AccountKey::from_ss58check(scheme, &key.to_string()),
+                Ok(key)
+            );
+        }
+        assert!("rsa".parse::<AccountScheme>().is_err());
+        assert!(AccountKey::from_raw(AccountScheme::Ecdsa, &[0; 32]).is_err());
+    }
+}
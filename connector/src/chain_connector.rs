@@ -0,0 +1,298 @@
+use async_trait::async_trait;
+use database::Repo;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::mpsc::error::{SendError, TrySendError};
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+
+use crate::ethereum::SwapConnector;
+use crate::polkadot::{EventFilter, PolkadotConnector};
+use crate::supervisor::ContractsConnector;
+
+/// State of the task running a connector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectorHealth {
+    /// not started or stopped
+    Stopped,
+    Running,
+    /// task ended on its own
+    Finished,
+}
+
+impl fmt::Display for ConnectorHealth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConnectorHealth::Stopped => write!(f, "stopped"),
+            ConnectorHealth::Running => write!(f, "running"),
+            ConnectorHealth::Finished => write!(f, "finished"),
+        }
+    }
+}
+
+/// Health of a connector from the handle of its task.
+pub fn task_health(handle: &Option<JoinHandle<()>>) -> ConnectorHealth {
+    match handle {
+        None => ConnectorHealth::Stopped,
+        Some(handle) if handle.is_finished() => ConnectorHealth::Finished,
+        Some(_) => ConnectorHealth::Running,
+    }
+}
+
+/// Events waiting in the TSS channel.
+pub fn queue_depth(sender: &mpsc::Sender<String>) -> usize {
+    sender.max_capacity() - sender.capacity()
+}
+
+/// Sends an event to TSS. TSS stops taking events while its msg pool is full; the connector
+/// then waits here instead of reading further events, so its cursor stays at the last sent one.
+pub async fn send_event(
+    sender: &mpsc::Sender<String>,
+    event: String,
+) -> Result<(), SendError<String>> {
+    match sender.try_send(event) {
+        Ok(()) => Ok(()),
+        Err(TrySendError::Full(event)) => {
+            log::warn!(
+                "TSS event queue full ({} queued), connector waiting",
+                queue_depth(sender)
+            );
+            sender.send(event).await
+        }
+        Err(TrySendError::Closed(event)) => Err(SendError(event)),
+    }
+}
+
+/// Source of events sent to TSS for signing.
+///
+/// `start` spawns the connector on the runtime and returns, `stop` aborts it.
+/// Several connectors can run in one node, all sending into the same TSS channel.
+#[async_trait]
+pub trait ChainConnector: Send + Sync {
+    /// name the connector is registered with
+    fn name(&self) -> &'static str;
+
+    /// `event_type` of the events sent to TSS
+    fn event_type(&self) -> &'static str;
+
+    fn start(&mut self) -> eyre::Result<()>;
+
+    fn stop(&mut self);
+
+    fn health(&self) -> ConnectorHealth;
+
+    /// position the connector resumes from after a restart, if it keeps one
+    async fn cursor(&self) -> Option<String>;
+}
+
+/// Shared resources handed to connectors when they are created.
+#[derive(Clone)]
+pub struct ConnectorContext {
+    pub connection: Arc<dyn Repo>,
+    pub sender: mpsc::Sender<String>,
+    /// blocks built on top of an ethereum log before it is sent to TSS
+    pub eth_confirmations: u64,
+    /// substrate chains followed by the polkadot connector
+    pub substrate_endpoints: Vec<String>,
+    /// runtime events sent by the polkadot connector
+    pub substrate_events: Vec<EventFilter>,
+    /// notified by the server when contracts are added or removed
+    pub contracts_changed: Arc<Notify>,
+}
+
+pub type ConnectorFactory = fn(&ConnectorContext) -> Box<dyn ChainConnector>;
+
+/// Connectors known to the node by name and the ones currently running.
+pub struct ConnectorRegistry {
+    factories: BTreeMap<&'static str, ConnectorFactory>,
+    running: Vec<Box<dyn ChainConnector>>,
+}
+
+impl ConnectorRegistry {
+    pub fn new() -> Self {
+        ConnectorRegistry {
+            factories: BTreeMap::new(),
+            running: vec![],
+        }
+    }
+
+    /// registry with all connectors of this crate
+    pub fn with_defaults() -> Self {
+        let mut registry = ConnectorRegistry::new();
+        registry.register(ContractsConnector::NAME, |ctx| {
+            Box::new(ContractsConnector::new(ctx))
+        });
+        registry.register(SwapConnector::NAME, |ctx| Box::new(SwapConnector::new(ctx)));
+        registry.register(PolkadotConnector::NAME, |ctx| {
+            Box::new(PolkadotConnector::new(ctx))
+        });
+        registry
+    }
+
+    pub fn register(&mut self, name: &'static str, factory: ConnectorFactory) {
+        self.factories.insert(name, factory);
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.factories.keys().copied().collect()
+    }
+
+    /// creates and starts the connector registered as `name`
+    pub fn start(&mut self, name: &str, ctx: &ConnectorContext) -> eyre::Result<()> {
+        if self.running.iter().any(|connector| connector.name() == name) {
+            return Err(eyre::eyre!("Connector {} already running", name));
+        }
+        let factory = self.factories.get(name).ok_or_else(|| {
+            eyre::eyre!(
+                "Unknown connector {}, available: {}",
+                name,
+                self.names().join(", ")
+            )
+        })?;
+
+        let mut connector = factory(ctx);
+        connector.start()?;
+        log::info!(
+            "Connector {} started, emitting {} events",
+            connector.name(),
+            connector.event_type()
+        );
+        self.running.push(connector);
+        Ok(())
+    }
+
+    pub fn stop_all(&mut self) {
+        for connector in self.running.iter_mut() {
+            connector.stop();
+            log::info!("Connector {} stopped", connector.name());
+        }
+        self.running.clear();
+    }
+
+    pub fn running(&self) -> &[Box<dyn ChainConnector>] {
+        &self.running
+    }
+
+    /// name and health of every running connector
+    pub fn health(&self) -> Vec<(&'static str, ConnectorHealth)> {
+        self.running
+            .iter()
+            .map(|connector| (connector.name(), connector.health()))
+            .collect()
+    }
+}
+
+impl Default for ConnectorRegistry {
+    fn default() -> Self {
+        ConnectorRegistry::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::MemoryRepo;
+
+    /// connector whose task runs until it is stopped
+    struct IdleConnector {
+        handle: Option<JoinHandle<()>>,
+    }
+
+    #[async_trait]
+    impl ChainConnector for IdleConnector {
+        fn name(&self) -> &'static str {
+            "idle"
+        }
+
+        fn event_type(&self) -> &'static str {
+            "idle"
+        }
+
+        fn start(&mut self) -> eyre::Result<()> {
+            self.handle = Some(tokio::spawn(futures::future::pending()));
+            Ok(())
+        }
+
+        fn stop(&mut self) {
+            if let Some(handle) = self.handle.take() {
+                handle.abort();
+            }
+        }
+
+        fn health(&self) -> ConnectorHealth {
+            task_health(&self.handle)
+        }
+
+        async fn cursor(&self) -> Option<String> {
+            None
+        }
+    }
+
+    fn context() -> ConnectorContext {
+        let (sender, _) = mpsc::channel(10);
+        ConnectorContext {
+            connection: Arc::new(MemoryRepo::new()),
+            sender,
+            eth_confirmations: 0,
+            substrate_endpoints: vec![],
+            substrate_events: vec![],
+            contracts_changed: Arc::new(Notify::new()),
+        }
+    }
+
+    //tests that a connector waits in send_event until tss reads from a full queue
+    #[tokio::test]
+    async fn send_event_waits_while_queue_full() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        send_event(&sender, "first".to_string()).await.unwrap();
+        assert_eq!(queue_depth(&sender), 1);
+
+        let waiting = tokio::spawn({
+            let sender = sender.clone();
+            async move { send_event(&sender, "second".to_string()).await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(!waiting.is_finished());
+
+        assert_eq!(receiver.recv().await.unwrap(), "first");
+        waiting.await.unwrap().unwrap();
+        assert_eq!(receiver.recv().await.unwrap(), "second");
+
+        drop(receiver);
+        assert!(send_event(&sender, "closed".to_string()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn starts_registered_connector_once() {
+        let ctx = context();
+        let mut registry = ConnectorRegistry::new();
+        registry.register("idle", |_| Box::new(IdleConnector { handle: None }));
+
+        assert!(registry.start("bitcoin", &ctx).is_err());
+        registry.start("idle", &ctx).unwrap();
+        assert!(registry.start("idle", &ctx).is_err());
+        assert_eq!(registry.health(), vec![("idle", ConnectorHealth::Running)]);
+
+        registry.stop_all();
+        assert!(registry.running().is_empty());
+        assert!(registry.health().is_empty());
+    }
+
+    //ethereum only follows contract logs, swap prices are a connector of their own
+    #[tokio::test]
+    async fn starts_ethereum_without_swap_prices() {
+        let ctx = context();
+        let mut registry = ConnectorRegistry::with_defaults();
+        assert_eq!(registry.names(), vec!["ethereum", "polkadot", "swap"]);
+
+        registry.start("ethereum", &ctx).unwrap();
+        assert_eq!(
+            registry.health(),
+            vec![("ethereum", ConnectorHealth::Running)]
+        );
+        // polkadot refuses to start without endpoints
+        assert!(registry.start("polkadot", &ctx).is_err());
+        registry.stop_all();
+    }
+}
@@ -1,341 +1,692 @@
+use crate::chain_connector::{
+    send_event, task_health, ChainConnector, ConnectorContext, ConnectorHealth,
+};
+use crate::price::{
+    aggregate_prices, AggregatedPrice, PriceError, PriceHistory, PriceSource, MAX_DEVIATION_BPS,
+};
+use async_trait::async_trait;
 use database::models::TokenSwap;
-use database::MongoRepo;
+use database::Repo;
 use serde_json::json;
-use std::error::Error;
-use std::fs::File;
-use std::io::Read;
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
-use tokio::sync::mpsc;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
 use web3::contract::{Contract, Options};
 use web3::transports::Http;
 use web3::types::{Address, U256};
 
+/// Interval between two rounds of swap price queries.
+const SWAP_INTERVAL: Duration = Duration::from_millis(10000);
+
+/// Decimals of the fixed-point `swap_price` sent to tss.
+pub const PRICE_DECIMALS: u32 = 18;
+
+/// Tokens with more decimals are rejected, their amounts would overflow the price.
+const MAX_TOKEN_DECIMALS: u32 = 36;
+
+/// ABI json by endpoint (url or file) it was loaded from.
+pub type AbiCache = Arc<Mutex<HashMap<String, String>>>;
+
+/// web3 clients by the chain endpoint of the pairs they query.
+pub type ClientCache = Arc<Mutex<HashMap<String, web3::Web3<Http>>>>;
+
+/// Aggregated prices of the last rounds by pair, see `pair_key`.
+pub type PriceHistories = Arc<Mutex<HashMap<String, PriceHistory>>>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SwapError {
+    #[error("invalid address {0}")]
+    InvalidAddress(String),
+    #[error("invalid chain endpoint {endpoint}: {reason}")]
+    Endpoint { endpoint: String, reason: String },
+    #[error("unable to load abi from {endpoint}: {reason}")]
+    Abi { endpoint: String, reason: String },
+    #[error("contract call failed: {0}")]
+    Contract(#[from] web3::contract::Error),
+    #[error("unexpected result: {0}")]
+    UnexpectedResult(String),
+    #[error("price aggregation failed: {0}")]
+    Price(#[from] PriceError),
+}
+
 #[derive(Clone)]
 pub struct SwapToken {
-    pub web_socket: web3::Web3<Http>,
-    pub connection: MongoRepo,
+    pub clients: ClientCache,
+    pub connection: Arc<dyn Repo>,
     pub sender: mpsc::Sender<String>,
+    pub abi_cache: AbiCache,
+    pub price_history: PriceHistories,
 }
 
 impl SwapToken {
     /// create a new event instance
-    pub fn new(
-        web_socket: web3::Web3<Http>,
-        connection: MongoRepo,
-        sender: mpsc::Sender<String>,
-    ) -> Self {
+    pub fn new(connection: Arc<dyn Repo>, sender: mpsc::Sender<String>) -> Self {
         SwapToken {
-            web_socket: web_socket,
+            clients: Arc::new(Mutex::new(HashMap::new())),
             connection: connection,
             sender: sender,
+            abi_cache: Arc::new(Mutex::new(HashMap::new())),
+            price_history: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
     pub async fn swap_thread_handler(&self, index: i32) {
-        let swap_data = MongoRepo::get_swap_data(&self.connection.clone())
-            .await
-            .unwrap();
+        let swap_data = match self.connection.get_swap_data().await {
+            Ok(swap_data) => swap_data,
+            Err(e) => {
+                log::error!("Unable to load swap pairs: {}", e);
+                return;
+            }
+        };
 
+        // every exchange configured for a pair is one price source
+        let mut pairs: BTreeMap<String, Vec<TokenSwap>> = BTreeMap::new();
         for swap in swap_data {
+            pairs.entry(pair_key(&swap)).or_default().push(swap);
+        }
+
+        for (pair, swaps) in pairs {
             let arguments = self.clone();
             tokio::spawn(async move {
-                let _ = SwapToken::swap_handler(&arguments, swap, index).await;
+                if let Err(e) = SwapToken::swap_handler(&arguments, swaps, index).await {
+                    log::error!("Swap price of {} failed: {}", pair, e);
+                }
             });
         }
     }
 
-    pub async fn swap_handler_old(&self, swap: TokenSwap) -> Result<(), Box<dyn Error>> {
-        let websocket = web3::Web3::new(
-            Http::new("https://goerli.rpc.rivet.cloud/a507940678ae4740a727967aa8566e08").unwrap(),
-        );
-        let unit_from: u64 = i64::pow(
-            10,
-            SwapToken::decimals(
-                &websocket,
-                swap.token_endpoint.as_str(),
-                swap.token_address.as_str(),
-            )
-            .await
-            .map_err(|e| Into::<Box<dyn Error>>::into(e))? as u32,
-        ) as u64;
-
-        let unit_to: u64 = i64::pow(
-            10,
-            SwapToken::decimals(
-                &websocket,
-                swap.token_endpoint.as_str(),
-                swap.swap_token_address.as_str(),
-            )
-            .await
-            .map_err(|e| Into::<Box<dyn Error>>::into(e))? as u32,
-        ) as u64;
-        // log::info!("atomic values ---> {:?}--{:?}", unit_to, unit_from);
-
-        let from_val: f64 = 1.0;
-        let query_method = "getAmountsOut";
-
-        let query_parameter = (
-            U256::from((from_val * unit_from as f64) as u64),
-            [
-                Address::from_str(swap.token_address.as_str()).unwrap(),
-                Address::from_str(swap.swap_token_address.as_str()).unwrap(),
-            ],
-            12,
-        );
-
-        // log::info!("query_parameter ---> {:?}", query_parameter);
-        let swap_result: Vec<i32> = SwapToken::swap_price(
-            &self.web_socket,
-            swap.exchange_endpoint.as_str(),
-            swap.exchange_address.as_str(),
-            query_method,
-            query_parameter,
-        )
-        .await
-        .map_err(|e| Into::<Box<dyn std::error::Error>>::into(e))?;
-        log::info!("swap ----> {:?}", swap_result);
-        if swap_result.len() != 2 {
-            return Err(From::from("Received Unexpected swap results."));
-        }
-
-        // let swap_value = swap_result[1].as_u128() as f64 / unit_to as f64;
-        let swap_value = swap_result[1] / unit_to as i32;
-        log::info!(
-            "swap {} token to {} token:  {:?}",
-            swap.token,
-            swap.swap_token,
-            swap_value
-        );
-
-        Ok(())
-    }
-
+    /// Queries the price of a pair on all of its exchanges and sends the median
+    /// of the sources agreeing with each other to tss, together with every source.
     pub async fn swap_handler(
         &self,
-        swap: TokenSwap,
+        swaps: Vec<TokenSwap>,
         index: i32,
-    ) -> Result<Vec<i32>, Box<dyn Error>> {
-        // let websocket = web3::Web3::new(
-        //     Http::new("https://a507940678ae4740a727967aa8566e08.eth.rpc.rivet.cloud").unwrap(),
-        // );
-
-        let unit_from: u64 = i64::pow(
-            10,
-            SwapToken::decimals(
-                &self.web_socket,
-                swap.token_endpoint.as_str(),
-                swap.token_address.as_str(),
-            )
-            .await
-            .map_err(|e| Into::<Box<dyn Error>>::into(e))? as u32,
-        ) as u64;
-        log::info!("query_parameter ---> {:?}", unit_from);
-        let unit_to: u64 = i64::pow(
-            10,
-            SwapToken::decimals(
-                &self.web_socket,
-                swap.token_endpoint.as_str(),
-                swap.swap_token_address.as_str(),
-            )
-            .await
-            .map_err(|e| Into::<Box<dyn Error>>::into(e))? as u32,
-        ) as u64;
-        log::info!("query_parameter ---> {:?}", unit_to);
-        let from_val: f64 = 1.0;
-        let query_method = "getAmountsOut";
-
-        let query_parameter = (
-            U256::from((from_val * unit_from as f64) as u64),
-            [
-                Address::from_str(swap.token_address.as_str()).unwrap(),
-                Address::from_str(swap.swap_token_address.as_str()).unwrap(),
-            ],
-            12,
-        );
-        // log::info!("query_parameter ---> {:?}", query_parameter);
-        let swap_result: Vec<i32> = SwapToken::swap_price(
-            &self.web_socket,
-            swap.exchange_endpoint.as_str(),
-            swap.exchange_address.as_str(),
-            query_method,
-            query_parameter,
-        )
-        .await
-        .map_err(|e| Into::<Box<dyn std::error::Error>>::into(e))?;
-        log::info!("swap ----> {:?}", swap_result);
-        if swap_result.len() != 2 {
-            return Err(From::from("Received Unexpected swap results."));
+    ) -> Result<AggregatedPrice, SwapError> {
+        let first = swaps
+            .first()
+            .cloned()
+            .ok_or(SwapError::Price(PriceError::NoSources))?;
+
+        let prices =
+            futures::future::join_all(swaps.iter().map(|swap| self.query_price(swap))).await;
+        let mut sources = vec![];
+        for (swap, price) in swaps.iter().zip(prices) {
+            match price {
+                Ok(swap_price) => sources.push(PriceSource {
+                    exchange: swap.exchange.clone(),
+                    exchange_address: swap.exchange_address.clone(),
+                    swap_price,
+                    accepted: false,
+                }),
+                Err(e) if swaps.len() == 1 => return Err(e),
+                Err(e) => log::warn!("Swap price on {} failed: {}", swap.exchange, e),
+            }
         }
 
-        // let swap_value = swap_result[1].as_u128() as f64 / unit_to as f64;
-        let swap_value = swap_result[0];
+        let aggregated = aggregate_prices(sources, MAX_DEVIATION_BPS)?;
+        let twap = {
+            let mut histories = self.price_history.lock().await;
+            let history = histories.entry(pair_key(&first)).or_default();
+            let now = Instant::now();
+            history.record(now, aggregated.price);
+            history.twap(now).unwrap_or(aggregated.price)
+        };
         log::info!(
-            "swap {} token to {} token:  {:?}",
-            swap.token,
-            swap.swap_token,
-            swap_value
+            "swap {} token to {} token: {} ({} decimals) from {} sources",
+            first.token,
+            first.swap_token,
+            aggregated.price,
+            PRICE_DECIMALS,
+            aggregated.sources.len()
         );
 
         let swapevent_json = json!({
-            "chain": swap.chain.clone(),
-            "exchange": swap.exchange.clone(),
-            "swap_from": swap.token_address.clone(),
-            "swap_to": swap.swap_token_address.clone(),
-            "swap_price": swap_value.clone().to_string(),
+            "chain": first.chain.clone(),
+            "swap_from": first.token_address.clone(),
+            "swap_to": first.swap_token_address.clone(),
+            "swap_price": aggregated.price.to_string(),
+            "twap_price": twap.to_string(),
+            "price_decimals": PRICE_DECIMALS,
+            "sources": aggregated.sources,
             "swap_index": index,
             "event_type": "swap"
         });
-        let serialized_swapevent = serde_json::to_string(&swapevent_json).unwrap();
         //Sending the event to TSS channel.
-        match self.sender.send(serialized_swapevent).await {
+        match send_event(&self.sender, swapevent_json.to_string()).await {
             Ok(()) => log::info!("Connector successfully send swap event to channel"),
             Err(e) => log::info!("Connector failed to send swap event to channel: {:?}", e),
         }
 
-        Ok(swap_result)
+        Ok(aggregated)
     }
 
-    /// This function is to fetch the swap price of a token.
-    pub async fn swap_price<P: web3::contract::tokens::Tokenize>(
-        web_socket: &web3::Web3<Http>,
-        abi_url: &str,
-        exchange_address: &str,
-        query_method: &str,
-        _query_parameter: P,
-    ) -> Result<Vec<i32>, Box<dyn std::error::Error>> {
-        let exchange = Address::from_str(exchange_address).unwrap();
-        let mut res = String::new();
-        if abi_url.contains("http") {
-            res = reqwest::blocking::get(abi_url).unwrap().text().unwrap();
-        } else {
-            let mut abi_file = File::open(abi_url).unwrap();
-            abi_file.read_to_string(&mut res).unwrap();
-        }
+    /// Queries the price of one `token` in `swap_token` on the exchange of `swap`, through
+    /// the chain endpoint of `swap`. Returns the fixed-point price with `PRICE_DECIMALS` decimals.
+    pub async fn query_price(&self, swap: &TokenSwap) -> Result<U256, SwapError> {
+        let token_address = parse_address(&swap.token_address)?;
+        let swap_token_address = parse_address(&swap.swap_token_address)?;
+        let client = self.client(&swap.chain_endpoint).await?;
 
-        // log::info!("token_contract ---> node start up {}",res);
-        let json: serde_json::Value =
-            serde_json::from_str(&res.to_owned()).expect("JSON was not well-formatted");
-        // log::info!("token_contract ---> node start up {}",json);
+        let decimals_from = self
+            .decimals(&client, swap.token_endpoint.as_str(), token_address)
+            .await?;
+        let decimals_to = self
+            .decimals(
+                &client,
+                swap.swap_token_endpoint.as_str(),
+                swap_token_address,
+            )
+            .await?;
 
-        let abi_date = match serde_json::to_string(&json["abi"]) {
-            Ok(response_str) => response_str,
-            Err(_) => "data Error".to_string(),
+        // price of one whole token
+        let amount_in = pow10(decimals_from)?;
+        let amounts = self
+            .amounts_out(
+                &client,
+                swap.exchange_endpoint.as_str(),
+                swap.exchange_address.as_str(),
+                amount_in,
+                vec![token_address, swap_token_address],
+            )
+            .await?;
+        let amount_out = match amounts.as_slice() {
+            [_, amount_out] => *amount_out,
+            _ => {
+                return Err(SwapError::UnexpectedResult(format!(
+                    "expected 2 amounts, got {}",
+                    amounts.len()
+                )))
+            }
         };
-        // log::info!("abi_date ---> node start up {}",abi_date);
-
-        // let abi: String = match &json["abi"] {
-        //     serde_json::Value::String(v) => v.clone(),
-        //     _ => return Err(From::from("abi not found")),
-        // };
-        // log::info!("abi ---> node start up {}",abi);
-        // let abi = match serde_json::to_string(&json["abi"]) {
-        //     Ok(response_str) => response_str,
-        //     Err(_) => "data Error".to_string(),
-        // };
-        // log::info!("json ---> node start up {}",json);
-        // Accessing existing contract of exchange.
-        let token_contract =
-            match Contract::from_json(web_socket.eth(), exchange, abi_date.as_bytes()) {
-                Ok(contract) => contract,
-                Err(error) => return Err(From::from(error)),
-            };
-        // log::info!("json ---> node start up {:?}",token_contract);
-        let query_response: Vec<i32> = match token_contract
+
+        normalize_price(amount_out, decimals_to)
+    }
+
+    /// Calls `getAmountsOut(amountIn, path)` of a Uniswap V2 style router.
+    pub async fn amounts_out(
+        &self,
+        client: &web3::Web3<Http>,
+        abi_endpoint: &str,
+        exchange_address: &str,
+        amount_in: U256,
+        path: Vec<Address>,
+    ) -> Result<Vec<U256>, SwapError> {
+        let exchange = parse_address(exchange_address)?;
+        let exchange_contract = self.contract(client, abi_endpoint, exchange).await?;
+
+        let amounts: Vec<U256> = exchange_contract
             .query(
-                query_method,
-                (exchange.clone(), exchange.clone(), 25),
+                "getAmountsOut",
+                (amount_in, path),
                 None,
                 Options::default(),
                 None,
             )
-            .await
-        {
-            Ok(query_response) => query_response,
-            Err(error) => return Err(From::from(error)),
-        };
-        // log::info!("json ---> node start up {:?}", query_response);
-        Ok(query_response)
+            .await?;
+        Ok(amounts)
     }
 
     /// This function is to fetch the decimals digit of a token.
     pub async fn decimals(
-        web_socket: &web3::Web3<Http>,
-        token_abi_url: &str,
-        token_address: &str,
-    ) -> Result<i32, Box<dyn std::error::Error>> {
-        let exchange = match Address::from_str(token_address) {
-            Ok(address) => address,
-            Err(error) => return Err(From::from(error)),
-        };
-        // log::info!("atomic values ----3");
-        let mut abi_file = File::open(token_abi_url).unwrap();
-        let mut res = String::new();
-        abi_file.read_to_string(&mut res).unwrap();
-
-        // log::info!("token_contract ---> node start up {}",res);
-        let json: serde_json::Value =
-            serde_json::from_str(&res.to_owned()).expect("JSON was not well-formatted");
-        // log::info!("token_contract ---> node start up {}",json);
+        &self,
+        client: &web3::Web3<Http>,
+        abi_endpoint: &str,
+        token_address: Address,
+    ) -> Result<u32, SwapError> {
+        let token_contract = self.contract(client, abi_endpoint, token_address).await?;
 
-        let abi_date = match serde_json::to_string(&json["abi"]) {
-            Ok(response_str) => response_str,
-            Err(_) => "data Error".to_string(),
-        };
-        // log::info!("atomic values ----44");
-        // let abi: String = match &json["abi"] {
-        //     serde_json::Value::String(v) => v.clone(),
-        //     _ => String::from(""),
-        // };
-
-        // Accessing existing contract of exchange.
-        let token_contract =
-            match Contract::from_json(web_socket.eth(), exchange, abi_date.as_bytes()) {
-                Ok(contract) => contract,
-                Err(error) => return Err(From::from(error)),
-            };
         // fetching the decimal of a particular token.
-        // log::info!("atomic values ----5");
-        token_contract
+        let decimals: U256 = token_contract
             .query("decimals", (), None, Options::default(), None)
-            .await
-            .map_err(Into::into)
+            .await?;
+        if decimals > U256::from(MAX_TOKEN_DECIMALS) {
+            return Err(SwapError::UnexpectedResult(format!(
+                "token {:?} has {} decimals",
+                token_address, decimals
+            )));
+        }
+        Ok(decimals.as_u32())
     }
-    pub async fn decimals_old(
-        web_socket: &web3::Web3<Http>,
-        token_abi_url: &str,
-        token_address: &str,
-    ) -> Result<i32, Box<dyn std::error::Error>> {
-        let exchange = match Address::from_str(token_address) {
-            Ok(address) => address,
-            Err(error) => return Err(From::from(error)),
+
+    /// Client of a chain endpoint, created once.
+    async fn client(&self, chain_endpoint: &str) -> Result<web3::Web3<Http>, SwapError> {
+        let mut clients = self.clients.lock().await;
+        if let Some(client) = clients.get(chain_endpoint) {
+            return Ok(client.clone());
+        }
+        let transport = Http::new(chain_endpoint).map_err(|e| SwapError::Endpoint {
+            endpoint: chain_endpoint.to_string(),
+            reason: e.to_string(),
+        })?;
+        let client = web3::Web3::new(transport);
+        clients.insert(chain_endpoint.to_string(), client.clone());
+        Ok(client)
+    }
+
+    /// Accessing existing contract with the abi of `abi_endpoint`.
+    async fn contract(
+        &self,
+        client: &web3::Web3<Http>,
+        abi_endpoint: &str,
+        address: Address,
+    ) -> Result<Contract<Http>, SwapError> {
+        let abi = self.abi(abi_endpoint).await?;
+        Contract::from_json(client.eth(), address, abi.as_bytes()).map_err(|e| SwapError::Abi {
+            endpoint: abi_endpoint.to_string(),
+            reason: e.to_string(),
+        })
+    }
+
+    /// Loads the abi of an endpoint once, from an etherscan `getabi` url
+    /// (`{"result": "<abi>"}`) or a file holding `{"abi": [..]}`.
+    async fn abi(&self, abi_endpoint: &str) -> Result<String, SwapError> {
+        if let Some(abi) = self.abi_cache.lock().await.get(abi_endpoint) {
+            return Ok(abi.clone());
+        }
+
+        let abi_error = |reason: String| SwapError::Abi {
+            endpoint: abi_endpoint.to_string(),
+            reason,
         };
-        // todo file or url
-        let res = match reqwest::blocking::get(token_abi_url) {
-            Ok(url) => match url.text() {
-                Ok(url) => url,
-                Err(error) => return Err(From::from(error)),
-            },
-            Err(error) => return Err(From::from(error)),
+        let res = if abi_endpoint.starts_with("http") {
+            reqwest::get(abi_endpoint)
+                .await
+                .map_err(|e| abi_error(e.to_string()))?
+                .text()
+                .await
+                .map_err(|e| abi_error(e.to_string()))?
+        } else {
+            tokio::fs::read_to_string(abi_endpoint)
+                .await
+                .map_err(|e| abi_error(e.to_string()))?
         };
 
         let json: serde_json::Value =
-            serde_json::from_str(&res.to_owned()).expect("JSON was not well-formatted");
-        log::info!("atomic values ----4");
-        let abi: String = match &json["result"] {
-            serde_json::Value::String(v) => v.clone(),
-            _ => String::from(""),
+            serde_json::from_str(&res).map_err(|e| abi_error(e.to_string()))?;
+        let abi = match (&json["abi"], &json["result"]) {
+            (serde_json::Value::Array(_), _) => json["abi"].to_string(),
+            (_, serde_json::Value::String(abi)) => abi.clone(),
+            _ => return Err(abi_error("no abi in response".into())),
         };
 
-        // Accessing existing contract of exchange.
-        let token_contract = match Contract::from_json(web_socket.eth(), exchange, abi.as_bytes()) {
-            Ok(contract) => contract,
-            Err(error) => return Err(From::from(error)),
-        };
-        // fetching the decimal of a particular token.
+        self.abi_cache
+            .lock()
+            .await
+            .insert(abi_endpoint.to_string(), abi.clone());
+        Ok(abi)
+    }
+}
 
-        token_contract
-            .query("decimals", (), None, Options::default(), None)
+/// Index of the next swap round since the unix epoch and the time until it starts.
+/// Nodes agree on observed prices per round, see `tss::consensus`.
+pub fn next_swap_round(since_epoch: Duration) -> (i32, Duration) {
+    let interval = SWAP_INTERVAL.as_millis();
+    let now = since_epoch.as_millis();
+    let index = now / interval + 1;
+    let until_round = Duration::from_millis((index * interval - now) as u64);
+    (index as i32, until_round)
+}
+
+/// pairs are identified by their token addresses, whatever the case of the hex
+fn pair_key(swap: &TokenSwap) -> String {
+    format!(
+        "{}/{}",
+        swap.token_address.to_lowercase(),
+        swap.swap_token_address.to_lowercase()
+    )
+}
+
+fn parse_address(address: &str) -> Result<Address, SwapError> {
+    Address::from_str(address).map_err(|_| SwapError::InvalidAddress(address.to_string()))
+}
+
+fn pow10(exponent: u32) -> Result<U256, SwapError> {
+    U256::from(10u64)
+        .checked_pow(U256::from(exponent))
+        .ok_or_else(|| SwapError::UnexpectedResult(format!("10^{} overflows", exponent)))
+}
+
+/// Converts an amount with `decimals` decimals into a price with `PRICE_DECIMALS` decimals.
+pub fn normalize_price(amount: U256, decimals: u32) -> Result<U256, SwapError> {
+    if decimals <= PRICE_DECIMALS {
+        amount
+            .checked_mul(pow10(PRICE_DECIMALS - decimals)?)
+            .ok_or_else(|| SwapError::UnexpectedResult(format!("price {} overflows", amount)))
+    } else {
+        Ok(amount / pow10(decimals - PRICE_DECIMALS)?)
+    }
+}
+
+/// Swap prices of the `token_swap` collection as a `ChainConnector`,
+/// every round queries all configured pairs.
+pub struct SwapConnector {
+    pub connection: Arc<dyn Repo>,
+    pub sender: mpsc::Sender<String>,
+    clients: ClientCache,
+    abi_cache: AbiCache,
+    price_history: PriceHistories,
+    swap_index: Arc<AtomicI32>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl SwapConnector {
+    pub const NAME: &'static str = "swap";
+
+    pub fn new(ctx: &ConnectorContext) -> Self {
+        SwapConnector {
+            connection: ctx.connection.clone(),
+            sender: ctx.sender.clone(),
+            clients: Arc::new(Mutex::new(HashMap::new())),
+            abi_cache: Arc::new(Mutex::new(HashMap::new())),
+            price_history: Arc::new(Mutex::new(HashMap::new())),
+            swap_index: Arc::new(AtomicI32::new(0)),
+            handle: None,
+        }
+    }
+
+    async fn run(
+        connection: Arc<dyn Repo>,
+        sender: mpsc::Sender<String>,
+        clients: ClientCache,
+        abi_cache: AbiCache,
+        price_history: PriceHistories,
+        swap_index: Arc<AtomicI32>,
+    ) {
+        loop {
+            // rounds start at multiples of the interval, so every node queries the same round
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let (index, until_round) = next_swap_round(now);
+            tokio::time::sleep(until_round).await;
+            swap_index.store(index, Ordering::SeqCst);
+            // each pair is queried through its own chain endpoint
+            let arguments = SwapToken {
+                clients: clients.clone(),
+                connection: connection.clone(),
+                sender: sender.clone(),
+                abi_cache: abi_cache.clone(),
+                price_history: price_history.clone(),
+            };
+            SwapToken::swap_thread_handler(&arguments, index).await;
+        }
+    }
+}
+
+#[async_trait]
+impl ChainConnector for SwapConnector {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn event_type(&self) -> &'static str {
+        "swap"
+    }
+
+    fn start(&mut self) -> eyre::Result<()> {
+        let connection = self.connection.clone();
+        let sender = self.sender.clone();
+        let clients = self.clients.clone();
+        let abi_cache = self.abi_cache.clone();
+        let price_history = self.price_history.clone();
+        let swap_index = self.swap_index.clone();
+        self.handle = Some(tokio::spawn(async move {
+            SwapConnector::run(
+                connection,
+                sender,
+                clients,
+                abi_cache,
+                price_history,
+                swap_index,
+            )
             .await
-            .map_err(Into::into)
+        }));
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+        }
+    }
+
+    fn health(&self) -> ConnectorHealth {
+        task_health(&self.handle)
+    }
+
+    /// index of the last swap round
+    async fn cursor(&self) -> Option<String> {
+        Some(self.swap_index.load(Ordering::SeqCst).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockEthereum;
+    use database::MemoryRepo;
+    use web3::ethabi::{encode, Token};
+
+    /// writes `{"abi": [..]}` file like the ones served by etherscan
+    fn abi_file(name: &str, abi: serde_json::Value) -> String {
+        let path = std::env::temp_dir().join(format!("tango-{}-{}.json", name, std::process::id()));
+        std::fs::write(&path, json!({ "abi": abi }).to_string()).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    /// amounts returned by `getAmountsOut` of a router for 1 WETH (18 decimals)
+    fn amounts_out(usdt: u64) -> Vec<u8> {
+        encode(&[Token::Array(vec![
+            Token::Uint(U256::exp10(18)),
+            Token::Uint(U256::from(usdt)),
+        ])])
+    }
+
+    /// WETH/USDT pair on every exchange of `exchanges`, each at its own address
+    fn weth_usdt_swaps(node: &MockEthereum, exchanges: &[(&str, u64)]) -> Vec<TokenSwap> {
+        let token = ethers::types::Address::from_low_u64_be(2);
+        let swap_token = ethers::types::Address::from_low_u64_be(3);
+        node.set_call(token, encode(&[Token::Uint(18.into())]));
+        node.set_call(swap_token, encode(&[Token::Uint(6.into())]));
+
+        let token_abi = abi_file(
+            "token",
+            json!([{
+                "name": "decimals", "type": "function", "stateMutability": "view",
+                "inputs": [], "outputs": [{"name": "", "type": "uint8"}]
+            }]),
+        );
+        let exchange_abi = abi_file(
+            "exchange",
+            json!([{
+                "name": "getAmountsOut", "type": "function", "stateMutability": "view",
+                "inputs": [
+                    {"name": "amountIn", "type": "uint256"},
+                    {"name": "path", "type": "address[]"}
+                ],
+                "outputs": [{"name": "amounts", "type": "uint256[]"}]
+            }]),
+        );
+
+        exchanges
+            .iter()
+            .enumerate()
+            .map(|(i, (exchange, usdt))| {
+                let exchange_address = ethers::types::Address::from_low_u64_be(10 + i as u64);
+                node.set_call(exchange_address, amounts_out(*usdt));
+                TokenSwap::new(
+                    "ethereum".into(),
+                    node.node.http_url(),
+                    exchange.to_string(),
+                    format!("{:?}", exchange_address),
+                    exchange_abi.clone(),
+                    "WETH".into(),
+                    format!("{:?}", token),
+                    token_abi.clone(),
+                    "USDT".into(),
+                    format!("{:?}", swap_token),
+                    token_abi.clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn swap_handler_sends_swap_event_to_tss() {
+        let node = MockEthereum::start(10).await;
+        // 1 WETH (18 decimals) for 1700.5 USDT (6 decimals)
+        let swaps = weth_usdt_swaps(&node, &[("Uniswap", 1_700_500_000)]);
+        let (swap_from, swap_to) = (
+            swaps[0].token_address.clone(),
+            swaps[0].swap_token_address.clone(),
+        );
+
+        let (sender, mut receiver) = mpsc::channel(10);
+        let swap_token_connector = SwapToken::new(Arc::new(MemoryRepo::new()), sender);
+
+        let aggregated = swap_token_connector.swap_handler(swaps, 7).await.unwrap();
+        assert_eq!(aggregated.price, U256::from(17_005u64) * U256::exp10(17));
+
+        let event = receiver.recv().await.unwrap();
+        let event: serde_json::Value = serde_json::from_str(&event).unwrap();
+        assert_eq!(event["event_type"], "swap");
+        assert_eq!(event["swap_from"], swap_from);
+        assert_eq!(event["swap_to"], swap_to);
+        assert_eq!(event["swap_index"], 7);
+        assert_eq!(event["swap_price"], "1700500000000000000000");
+        assert_eq!(event["twap_price"], "1700500000000000000000");
+        assert_eq!(event["price_decimals"], PRICE_DECIMALS);
+        assert_eq!(event["sources"][0]["exchange"], "Uniswap");
+        assert_eq!(event["sources"][0]["accepted"], true);
+    }
+
+    #[tokio::test]
+    async fn swap_handler_rejects_manipulated_exchange() {
+        let node = MockEthereum::start(10).await;
+        let swaps = weth_usdt_swaps(
+            &node,
+            &[
+                ("Uniswap", 1_700_000_000),
+                ("Sushiswap", 1_702_000_000),
+                ("Manipulated", 2_500_000_000),
+            ],
+        );
+
+        let (sender, mut receiver) = mpsc::channel(10);
+        let swap_token_connector = SwapToken::new(Arc::new(MemoryRepo::new()), sender);
+
+        let aggregated = swap_token_connector.swap_handler(swaps, 1).await.unwrap();
+        assert_eq!(aggregated.price, U256::from(1_700u64) * U256::exp10(18));
+
+        let event = receiver.recv().await.unwrap();
+        let event: serde_json::Value = serde_json::from_str(&event).unwrap();
+        assert_eq!(event["swap_price"], "1700000000000000000000");
+        let sources = event["sources"].as_array().unwrap();
+        assert_eq!(sources.len(), 3);
+        assert_eq!(sources[2]["exchange"], "Manipulated");
+        assert_eq!(sources[2]["swap_price"], "2500000000000000000000");
+        assert_eq!(sources[2]["accepted"], false);
+    }
+
+    #[tokio::test]
+    async fn swap_handler_reports_missing_abi() {
+        let node = MockEthereum::start(10).await;
+        let swap = TokenSwap::new(
+            "ethereum".into(),
+            node.node.http_url(),
+            "Uniswap".into(),
+            format!("{:?}", ethers::types::Address::from_low_u64_be(1)),
+            "/nonexistent/exchange.json".into(),
+            "WETH".into(),
+            format!("{:?}", ethers::types::Address::from_low_u64_be(2)),
+            "/nonexistent/token.json".into(),
+            "USDT".into(),
+            format!("{:?}", ethers::types::Address::from_low_u64_be(3)),
+            "/nonexistent/token.json".into(),
+        );
+
+        let (sender, mut receiver) = mpsc::channel(10);
+        let swap_token_connector = SwapToken::new(Arc::new(MemoryRepo::new()), sender);
+
+        let result = swap_token_connector.swap_handler(vec![swap], 1).await;
+        assert!(matches!(result, Err(SwapError::Abi { .. })));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    //tests that a swap round prices the pairs stored in the repo
+    #[tokio::test]
+    async fn swap_round_prices_stored_pairs() {
+        let node = MockEthereum::start(10).await;
+        let connection = Arc::new(MemoryRepo::new());
+        connection.insert_swap_data(weth_usdt_swaps(&node, &[("Uniswap", 1_700_000_000)]));
+
+        let (sender, mut receiver) = mpsc::channel(10);
+        SwapToken::new(connection, sender)
+            .swap_thread_handler(3)
+            .await;
+
+        let event = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .expect("no swap event sent to tss")
+            .unwrap();
+        let event: serde_json::Value = serde_json::from_str(&event).unwrap();
+        assert_eq!(event["swap_index"], 3);
+        assert_eq!(event["swap_price"], "1700000000000000000000");
+    }
+
+    //tests that pairs of different chains are priced by the node of their own chain endpoint
+    #[tokio::test]
+    async fn queries_each_pair_on_its_chain_endpoint() {
+        let first = MockEthereum::start(10).await;
+        let second = MockEthereum::start(10).await;
+        let first_swaps = weth_usdt_swaps(&first, &[("Uniswap", 1_700_000_000)]);
+        let second_swaps = weth_usdt_swaps(&second, &[("Uniswap", 1_800_000_000)]);
+
+        let (sender, _receiver) = mpsc::channel(10);
+        let swap_token_connector = SwapToken::new(Arc::new(MemoryRepo::new()), sender);
+        assert_eq!(
+            swap_token_connector
+                .query_price(&first_swaps[0])
+                .await
+                .unwrap(),
+            U256::from(1_700u64) * U256::exp10(18)
+        );
+        assert_eq!(
+            swap_token_connector
+                .query_price(&second_swaps[0])
+                .await
+                .unwrap(),
+            U256::from(1_800u64) * U256::exp10(18)
+        );
+        assert_eq!(swap_token_connector.clients.lock().await.len(), 2);
+    }
+
+    #[test]
+    fn swap_rounds_start_at_interval() {
+        assert_eq!(
+            next_swap_round(Duration::from_millis(1_700_000_003_500)),
+            (170_000_001, Duration::from_millis(6_500))
+        );
+        assert_eq!(
+            next_swap_round(Duration::from_secs(1_700_000_010)),
+            (170_000_002, SWAP_INTERVAL)
+        );
+    }
+
+    #[test]
+    fn normalizes_price_decimals() {
+        assert_eq!(
+            normalize_price(U256::from(1_700_500_000u64), 6).unwrap(),
+            U256::from(17_005u64) * U256::exp10(17)
+        );
+        assert_eq!(
+            normalize_price(U256::from(5u64) * U256::exp10(20), 20).unwrap(),
+            U256::from(5u64) * U256::exp10(18)
+        );
+        assert!(normalize_price(U256::MAX, 0).is_err());
     }
 }
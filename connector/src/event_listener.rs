@@ -1,89 +1,537 @@
+use crate::chain_connector::send_event;
+use database::models::ConnectorCursor;
+use database::Repo;
 use ethers::prelude::*;
 use eyre::Result;
-use std::fs::File;
-use std::io::prelude::*;
+use serde_json::json;
+use std::collections::BTreeMap;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
 
+/// Number of blocks built on top of a log before it is sent to TSS.
+pub const DEFAULT_CONFIRMATIONS: u64 = 12;
+
+/// Forwarded logs are remembered for this many blocks after confirmation,
+/// so a reorg deeper than the confirmation depth still cancels them in TSS.
+const REORG_HISTORY_BLOCKS: u64 = 64;
+
+/// Number of blocks requested per `eth_getLogs` call while backfilling.
+pub const BACKFILL_BLOCK_RANGE: u64 = 2000;
+
+/// Wait before reconnecting to the provider after the subscription ended.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// identifies a log inside a block, removed logs are reported with the same values
+type LogKey = (Option<H256>, Option<H256>, Option<U256>);
+
+fn log_key(log: &Log) -> LogKey {
+    (log.block_hash, log.transaction_hash, log.log_index)
+}
+
+/// Message telling TSS to drop an event which was reorged out before it got signed.
+pub fn cancel_event_msg(serialized_event: &str) -> String {
+    json!({
+        "event_type": "cancel",
+        "event": serialized_event,
+    })
+    .to_string()
+}
+
 #[derive(Clone)]
 pub struct Connector {
     pub infura_endpoint: String,
     pub contract_address: String,
     pub topic: String,
+    pub confirmations: u64,
+    pub connection: Arc<dyn Repo>,
     pub sender: mpsc::Sender<String>,
 }
 
+/// Logs of one provider session: waiting for confirmations and already sent to TSS, by block number.
+#[derive(Default)]
+struct LogBuffer {
+    pending: BTreeMap<u64, Vec<Log>>,
+    forwarded: BTreeMap<u64, Vec<(Log, String)>>,
+    /// stored cursor, the last block whose logs were sent to TSS
+    cursor: Option<u64>,
+}
+
+impl LogBuffer {
+    /// adds a log unless the subscription and a backfill both returned it
+    fn insert(&mut self, block_number: u64, log: Log) {
+        let key = log_key(&log);
+        let logs = self.pending.entry(block_number).or_default();
+        let already_sent = self
+            .forwarded
+            .get(&block_number)
+            .map_or(false, |sent| sent.iter().any(|(sent_log, _)| log_key(sent_log) == key));
+        if !already_sent && !logs.iter().any(|pending_log| log_key(pending_log) == key) {
+            logs.push(log);
+        }
+    }
+}
+
 impl Connector {
     /// create a new event instance
     pub fn new(
         infura_endpoint: &str,
         contract_address: &str,
         topic: &str,
+        confirmations: u64,
+        connection: Arc<dyn Repo>,
         sender: mpsc::Sender<String>,
     ) -> Self {
         Connector {
             infura_endpoint: infura_endpoint.to_string(),
             contract_address: contract_address.to_string(),
             topic: topic.to_string(),
+            confirmations,
+            connection,
             sender: sender,
         }
     }
 
+    /// Keeps the listener running, every reconnect resumes from the stored cursor.
+    pub async fn run(&self) {
+        loop {
+            if let Err(e) = self.event_listener().await {
+                log::error!("Connector listener for {} stopped: {:?}", self.contract_address, e);
+            } else {
+                log::warn!("Connector subscription for {} ended", self.contract_address);
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
     pub async fn event_listener(&self) -> Result<()> {
         let provider = Provider::<Ws>::connect(self.infura_endpoint.as_str()).await;
         if let Err(e) = provider {
             log::error!("Error connecting to provider: {:?}", e);
             return Err(eyre::eyre!("Error connecting to provider"));
         }
-        let client = Arc::new(provider.unwrap());
-
-        let current_block = client
-            .get_block(BlockNumber::Latest)
-            .await?
-            .unwrap()
-            .number
-            .unwrap();
-        println!("current_block: {}", current_block);
-
-        // Fetch the last block number stored on chain.
-        let mut file = File::open("blockdata.db").unwrap();
-        let mut last_blocknumber = String::new();
-        file.read_to_string(&mut last_blocknumber).unwrap();
+        let client = provider.unwrap();
 
         // Creating filter to fetch the events of smart contract.
         let erc20_transfer_filter = Filter::new()
-            .from_block(BlockNumber::Number(U64::from(
-                last_blocknumber.parse::<i64>().unwrap(),
-            )))
-            .address(vec![
-                Address::from_str(self.contract_address.as_str()).unwrap()
-            ])
-            .topic0(vec![H256::from_str(self.topic.as_ref()).unwrap()]);
+            .address(vec![Address::from_str(self.contract_address.as_str())?])
+            .topic0(vec![H256::from_str(self.topic.as_ref())?]);
 
+        // subscribing before backfill so no log between backfill and live stream is lost
         let mut stream = client.subscribe_logs(&erc20_transfer_filter).await?;
+        let mut blocks = client.subscribe_blocks().await?;
+
+        let mut head = client.get_block_number().await?.as_u64();
+        log::info!("Connector {} current block: {}", self.contract_address, head);
 
-        // Streaming the events to the TSS channel.
-        while let Some(log) = stream.next().await {
-            // Updating the block no in the db.
-            let mut file = std::fs::OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .open("blockdata.db")
-                .unwrap();
-            let blocknumber = i64::try_from(log.block_number.unwrap()).unwrap();
-            file.write_all(blocknumber.to_string().as_ref())?;
-
-            let serialized_event = serde_json::to_string(&log).unwrap();
-
-            //Sending the event to TSS channel.
-            match self.sender.send(serialized_event).await {
-                Ok(()) => log::info!("Connector successfully send event to channel"),
-                Err(_) => log::info!("Connector failed to send event to channel"),
+        // Fetch the last block whose logs were sent to TSS, without cursor only new logs are sent.
+        let cursor = match self
+            .connection
+            .get_connector_cursor(&self.contract_address, &self.topic)
+            .await?
+        {
+            Some(cursor) => Some(u64::try_from(cursor.block_number)?),
+            None => None,
+        };
+        let from_block = match cursor {
+            Some(cursor) => cursor + 1,
+            None => head.saturating_sub(self.confirmations) + 1,
+        };
+
+        let mut buffer = LogBuffer {
+            cursor,
+            ..LogBuffer::default()
+        };
+        self.backfill(&client, &erc20_transfer_filter, from_block, head, &mut buffer)
+            .await?;
+        self.forward_confirmed_logs(&client, head, &mut buffer)
+            .await?;
+
+        loop {
+            tokio::select! {
+                log = stream.next() => {
+                    let log = match log {
+                        Some(log) => log,
+                        None => break,
+                    };
+                    let block_number = match log.block_number {
+                        Some(block_number) => block_number.as_u64(),
+                        None => {
+                            log::warn!("Connector received log without block number");
+                            continue;
+                        }
+                    };
+
+                    if log.removed == Some(true) {
+                        self.handle_removed_log(&log, block_number, &mut buffer).await;
+                    } else {
+                        buffer.insert(block_number, log);
+                    }
+                }
+
+                block = blocks.next() => {
+                    let new_head = match block.and_then(|block| block.number) {
+                        Some(new_head) => new_head.as_u64(),
+                        None => break,
+                    };
+
+                    // headers were skipped, logs of those blocks may be missing as well
+                    if new_head > head + 1 {
+                        log::warn!(
+                            "Connector {} missed blocks {} to {}, backfilling",
+                            self.contract_address,
+                            head + 1,
+                            new_head - 1
+                        );
+                        self.backfill(&client, &erc20_transfer_filter, head + 1, new_head, &mut buffer)
+                            .await?;
+                    }
+                    head = head.max(new_head);
+
+                    self.forward_confirmed_logs(&client, head, &mut buffer)
+                        .await?;
+                }
             }
         }
 
         Ok(())
     }
+
+    /// Fetches logs of blocks `from_block..=to_block` in ranges of `BACKFILL_BLOCK_RANGE`.
+    async fn backfill(
+        &self,
+        client: &Provider<Ws>,
+        filter: &Filter,
+        from_block: u64,
+        to_block: u64,
+        buffer: &mut LogBuffer,
+    ) -> Result<()> {
+        let mut start = from_block;
+        while start <= to_block {
+            let end = to_block.min(start + BACKFILL_BLOCK_RANGE - 1);
+            let range_filter = filter
+                .clone()
+                .from_block(BlockNumber::Number(U64::from(start)))
+                .to_block(BlockNumber::Number(U64::from(end)));
+
+            let logs = client.get_logs(&range_filter).await?;
+            log::info!(
+                "Connector {} backfilled {} logs of blocks {} to {}",
+                self.contract_address,
+                logs.len(),
+                start,
+                end
+            );
+            for log in logs {
+                if let Some(block_number) = log.block_number {
+                    buffer.insert(block_number.as_u64(), log);
+                }
+            }
+            start = end + 1;
+        }
+        Ok(())
+    }
+
+    /// Drops a log removed by a reorg. If it was already sent to TSS a cancellation is sent.
+    async fn handle_removed_log(&self, log: &Log, block_number: u64, buffer: &mut LogBuffer) {
+        let key = log_key(log);
+
+        if let Some(logs) = buffer.pending.get_mut(&block_number) {
+            let before = logs.len();
+            logs.retain(|pending_log| log_key(pending_log) != key);
+            if logs.len() != before {
+                log::info!(
+                    "Connector dropped unconfirmed log of block {} removed by reorg",
+                    block_number
+                );
+            }
+        }
+
+        if let Some(logs) = buffer.forwarded.get_mut(&block_number) {
+            if let Some(index) = logs.iter().position(|(sent_log, _)| log_key(sent_log) == key) {
+                let (_, serialized_event) = logs.remove(index);
+                log::warn!(
+                    "Connector log of block {} reorged out after confirmation, cancelling",
+                    block_number
+                );
+
+                //Sending the cancellation to TSS channel.
+                match send_event(&self.sender, cancel_event_msg(&serialized_event)).await {
+                    Ok(()) => log::info!("Connector successfully send cancel event to channel"),
+                    Err(_) => log::info!("Connector failed to send cancel event to channel"),
+                }
+            }
+        }
+    }
+
+    /// Sends logs with enough confirmations at `head` to TSS and moves the stored cursor to the
+    /// last block whose logs were sent.
+    async fn forward_confirmed_logs(
+        &self,
+        client: &Provider<Ws>,
+        head: u64,
+        buffer: &mut LogBuffer,
+    ) -> Result<()> {
+        let confirmed_head = match head.checked_sub(self.confirmations) {
+            Some(confirmed_head) => confirmed_head,
+            None => return Ok(()),
+        };
+        let confirmed_blocks: Vec<u64> = buffer
+            .pending
+            .range(..=confirmed_head)
+            .map(|(block_number, _)| *block_number)
+            .collect();
+
+        let mut last_sent = None;
+        for block_number in confirmed_blocks {
+            let logs = buffer.pending.remove(&block_number).unwrap_or_default();
+            if logs.is_empty() {
+                continue;
+            }
+
+            // a log whose block is not canonical anymore was reorged out without a removed log
+            let canonical_hash = client
+                .get_block(BlockNumber::Number(U64::from(block_number)))
+                .await?
+                .and_then(|block| block.hash);
+
+            for log in logs {
+                if log.block_hash != canonical_hash {
+                    log::warn!(
+                        "Connector skipped log of non canonical block {}",
+                        block_number
+                    );
+                    continue;
+                }
+
+                let serialized_event = serde_json::to_string(&log)?;
+
+                //Sending the event to TSS channel, the cursor only moves past sent events.
+                send_event(&self.sender, serialized_event.clone())
+                    .await
+                    .map_err(|_| eyre::eyre!("TSS channel closed"))?;
+                log::info!("Connector successfully send event to channel");
+                buffer
+                    .forwarded
+                    .entry(block_number)
+                    .or_default()
+                    .push((log, serialized_event));
+                last_sent = Some(block_number);
+            }
+        }
+
+        // Updating the cursor in the db. Logs of later confirmed blocks may still arrive from the
+        // subscription, so it only moves to blocks whose logs were sent and never back.
+        if let Some(block_number) =
+            last_sent.filter(|block_number| buffer.cursor < Some(*block_number))
+        {
+            self.connection
+                .upsert_connector_cursor(ConnectorCursor::new(
+                    self.contract_address.clone(),
+                    self.topic.clone(),
+                    i64::try_from(block_number)?,
+                ))
+                .await?;
+            buffer.cursor = Some(block_number);
+        }
+
+        let keep_from = head.saturating_sub(self.confirmations + REORG_HISTORY_BLOCKS);
+        buffer.forwarded = buffer.forwarded.split_off(&keep_from);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockEthereum;
+    use database::MemoryRepo;
+    use tokio::time::timeout;
+
+    /// contract without stored cursor
+    fn new_contract() -> Address {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        Address::from_low_u64_be(nanos as u64)
+    }
+
+    fn start_listener(
+        node: &MockEthereum,
+        contract: Address,
+        confirmations: u64,
+        connection: Arc<dyn Repo>,
+    ) -> (tokio::task::JoinHandle<Result<()>>, mpsc::Receiver<String>) {
+        let (sender, receiver) = mpsc::channel(10);
+        let connector = Connector::new(
+            &node.node.ws_url(),
+            &format!("{:?}", contract),
+            &format!("{:?}", MockEthereum::topic()),
+            confirmations,
+            connection,
+            sender,
+        );
+        let listener = tokio::spawn(async move { connector.event_listener().await });
+        (listener, receiver)
+    }
+
+    async fn next_event(receiver: &mut mpsc::Receiver<String>) -> String {
+        timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .expect("no event sent to tss")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn forwards_confirmed_logs_and_drops_removed_logs() {
+        let node = MockEthereum::start(10).await;
+        let contract = new_contract();
+        let (listener, mut receiver) =
+            start_listener(&node, contract, 2, Arc::new(MemoryRepo::new()));
+
+        let confirmed = MockEthereum::log(contract, 11, 0);
+        let reorged = MockEthereum::log(contract, 12, 0);
+        node.push_log(&confirmed);
+        node.push_log(&reorged);
+        node.push_removed_log(&reorged);
+        for head in 11..=14 {
+            node.new_head(head);
+        }
+
+        let event = next_event(&mut receiver).await;
+        assert_eq!(serde_json::from_str::<Log>(&event).unwrap(), confirmed);
+        assert!(timeout(Duration::from_millis(500), receiver.recv())
+            .await
+            .is_err());
+        listener.abort();
+    }
+
+    #[tokio::test]
+    async fn cancels_forwarded_log_removed_by_reorg() {
+        let node = MockEthereum::start(10).await;
+        let contract = new_contract();
+        let (listener, mut receiver) =
+            start_listener(&node, contract, 0, Arc::new(MemoryRepo::new()));
+
+        let log = MockEthereum::log(contract, 11, 0);
+        node.push_log(&log);
+        node.new_head(11);
+        node.push_removed_log(&log);
+
+        let event = next_event(&mut receiver).await;
+        assert_eq!(serde_json::from_str::<Log>(&event).unwrap(), log);
+        assert_eq!(next_event(&mut receiver).await, cancel_event_msg(&event));
+        listener.abort();
+    }
+
+    //a reorg replaced block 11 without reporting the removed log, only the log of the canonical block is sent
+    #[tokio::test]
+    async fn skips_log_of_reorged_block() {
+        let node = MockEthereum::start(10).await;
+        let contract = new_contract();
+        let (listener, mut receiver) =
+            start_listener(&node, contract, 1, Arc::new(MemoryRepo::new()));
+
+        let mut forked = MockEthereum::log(contract, 11, 0);
+        forked.block_hash = Some(H256::repeat_byte(0xaa));
+        let canonical = MockEthereum::log(contract, 11, 1);
+        node.push_log(&forked);
+        node.push_log(&canonical);
+        for head in 11..=13 {
+            node.new_head(head);
+        }
+
+        let event = next_event(&mut receiver).await;
+        assert_eq!(serde_json::from_str::<Log>(&event).unwrap(), canonical);
+        assert!(timeout(Duration::from_millis(500), receiver.recv())
+            .await
+            .is_err());
+        listener.abort();
+    }
+
+    #[tokio::test]
+    async fn backfills_from_stored_cursor() {
+        let node = MockEthereum::start(10).await;
+        let contract = new_contract();
+        let contract_address = format!("{:?}", contract);
+        let topic = format!("{:?}", MockEthereum::topic());
+        let connection = Arc::new(MemoryRepo::new());
+        connection
+            .upsert_connector_cursor(ConnectorCursor::new(
+                contract_address.clone(),
+                topic.clone(),
+                5,
+            ))
+            .await
+            .unwrap();
+
+        // before the cursor, already sent
+        node.add_log(MockEthereum::log(contract, 4, 0));
+        let missed = MockEthereum::log(contract, 7, 0);
+        node.add_log(missed.clone());
+
+        let (listener, mut receiver) = start_listener(&node, contract, 2, connection.clone());
+
+        let event = next_event(&mut receiver).await;
+        assert_eq!(serde_json::from_str::<Log>(&event).unwrap(), missed);
+        assert!(timeout(Duration::from_millis(500), receiver.recv())
+            .await
+            .is_err());
+
+        // the confirmed head is 8, the cursor stays at the block of the last sent log
+        let cursor = connection
+            .get_connector_cursor(&contract_address, &topic)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cursor.block_number, 7);
+        listener.abort();
+    }
+
+    //tests that the cursor does not move past confirmed blocks whose logs were not sent yet
+    #[tokio::test]
+    async fn keeps_cursor_at_last_sent_log() {
+        let node = MockEthereum::start(10).await;
+        let contract = new_contract();
+        let contract_address = format!("{:?}", contract);
+        let topic = format!("{:?}", MockEthereum::topic());
+        let connection = Arc::new(MemoryRepo::new());
+        let (listener, mut receiver) = start_listener(&node, contract, 0, connection.clone());
+
+        // block 12 is confirmed before its log arrives
+        node.new_head(11);
+        node.new_head(12);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(connection
+            .get_connector_cursor(&contract_address, &topic)
+            .await
+            .unwrap()
+            .is_none());
+
+        let late = MockEthereum::log(contract, 12, 0);
+        node.push_log(&late);
+        node.new_head(13);
+        node.new_head(14);
+        let event = next_event(&mut receiver).await;
+        assert_eq!(serde_json::from_str::<Log>(&event).unwrap(), late);
+        let cursor = timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(cursor) = connection
+                    .get_connector_cursor(&contract_address, &topic)
+                    .await
+                    .unwrap()
+                {
+                    return cursor;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("cursor not stored");
+        assert_eq!(cursor.block_number, 12);
+        listener.abort();
+    }
 }
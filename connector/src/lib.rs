@@ -1,3 +1,9 @@
+pub mod chain_connector;
 pub mod ethereum;
 pub mod event_listener;
 pub mod polkadot;
+pub mod price;
+pub mod supervisor;
+
+#[cfg(test)]
+mod mock;
@@ -0,0 +1,605 @@
+//! In-process chain nodes for connector tests.
+//!
+//! `MockNode` answers JSON-RPC over WebSocket and HTTP from scripted handlers and
+//! pushes subscription notifications in the order they were queued.
+//! `MockEthereum` scripts the calls used by the ethereum connectors on top of it,
+//! `MockSubstrate` the calls used by the polkadot connector.
+
+use codec::{Compact, Encode};
+use ethers::prelude::{Address, Block, Bytes, Log, TxHash, H256, U256, U64};
+use futures::{SinkExt, StreamExt};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use scale_info::{meta_type, TypeInfo};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use subxt::config::substrate::{BlakeTwo256, Digest, SubstrateHeader, H256 as SubstrateHash};
+use subxt::config::Header;
+use subxt::events::Phase;
+use subxt::ext::frame_metadata::v15::{
+    CustomMetadata, ExtrinsicMetadata, OuterEnums, PalletEventMetadata, PalletMetadata,
+    RuntimeMetadataV15,
+};
+use subxt::ext::frame_metadata::RuntimeMetadataPrefixed;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::Message;
+
+/// Gap between two notifications so clients see them in order even when
+/// they are routed to different subscription streams.
+const NOTIFICATION_INTERVAL: Duration = Duration::from_millis(50);
+
+type Handler = Box<dyn Fn(&Value) -> Value + Send + Sync>;
+
+/// Subscribe method of the node and the method of its notifications.
+/// With `kind_from_params` the first param names the subscription (`eth_subscribe`).
+struct SubscribeMethod {
+    notification: String,
+    kind_from_params: bool,
+}
+
+#[derive(Default)]
+struct MockState {
+    handlers: HashMap<String, Handler>,
+    subscribe_methods: HashMap<String, SubscribeMethod>,
+    /// notifications not yet delivered, by subscription kind
+    notifications: VecDeque<(String, Value)>,
+    next_subscription: u64,
+}
+
+impl MockState {
+    fn respond(&self, request: &Value) -> Value {
+        let id = request["id"].clone();
+        let method = request["method"].as_str().unwrap_or_default();
+        match self.handlers.get(method) {
+            Some(handler) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": handler(&request["params"]),
+            }),
+            None => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": -32601, "message": format!("Method not found: {}", method)},
+            }),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MockNode {
+    state: Arc<Mutex<MockState>>,
+    ws_addr: SocketAddr,
+    http_addr: SocketAddr,
+}
+
+impl MockNode {
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(MockState::default()));
+
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ws_addr = ws_listener.local_addr().unwrap();
+        let ws_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = ws_listener.accept().await {
+                tokio::spawn(serve_ws(stream, ws_state.clone()));
+            }
+        });
+
+        let http_listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let http_addr = http_listener.local_addr().unwrap();
+        let http_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = http_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    serve_http(request, state.clone())
+                }))
+            }
+        });
+        let server = Server::from_tcp(http_listener).unwrap().serve(make_service);
+        tokio::spawn(server);
+
+        MockNode {
+            state,
+            ws_addr,
+            http_addr,
+        }
+    }
+
+    pub fn ws_url(&self) -> String {
+        format!("ws://{}", self.ws_addr)
+    }
+
+    pub fn http_url(&self) -> String {
+        format!("http://{}", self.http_addr)
+    }
+
+    /// answers `method` with the result of `handler` called with the request params
+    pub fn on<F>(&self, method: &str, handler: F)
+    where
+        F: Fn(&Value) -> Value + Send + Sync + 'static,
+    {
+        self.state
+            .lock()
+            .unwrap()
+            .handlers
+            .insert(method.to_string(), Box::new(handler));
+    }
+
+    pub fn on_subscribe(&self, method: &str, notification: &str, kind_from_params: bool) {
+        self.state.lock().unwrap().subscribe_methods.insert(
+            method.to_string(),
+            SubscribeMethod {
+                notification: notification.to_string(),
+                kind_from_params,
+            },
+        );
+    }
+
+    /// queues a notification, it is delivered once a client subscribed to `kind`
+    pub fn notify(&self, kind: &str, result: Value) {
+        self.state
+            .lock()
+            .unwrap()
+            .notifications
+            .push_back((kind.to_string(), result));
+    }
+}
+
+async fn serve_http(
+    request: Request<Body>,
+    state: Arc<Mutex<MockState>>,
+) -> Result<Response<Body>, Infallible> {
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .unwrap_or_default();
+    let response = match serde_json::from_slice::<Value>(&body) {
+        Ok(Value::Array(requests)) => {
+            let state = state.lock().unwrap();
+            Value::Array(requests.iter().map(|r| state.respond(r)).collect())
+        }
+        Ok(request) => state.lock().unwrap().respond(&request),
+        Err(_) => json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32700, "message": "Parse error"}}),
+    };
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Body::from(response.to_string()))
+        .unwrap())
+}
+
+async fn serve_ws(stream: TcpStream, state: Arc<Mutex<MockState>>) {
+    let socket = match tokio_tungstenite::accept_async(stream).await {
+        Ok(socket) => socket,
+        Err(_) => return,
+    };
+    let (mut sink, mut source) = socket.split();
+    // subscription kind of this connection to (subscription id, notification method)
+    let mut subscriptions: HashMap<String, (String, String)> = HashMap::new();
+    let mut ticker = tokio::time::interval(NOTIFICATION_INTERVAL);
+
+    loop {
+        tokio::select! {
+            message = source.next() => {
+                let request = match message {
+                    Some(Ok(Message::Text(text))) => match serde_json::from_str::<Value>(&text) {
+                        Ok(request) => request,
+                        Err(_) => continue,
+                    },
+                    Some(Ok(_)) => continue,
+                    _ => return,
+                };
+
+                let response = {
+                    let mut state = state.lock().unwrap();
+                    let method = request["method"].as_str().unwrap_or_default().to_string();
+                    let subscribe = state.subscribe_methods.get(&method).map(|subscribe| {
+                        let kind = if subscribe.kind_from_params {
+                            request["params"][0].as_str().unwrap_or_default().to_string()
+                        } else {
+                            method.clone()
+                        };
+                        (kind, subscribe.notification.clone())
+                    });
+                    match subscribe {
+                        Some((kind, notification)) => {
+                            state.next_subscription += 1;
+                            let id = format!("0x{:x}", state.next_subscription);
+                            subscriptions.insert(kind, (id.clone(), notification));
+                            json!({"jsonrpc": "2.0", "id": request["id"], "result": id})
+                        }
+                        None => state.respond(&request),
+                    }
+                };
+                if sink.send(Message::Text(response.to_string())).await.is_err() {
+                    return;
+                }
+            }
+
+            _ = ticker.tick() => {
+                let notification = {
+                    let mut state = state.lock().unwrap();
+                    let subscribed = state
+                        .notifications
+                        .front()
+                        .and_then(|(kind, _)| subscriptions.get(kind).cloned());
+                    match subscribed {
+                        Some((id, method)) => state
+                            .notifications
+                            .pop_front()
+                            .map(|(_, result)| json!({
+                                "jsonrpc": "2.0",
+                                "method": method,
+                                "params": {"subscription": id, "result": result},
+                            })),
+                        None => None,
+                    }
+                };
+                if let Some(notification) = notification {
+                    if sink.send(Message::Text(notification.to_string())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// hash of mock block `number`
+pub fn block_hash(number: u64) -> H256 {
+    H256::from_low_u64_be(number + 1)
+}
+
+fn block_json(number: u64) -> Value {
+    let block = Block::<TxHash> {
+        hash: Some(block_hash(number)),
+        parent_hash: block_hash(number.saturating_sub(1)),
+        number: Some(U64::from(number)),
+        ..Default::default()
+    };
+    serde_json::to_value(block).unwrap()
+}
+
+fn block_number_param(param: &Value) -> Option<u64> {
+    let param = param.as_str()?;
+    u64::from_str_radix(param.trim_start_matches("0x"), 16).ok()
+}
+
+/// Ethereum node serving a chain with head `head`, scripted logs and `eth_call` results.
+#[derive(Clone)]
+pub struct MockEthereum {
+    pub node: MockNode,
+    head: Arc<AtomicU64>,
+    logs: Arc<Mutex<Vec<Log>>>,
+    calls: Arc<Mutex<HashMap<Address, Bytes>>>,
+}
+
+impl MockEthereum {
+    pub async fn start(head: u64) -> Self {
+        let node = MockNode::start().await;
+        let mock = MockEthereum {
+            node,
+            head: Arc::new(AtomicU64::new(head)),
+            logs: Arc::new(Mutex::new(vec![])),
+            calls: Arc::new(Mutex::new(HashMap::new())),
+        };
+
+        mock.node.on_subscribe("eth_subscribe", "eth_subscription", true);
+        mock.node.on("eth_unsubscribe", |_| json!(true));
+        mock.node.on("eth_chainId", |_| json!("0x1"));
+        mock.node.on("net_version", |_| json!("1"));
+
+        let head = mock.head.clone();
+        mock.node.on("eth_blockNumber", move |_| {
+            json!(U64::from(head.load(Ordering::SeqCst)))
+        });
+
+        let head = mock.head.clone();
+        mock.node.on("eth_getBlockByNumber", move |params| {
+            let number = match params[0].as_str() {
+                Some("latest") | Some("finalized") | Some("safe") => {
+                    Some(head.load(Ordering::SeqCst))
+                }
+                _ => block_number_param(&params[0]),
+            };
+            match number {
+                Some(number) if number <= head.load(Ordering::SeqCst) => block_json(number),
+                _ => Value::Null,
+            }
+        });
+
+        let logs = mock.logs.clone();
+        mock.node.on("eth_getLogs", move |params| {
+            let from_block = block_number_param(&params[0]["fromBlock"]).unwrap_or(0);
+            let to_block = block_number_param(&params[0]["toBlock"]).unwrap_or(u64::MAX);
+            let logs: Vec<Log> = logs
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|log| {
+                    let number = log.block_number.unwrap_or_default().as_u64();
+                    from_block <= number && number <= to_block
+                })
+                .cloned()
+                .collect();
+            serde_json::to_value(logs).unwrap()
+        });
+
+        let calls = mock.calls.clone();
+        mock.node.on("eth_call", move |params| {
+            let to = params[0]["to"]
+                .as_str()
+                .and_then(|to| to.parse::<Address>().ok())
+                .unwrap_or_default();
+            match calls.lock().unwrap().get(&to) {
+                Some(result) => json!(result),
+                None => json!("0x"),
+            }
+        });
+
+        mock
+    }
+
+    /// log of `contract` with the mock topic in block `number`
+    pub fn log(contract: Address, number: u64, index: u64) -> Log {
+        Log {
+            address: contract,
+            topics: vec![Self::topic()],
+            data: Bytes::from(vec![0u8; 32]),
+            block_hash: Some(block_hash(number)),
+            block_number: Some(U64::from(number)),
+            transaction_hash: Some(H256::from_low_u64_be(number * 1000 + index)),
+            transaction_index: Some(U64::zero()),
+            log_index: Some(U256::from(index)),
+            transaction_log_index: None,
+            log_type: None,
+            removed: Some(false),
+        }
+    }
+
+    pub fn topic() -> H256 {
+        H256::from_low_u64_be(0xddf2)
+    }
+
+    /// log already in the chain, returned by `eth_getLogs`
+    pub fn add_log(&self, log: Log) {
+        self.logs.lock().unwrap().push(log);
+    }
+
+    /// sends log to subscribers of `logs`
+    pub fn push_log(&self, log: &Log) {
+        self.add_log(log.clone());
+        self.node.notify("logs", serde_json::to_value(log).unwrap());
+    }
+
+    /// sends log with `removed: true` to subscribers of `logs` as done on a reorg
+    pub fn push_removed_log(&self, log: &Log) {
+        self.logs.lock().unwrap().retain(|stored| stored != log);
+        let mut removed = log.clone();
+        removed.removed = Some(true);
+        self.node.notify("logs", serde_json::to_value(removed).unwrap());
+    }
+
+    /// moves the head and sends the block to subscribers of `newHeads`
+    pub fn new_head(&self, number: u64) {
+        self.head.fetch_max(number, Ordering::SeqCst);
+        self.node.notify("newHeads", block_json(number));
+    }
+
+    /// result of every `eth_call` made to `contract`
+    pub fn set_call(&self, contract: Address, result: Vec<u8>) {
+        self.calls
+            .lock()
+            .unwrap()
+            .insert(contract, Bytes::from(result));
+    }
+}
+
+/// Event of the `Balances` pallet of the runtime served by `MockSubstrate`.
+#[derive(Encode, TypeInfo)]
+pub enum BalancesEvent {
+    Transfer {
+        from: [u8; 32],
+        to: [u8; 32],
+        amount: u128,
+    },
+}
+
+/// Outer event enum of the mock runtime, variants are indexed by pallet index.
+#[derive(Encode, TypeInfo)]
+enum RuntimeEvent {
+    #[codec(index = 5)]
+    Balances(BalancesEvent),
+}
+
+/// Calls of the mock runtime, subxt needs the call type to accept the metadata.
+#[allow(unused)]
+#[derive(TypeInfo)]
+enum RuntimeCall {
+    Balances(BalancesCall),
+}
+
+#[allow(unused)]
+#[derive(TypeInfo)]
+enum BalancesCall {
+    Transfer,
+}
+
+type SubstrateBlockHeader = SubstrateHeader<u32, BlakeTwo256>;
+
+/// V15 metadata of a runtime with the `Balances` pallet at index 5.
+fn substrate_metadata() -> Vec<u8> {
+    let pallets = vec![PalletMetadata {
+        name: "Balances",
+        storage: None,
+        calls: None,
+        event: Some(PalletEventMetadata {
+            ty: meta_type::<BalancesEvent>(),
+        }),
+        constants: vec![],
+        error: None,
+        index: 5,
+        docs: vec![],
+    }];
+    let extrinsic = ExtrinsicMetadata {
+        version: 4,
+        signed_extensions: vec![],
+        address_ty: meta_type::<()>(),
+        call_ty: meta_type::<RuntimeCall>(),
+        signature_ty: meta_type::<()>(),
+        extra_ty: meta_type::<()>(),
+    };
+    let metadata = RuntimeMetadataV15::new(
+        pallets,
+        extrinsic,
+        meta_type::<()>(),
+        vec![],
+        OuterEnums {
+            call_enum_ty: meta_type::<RuntimeCall>(),
+            event_enum_ty: meta_type::<RuntimeEvent>(),
+            error_enum_ty: meta_type::<()>(),
+        },
+        CustomMetadata {
+            map: Default::default(),
+        },
+    );
+    RuntimeMetadataPrefixed::from(metadata).encode()
+}
+
+/// header of mock substrate block `number`, its hash is the hash subxt computes
+pub fn substrate_header(number: u32) -> SubstrateBlockHeader {
+    SubstrateHeader {
+        parent_hash: SubstrateHash::from_low_u64_be(u64::from(number)),
+        number,
+        state_root: SubstrateHash::zero(),
+        extrinsics_root: SubstrateHash::zero(),
+        digest: Digest::default(),
+    }
+}
+
+/// Substrate node finalizing blocks up to `finalized`, `System::Events` of
+/// every block are the scripted `Balances` events.
+#[derive(Clone)]
+pub struct MockSubstrate {
+    pub node: MockNode,
+    finalized: Arc<AtomicU64>,
+    /// encoded event records by block number
+    events: Arc<Mutex<HashMap<u32, Vec<Vec<u8>>>>>,
+}
+
+impl MockSubstrate {
+    pub async fn start(finalized: u32) -> Self {
+        let node = MockNode::start().await;
+        let mock = MockSubstrate {
+            node,
+            finalized: Arc::new(AtomicU64::new(u64::from(finalized))),
+            events: Arc::new(Mutex::new(HashMap::new())),
+        };
+
+        mock.node.on_subscribe(
+            "chain_subscribeFinalizedHeads",
+            "chain_finalizedHead",
+            false,
+        );
+        mock.node
+            .on("chain_unsubscribeFinalizedHeads", |_| json!(true));
+        mock.node.on_subscribe(
+            "state_subscribeRuntimeVersion",
+            "state_runtimeVersion",
+            false,
+        );
+        mock.node
+            .on("state_unsubscribeRuntimeVersion", |_| json!(true));
+        mock.node.on(
+            "state_getRuntimeVersion",
+            |_| json!({"specVersion": 1, "transactionVersion": 1}),
+        );
+
+        let metadata = substrate_metadata();
+        mock.node
+            .on("state_call", move |params| match params[0].as_str() {
+                Some("Metadata_metadata_at_version") => {
+                    json!(format!(
+                        "0x{}",
+                        hex::encode(Some(metadata.clone()).encode())
+                    ))
+                }
+                _ => Value::Null,
+            });
+
+        let finalized = mock.finalized.clone();
+        mock.node.on("chain_getBlockHash", move |params| {
+            let number = params[0]
+                .as_u64()
+                .or_else(|| block_number_param(&params[0]))
+                .unwrap_or(0);
+            if number <= finalized.load(Ordering::SeqCst) {
+                json!(substrate_header(number as u32).hash())
+            } else {
+                Value::Null
+            }
+        });
+
+        let finalized = mock.finalized.clone();
+        mock.node.on("chain_getFinalizedHead", move |_| {
+            json!(substrate_header(finalized.load(Ordering::SeqCst) as u32).hash())
+        });
+
+        let finalized = mock.finalized.clone();
+        mock.node.on("chain_getHeader", move |params| {
+            match block_of_hash(&params[0], finalized.load(Ordering::SeqCst)) {
+                Some(number) => serde_json::to_value(substrate_header(number)).unwrap(),
+                None => Value::Null,
+            }
+        });
+
+        // only `System::Events` is read, so the storage key is not checked
+        let finalized = mock.finalized.clone();
+        let events = mock.events.clone();
+        mock.node.on("state_getStorage", move |params| {
+            let records = block_of_hash(&params[1], finalized.load(Ordering::SeqCst))
+                .and_then(|number| events.lock().unwrap().get(&number).cloned())
+                .unwrap_or_default();
+            let mut storage = Compact(records.len() as u32).encode();
+            storage.extend(records.concat());
+            json!(format!("0x{}", hex::encode(storage)))
+        });
+
+        mock
+    }
+
+    /// event emitted by extrinsic `extrinsic` of block `number`
+    pub fn add_event(&self, number: u32, extrinsic: u32, event: BalancesEvent) {
+        let record = (
+            Phase::ApplyExtrinsic(extrinsic),
+            RuntimeEvent::Balances(event),
+            Vec::<SubstrateHash>::new(),
+        );
+        self.events
+            .lock()
+            .unwrap()
+            .entry(number)
+            .or_default()
+            .push(record.encode());
+    }
+
+    /// finalizes blocks up to `number` and sends its header to subscribers of finalized heads
+    pub fn finalize(&self, number: u32) {
+        self.finalized
+            .fetch_max(u64::from(number), Ordering::SeqCst);
+        self.node.notify(
+            "chain_subscribeFinalizedHeads",
+            serde_json::to_value(substrate_header(number)).unwrap(),
+        );
+    }
+}
+
+/// number of the finalized block with hash `param`
+fn block_of_hash(param: &Value, finalized: u64) -> Option<u32> {
+    let hash = serde_json::from_value::<SubstrateHash>(param.clone()).ok()?;
+    (0..=finalized as u32).find(|number| substrate_header(*number).hash() == hash)
+}
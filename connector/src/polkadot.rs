@@ -1,47 +1,422 @@
+use crate::chain_connector::{send_event, ChainConnector, ConnectorContext, ConnectorHealth};
+use async_trait::async_trait;
+use database::models::ConnectorCursor;
+use database::Repo;
+use futures::StreamExt;
 use serde_json::json;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use subxt::blocks::Block;
 use subxt::{OnlineClient, PolkadotConfig};
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
-#[subxt::subxt(runtime_metadata_path = "metadata.scale")]
-pub mod substrate {}
+/// Endpoint of a local substrate node.
+pub const DEFAULT_SUBSTRATE_ENDPOINT: &str = "ws://127.0.0.1:9944";
 
+/// Wait before subscribing again after the finalized block subscription ended.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Message sent to tss for a runtime event, `fields` are the decoded fields of the event.
+pub fn substrate_event_msg(
+    chain: &str,
+    pallet: &str,
+    variant: &str,
+    block_number: u32,
+    block_hash: &str,
+    event_index: u32,
+    fields: serde_json::Value,
+) -> String {
+    json!({
+        "event_type": PolkadotConnector::EVENT_TYPE,
+        "chain": chain,
+        "pallet": pallet,
+        "variant": variant,
+        "block_number": block_number,
+        "block_hash": block_hash,
+        "event_index": event_index,
+        "fields": fields,
+    })
+    .to_string()
+}
+
+/// Runtime event sent to tss, `pallet::variant` as configured with `--substrate-events`.
+/// `variant` of `None` selects every event of the pallet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventFilter {
+    pub pallet: String,
+    pub variant: Option<String>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, pallet: &str, variant: &str) -> bool {
+        self.pallet == pallet && self.variant.as_deref().map_or(true, |v| v == variant)
+    }
+}
+
+impl FromStr for EventFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(2, "::");
+        let pallet = match parts.next() {
+            Some(pallet) if !pallet.is_empty() => pallet.to_string(),
+            _ => return Err(format!("Invalid event filter {}", s)),
+        };
+        let variant = match parts.next() {
+            None | Some("*") => None,
+            Some(variant) if !variant.is_empty() => Some(variant.to_string()),
+            Some(_) => return Err(format!("Invalid event filter {}", s)),
+        };
+        Ok(EventFilter { pallet, variant })
+    }
+}
+
+/// Follows one substrate chain. Events are decoded with the metadata of the
+/// chain fetched at runtime, so no runtime has to be compiled into the node.
 #[derive(Clone)]
 pub struct Polkadot {
+    pub endpoint: String,
+    pub connection: Arc<dyn Repo>,
+    pub events: Vec<EventFilter>,
     pub sender: mpsc::Sender<String>,
 }
 
 impl Polkadot {
     /// create a new event instance
-    pub fn new(sender: mpsc::Sender<String>) -> Self {
-        Polkadot { sender: sender }
+    pub fn new(
+        endpoint: &str,
+        connection: Arc<dyn Repo>,
+        events: Vec<EventFilter>,
+        sender: mpsc::Sender<String>,
+    ) -> Self {
+        Polkadot {
+            endpoint: endpoint.to_string(),
+            connection,
+            events,
+            sender: sender,
+        }
+    }
+
+    /// Keeps following finalized blocks, every reconnect resumes from the stored cursor.
+    pub async fn run(&self) {
+        loop {
+            match self.follow_finalized().await {
+                Ok(()) => log::warn!(
+                    "Polkadot finalized block subscription of {} ended",
+                    self.endpoint
+                ),
+                Err(e) => log::error!("Polkadot connector of {} stopped: {:?}", self.endpoint, e),
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    /// Sends configured events of every finalized block to tss.
+    pub async fn follow_finalized(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Create a client to fetch the onchain data from polkadot, it downloads the metadata.
+        let api = OnlineClient::<PolkadotConfig>::from_url(&self.endpoint).await?;
+
+        // metadata is replaced when the runtime of the chain is upgraded
+        let updater = api.updater();
+        let runtime_updates = tokio::spawn(async move {
+            if let Err(e) = updater.perform_runtime_updates().await {
+                log::error!("Polkadot runtime update failed: {:?}", e);
+            }
+        });
+
+        let result = self.follow_blocks(&api).await;
+        runtime_updates.abort();
+        result
     }
 
-    /// Fetch the accounts data from the substrate chain.
-    pub async fn get_accounts(&self) -> Result<(), Box<dyn std::error::Error>> {
-        // Create a client to fetch the onchain data from polkadot.
-        let api = OnlineClient::<PolkadotConfig>::new().await?;
+    async fn follow_blocks(
+        &self,
+        api: &OnlineClient<PolkadotConfig>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut cursor = self
+            .connection
+            .get_connector_cursor(&self.endpoint, &self.cursor_topic())
+            .await?
+            .map(|cursor| cursor.block_number);
 
-        let address = substrate::storage().system().account_iter();
+        let mut blocks = api.blocks().subscribe_finalized().await?;
+        while let Some(block) = blocks.next().await {
+            let block = block?;
+            let number = block.header().number;
 
-        let mut iter =  api.storage().at_latest().await?.iter(address).await?;
+            // blocks finalized while the node was offline or between two notifications
+            if let Some(last) = cursor {
+                for missed in (last + 1)..i64::from(number) {
+                    let hash = api
+                        .rpc()
+                        .block_hash(Some(u32::try_from(missed)?.into()))
+                        .await?
+                        .ok_or("missed finalized block not found")?;
+                    let missed_block = api.blocks().at(hash).await?;
+                    self.process_block(&missed_block).await?;
+                }
+                if i64::from(number) <= last {
+                    continue;
+                }
+            }
 
-        while let Some(Ok((key, account))) = iter.next().await {
-            log::info!("{}: {}", hex::encode(key.clone()), account.data.free);
+            self.process_block(&block).await?;
+            cursor = Some(i64::from(number));
+        }
 
-            let json_data = json!({
-                "account": hex::encode(key),
-                "balance": account.data.free.to_string()
-            });
+        Ok(())
+    }
 
-            let serialized_json_data = serde_json::to_string(&json_data)?;
+    async fn process_block(
+        &self,
+        block: &Block<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let number = block.header().number;
+        let block_hash = format!("0x{}", hex::encode(block.hash()));
 
-            //Sending the event to TSS channel.
-            match self.sender.send(serialized_json_data).await {
-                Ok(()) => log::info!("Connector successfully send swap event to channel"),
-                Err(e) => log::info!("Connector failed to send swap event to channel: {:?}", e),
+        let events = block.events().await?;
+        for event in events.iter() {
+            let event = event?;
+            if !self
+                .events
+                .iter()
+                .any(|filter| filter.matches(event.pallet_name(), event.variant_name()))
+            {
+                continue;
             }
+
+            let serialized_json_data = substrate_event_msg(
+                &self.endpoint,
+                event.pallet_name(),
+                event.variant_name(),
+                number,
+                &block_hash,
+                event.index(),
+                serde_json::to_value(event.field_values()?)?,
+            );
+
+            //Sending the event to TSS channel, the cursor only moves past sent events.
+            send_event(&self.sender, serialized_json_data).await?;
+            log::info!("Connector successfully send substrate event to channel");
+        }
+
+        // Updating the cursor in the db, every event up to this block was sent.
+        self.connection
+            .upsert_connector_cursor(ConnectorCursor::new(
+                self.endpoint.clone(),
+                self.cursor_topic(),
+                i64::from(number),
+            ))
+            .await?;
+
+        Ok(())
+    }
+
+    /// cursor is kept per chain and set of filtered events
+    fn cursor_topic(&self) -> String {
+        let filters: Vec<String> = self
+            .events
+            .iter()
+            .map(|filter| match &filter.variant {
+                Some(variant) => format!("{}::{}", filter.pallet, variant),
+                None => format!("{}::*", filter.pallet),
+            })
+            .collect();
+        filters.join(",")
+    }
+}
+
+/// Runtime events of finalized substrate blocks as a `ChainConnector`,
+/// one `Polkadot` instance runs for every configured endpoint.
+pub struct PolkadotConnector {
+    pub chains: Vec<Polkadot>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl PolkadotConnector {
+    pub const NAME: &'static str = "polkadot";
+    pub const EVENT_TYPE: &'static str = "substrate_event";
+
+    pub fn new(ctx: &ConnectorContext) -> Self {
+        let chains = ctx
+            .substrate_endpoints
+            .iter()
+            .map(|endpoint| {
+                Polkadot::new(
+                    endpoint,
+                    ctx.connection.clone(),
+                    ctx.substrate_events.clone(),
+                    ctx.sender.clone(),
+                )
+            })
+            .collect();
+        PolkadotConnector {
+            chains,
+            handles: vec![],
         }
+    }
+}
+
+#[async_trait]
+impl ChainConnector for PolkadotConnector {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn event_type(&self) -> &'static str {
+        Self::EVENT_TYPE
+    }
 
+    fn start(&mut self) -> eyre::Result<()> {
+        if self.chains.is_empty() {
+            return Err(eyre::eyre!("No substrate endpoints configured"));
+        }
+        for polkadot in self.chains.iter() {
+            if polkadot.events.is_empty() {
+                return Err(eyre::eyre!("No substrate events configured"));
+            }
+            let polkadot = polkadot.clone();
+            self.handles
+                .push(tokio::spawn(async move { polkadot.run().await }));
+        }
         Ok(())
     }
-}
\ No newline at end of file
+
+    fn stop(&mut self) {
+        for handle in self.handles.drain(..) {
+            handle.abort();
+        }
+    }
+
+    /// running while the task of any chain is running
+    fn health(&self) -> ConnectorHealth {
+        if self.handles.is_empty() {
+            ConnectorHealth::Stopped
+        } else if self.handles.iter().all(|handle| handle.is_finished()) {
+            ConnectorHealth::Finished
+        } else {
+            ConnectorHealth::Running
+        }
+    }
+
+    /// last finalized block whose events were sent, per chain
+    async fn cursor(&self) -> Option<String> {
+        let mut cursors = vec![];
+        for polkadot in self.chains.iter() {
+            let cursor = polkadot
+                .connection
+                .get_connector_cursor(&polkadot.endpoint, &polkadot.cursor_topic())
+                .await
+                .ok()
+                .flatten()?;
+            cursors.push(format!("{}={}", polkadot.endpoint, cursor.block_number));
+        }
+        Some(cursors.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{BalancesEvent, MockNode, MockSubstrate};
+    use database::MemoryRepo;
+    use serde_json::Value;
+
+    #[test]
+    fn parses_event_filters() {
+        let transfer = EventFilter::from_str("Balances::Transfer").unwrap();
+        assert!(transfer.matches("Balances", "Transfer"));
+        assert!(!transfer.matches("Balances", "Deposit"));
+
+        let assets = EventFilter::from_str("Assets::*").unwrap();
+        assert_eq!(assets, EventFilter::from_str("Assets").unwrap());
+        assert!(assets.matches("Assets", "Issued"));
+        assert!(!assets.matches("Balances", "Issued"));
+
+        assert!(EventFilter::from_str("::Transfer").is_err());
+        assert!(EventFilter::from_str("Balances::").is_err());
+    }
+
+    #[tokio::test]
+    async fn stops_without_panic_when_chain_serves_no_metadata() {
+        let node = MockNode::start().await;
+        node.on("chain_getBlockHash", |_| {
+            Value::String(format!("0x{}", "11".repeat(32)))
+        });
+        node.on("state_getRuntimeVersion", |_| {
+            serde_json::json!({"specVersion": 1, "transactionVersion": 1})
+        });
+
+        let (sender, mut receiver) = mpsc::channel(10);
+        let polkadot = Polkadot::new(
+            &node.ws_url(),
+            Arc::new(MemoryRepo::new()),
+            vec![EventFilter::from_str("Balances::Transfer").unwrap()],
+            sender,
+        );
+
+        assert!(polkadot.follow_finalized().await.is_err());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn sends_decoded_events_of_finalized_blocks() {
+        let node = MockSubstrate::start(1).await;
+        node.add_event(
+            2,
+            1,
+            BalancesEvent::Transfer {
+                from: [1; 32],
+                to: [2; 32],
+                amount: 500,
+            },
+        );
+
+        let connection = Arc::new(MemoryRepo::new());
+        let (sender, mut receiver) = mpsc::channel(10);
+        let polkadot = Polkadot::new(
+            &node.node.ws_url(),
+            connection.clone(),
+            vec![EventFilter::from_str("Balances::Transfer").unwrap()],
+            sender,
+        );
+        let topic = polkadot.cursor_topic();
+        // block 2 is only fetched as missed block between the cursor and the notified block 3
+        connection
+            .upsert_connector_cursor(ConnectorCursor::new(node.node.ws_url(), topic.clone(), 1))
+            .await
+            .unwrap();
+        let handle = tokio::spawn(async move { polkadot.follow_finalized().await });
+        node.finalize(3);
+
+        let msg = tokio::time::timeout(Duration::from_secs(10), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let msg: Value = serde_json::from_str(&msg).unwrap();
+        assert_eq!(msg["event_type"], "substrate_event");
+        assert_eq!(msg["pallet"], "Balances");
+        assert_eq!(msg["variant"], "Transfer");
+        assert_eq!(msg["block_number"], 2);
+        assert_eq!(msg["event_index"], 0);
+        assert_eq!(msg["fields"]["amount"], serde_json::json!(500));
+        assert!(msg["fields"]["from"].is_array());
+
+        let mut cursor = None;
+        for _ in 0..50 {
+            cursor = connection
+                .get_connector_cursor(&node.node.ws_url(), &topic)
+                .await
+                .unwrap();
+            if cursor.as_ref().map(|cursor| cursor.block_number) == Some(3) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(cursor.unwrap().block_number, 3);
+        assert!(receiver.try_recv().is_err());
+        handle.abort();
+    }
+}
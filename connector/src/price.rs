@@ -0,0 +1,211 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use web3::types::U256;
+
+/// Sources further than this from the median are rejected, in basis points.
+pub const MAX_DEVIATION_BPS: u64 = 200;
+
+/// Prices observed within this window make up the TWAP of a pair.
+pub const TWAP_WINDOW: Duration = Duration::from_secs(300);
+
+/// Price of a pair reported by one exchange.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PriceSource {
+    pub exchange: String,
+    pub exchange_address: String,
+    #[serde(serialize_with = "serialize_u256")]
+    pub swap_price: U256,
+    /// within `MAX_DEVIATION_BPS` of the median of all sources
+    pub accepted: bool,
+}
+
+/// Price of a pair agreed on by the sources of one round.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggregatedPrice {
+    /// median of the accepted sources
+    pub price: U256,
+    pub sources: Vec<PriceSource>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PriceError {
+    #[error("no price source responded")]
+    NoSources,
+    #[error("only {accepted} of {total} price sources agree")]
+    NoAgreement { accepted: usize, total: usize },
+}
+
+fn serialize_u256<S: serde::Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.to_string())
+}
+
+/// median of prices, the lower middle value for an even count
+fn median(prices: &mut [U256]) -> Option<U256> {
+    prices.sort();
+    if prices.is_empty() {
+        return None;
+    }
+    Some(prices[(prices.len() - 1) / 2])
+}
+
+/// Rejects sources deviating more than `max_deviation_bps` from the median of all
+/// sources and returns the median of the rest. A majority of sources has to be accepted,
+/// so a single manipulated pool can neither move the price nor be the only source left.
+pub fn aggregate_prices(
+    mut sources: Vec<PriceSource>,
+    max_deviation_bps: u64,
+) -> Result<AggregatedPrice, PriceError> {
+    let mut prices: Vec<U256> = sources.iter().map(|source| source.swap_price).collect();
+    let reference = median(&mut prices).ok_or(PriceError::NoSources)?;
+
+    let max_deviation = reference * U256::from(max_deviation_bps) / U256::from(10_000u64);
+    for source in sources.iter_mut() {
+        let deviation = if source.swap_price > reference {
+            source.swap_price - reference
+        } else {
+            reference - source.swap_price
+        };
+        source.accepted = deviation <= max_deviation;
+    }
+
+    let mut accepted: Vec<U256> = sources
+        .iter()
+        .filter(|source| source.accepted)
+        .map(|source| source.swap_price)
+        .collect();
+    if accepted.len() * 2 <= sources.len() {
+        return Err(PriceError::NoAgreement {
+            accepted: accepted.len(),
+            total: sources.len(),
+        });
+    }
+
+    Ok(AggregatedPrice {
+        price: median(&mut accepted).ok_or(PriceError::NoSources)?,
+        sources,
+    })
+}
+
+/// Aggregated prices of a pair over the last `TWAP_WINDOW`.
+#[derive(Debug, Clone, Default)]
+pub struct PriceHistory {
+    observations: VecDeque<(Instant, U256)>,
+}
+
+impl PriceHistory {
+    pub fn record(&mut self, at: Instant, price: U256) {
+        self.observations.push_back((at, price));
+        while let Some((observed, _)) = self.observations.front() {
+            if at.duration_since(*observed) > TWAP_WINDOW {
+                self.observations.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// time weighted average price until `now`, each price counts for the time until the next one
+    pub fn twap(&self, now: Instant) -> Option<U256> {
+        let (_, last_price) = *self.observations.back()?;
+
+        let mut weighted_sum = U256::zero();
+        let mut total_weight = U256::zero();
+        let next_times = self
+            .observations
+            .iter()
+            .skip(1)
+            .map(|(at, _)| *at)
+            .chain(std::iter::once(now));
+        for ((at, price), until) in self.observations.iter().zip(next_times) {
+            let weight = U256::from(until.saturating_duration_since(*at).as_millis() as u64);
+            weighted_sum = weighted_sum.saturating_add(price.saturating_mul(weight));
+            total_weight += weight;
+        }
+
+        if total_weight.is_zero() {
+            return Some(last_price);
+        }
+        Some(weighted_sum / total_weight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(exchange: &str, price: u64) -> PriceSource {
+        PriceSource {
+            exchange: exchange.into(),
+            exchange_address: exchange.into(),
+            swap_price: U256::from(price),
+            accepted: false,
+        }
+    }
+
+    #[test]
+    fn rejects_manipulated_source() {
+        let aggregated = aggregate_prices(
+            vec![
+                source("uniswap", 1700),
+                source("sushiswap", 1702),
+                source("manipulated", 2500),
+            ],
+            MAX_DEVIATION_BPS,
+        )
+        .unwrap();
+
+        assert_eq!(aggregated.price, U256::from(1700));
+        let rejected: Vec<&str> = aggregated
+            .sources
+            .iter()
+            .filter(|source| !source.accepted)
+            .map(|source| source.exchange.as_str())
+            .collect();
+        assert_eq!(rejected, vec!["manipulated"]);
+    }
+
+    #[test]
+    fn requires_majority_of_sources() {
+        assert_eq!(
+            aggregate_prices(
+                vec![source("a", 1000), source("b", 2000)],
+                MAX_DEVIATION_BPS
+            ),
+            Err(PriceError::NoAgreement {
+                accepted: 1,
+                total: 2
+            })
+        );
+        assert_eq!(
+            aggregate_prices(vec![], MAX_DEVIATION_BPS),
+            Err(PriceError::NoSources)
+        );
+        assert_eq!(
+            aggregate_prices(vec![source("a", 1000)], MAX_DEVIATION_BPS)
+                .unwrap()
+                .price,
+            U256::from(1000)
+        );
+    }
+
+    #[test]
+    fn twap_weights_prices_by_time() {
+        let start = Instant::now();
+        let mut history = PriceHistory::default();
+        history.record(start, U256::from(1000));
+        history.record(start + Duration::from_secs(30), U256::from(2000));
+
+        // 1000 for 30s and 2000 for 10s
+        assert_eq!(
+            history.twap(start + Duration::from_secs(40)),
+            Some(U256::from(1250))
+        );
+
+        history.record(
+            start + TWAP_WINDOW + Duration::from_secs(60),
+            U256::from(3000),
+        );
+        assert_eq!(history.observations.len(), 1);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::chain_connector::{task_health, ChainConnector, ConnectorContext, ConnectorHealth};
+use crate::event_listener::Connector;
+use async_trait::async_trait;
+use database::Repo;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+
+/// Runs one log listener per record of the `contracts` collection.
+pub struct ContractSupervisor {
+    pub connection: Arc<dyn Repo>,
+    pub confirmations: u64,
+    pub sender: mpsc::Sender<String>,
+    listeners: HashMap<String, JoinHandle<()>>,
+}
+
+impl ContractSupervisor {
+    pub fn new(
+        connection: Arc<dyn Repo>,
+        confirmations: u64,
+        sender: mpsc::Sender<String>,
+    ) -> Self {
+        ContractSupervisor {
+            connection,
+            confirmations,
+            sender,
+            listeners: HashMap::new(),
+        }
+    }
+
+    /// Starts listeners of all stored contracts, then reloads the contracts
+    /// every time the server reports that a contract was added or removed.
+    pub async fn run(&mut self, contracts_changed: Arc<Notify>) {
+        loop {
+            self.reconcile().await;
+            contracts_changed.notified().await;
+        }
+    }
+
+    /// Stops listeners of removed contracts and starts listeners of new ones.
+    pub async fn reconcile(&mut self) {
+        let contracts = match self.connection.get_contract_json().await {
+            Ok(contracts) => contracts,
+            Err(e) => {
+                log::error!("Supervisor unable to load contracts: {:?}", e);
+                return;
+            }
+        };
+
+        let ids: HashSet<String> = contracts
+            .iter()
+            .filter_map(|contract| contract.id.map(|id| id.to_hex()))
+            .collect();
+
+        self.listeners.retain(|id, handle| {
+            if ids.contains(id) {
+                true
+            } else {
+                log::info!("Supervisor stopping listener of contract {}", id);
+                handle.abort();
+                false
+            }
+        });
+
+        for contract in contracts {
+            let id = match contract.id {
+                Some(id) => id.to_hex(),
+                None => continue,
+            };
+            if self.listeners.contains_key(&id) {
+                continue;
+            }
+
+            log::info!(
+                "Supervisor starting listener of contract {} at {}",
+                id,
+                contract.contract_address
+            );
+            let listener = Connector::new(
+                &contract.chain_endpoint,
+                &contract.contract_address,
+                &contract.event_type,
+                self.confirmations,
+                self.connection.clone(),
+                self.sender.clone(),
+            );
+            let handle = tokio::spawn(async move { listener.run().await });
+            self.listeners.insert(id, handle);
+        }
+    }
+}
+
+impl Drop for ContractSupervisor {
+    fn drop(&mut self) {
+        for (_, handle) in self.listeners.drain() {
+            handle.abort();
+        }
+    }
+}
+
+/// Ethereum contract logs as a `ChainConnector`, runs a `ContractSupervisor`.
+pub struct ContractsConnector {
+    pub connection: Arc<dyn Repo>,
+    pub confirmations: u64,
+    pub sender: mpsc::Sender<String>,
+    pub contracts_changed: Arc<Notify>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ContractsConnector {
+    pub const NAME: &'static str = "ethereum";
+
+    pub fn new(ctx: &ConnectorContext) -> Self {
+        ContractsConnector {
+            connection: ctx.connection.clone(),
+            confirmations: ctx.eth_confirmations,
+            sender: ctx.sender.clone(),
+            contracts_changed: ctx.contracts_changed.clone(),
+            handle: None,
+        }
+    }
+}
+
+#[async_trait]
+impl ChainConnector for ContractsConnector {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn event_type(&self) -> &'static str {
+        "log"
+    }
+
+    fn start(&mut self) -> eyre::Result<()> {
+        let mut supervisor = ContractSupervisor::new(
+            self.connection.clone(),
+            self.confirmations,
+            self.sender.clone(),
+        );
+        let contracts_changed = self.contracts_changed.clone();
+        // aborting the task drops the supervisor which stops all listeners
+        self.handle = Some(tokio::spawn(async move {
+            supervisor.run(contracts_changed).await
+        }));
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+        }
+    }
+
+    fn health(&self) -> ConnectorHealth {
+        task_health(&self.handle)
+    }
+
+    /// last block whose logs were sent of every contract topic
+    async fn cursor(&self) -> Option<String> {
+        let cursors = self.connection.get_connector_cursors().await.ok()?;
+        let cursors: Vec<String> = cursors
+            .iter()
+            .map(|cursor| {
+                format!(
+                    "{}:{}={}",
+                    cursor.source, cursor.topic, cursor.block_number
+                )
+            })
+            .collect();
+        Some(cursors.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockEthereum;
+    use database::models::ContractJson;
+    use database::MemoryRepo;
+    use ethers::types::Address;
+
+    //tests that a listener runs for every stored contract and stops once the contract is removed
+    #[tokio::test]
+    async fn follows_contracts_collection() {
+        let node = MockEthereum::start(10).await;
+        let connection = Arc::new(MemoryRepo::new());
+        let id = connection.insert_contract(ContractJson::new(
+            format!("{:?}", Address::random()),
+            format!("{:?}", MockEthereum::topic()),
+            node.node.ws_url(),
+        ));
+
+        let (sender, _receiver) = mpsc::channel(10);
+        let mut supervisor = ContractSupervisor::new(connection.clone(), 0, sender);
+        supervisor.reconcile().await;
+        assert!(!supervisor.listeners[&id].is_finished());
+
+        // nothing changed, the running listener is kept
+        supervisor.reconcile().await;
+        assert!(!supervisor.listeners[&id].is_finished());
+
+        connection.delete_contract(&id);
+        supervisor.reconcile().await;
+        assert!(!supervisor.listeners.contains_key(&id));
+    }
+
+    #[tokio::test]
+    async fn stops_supervisor_with_connector() {
+        let (sender, _receiver) = mpsc::channel(10);
+        let mut connector = ContractsConnector::new(&ConnectorContext {
+            connection: Arc::new(MemoryRepo::new()),
+            sender,
+            eth_confirmations: 0,
+            substrate_endpoints: vec![],
+            substrate_events: vec![],
+            contracts_changed: Arc::new(Notify::new()),
+        });
+        assert_eq!(connector.health(), ConnectorHealth::Stopped);
+
+        connector.start().unwrap();
+        assert_eq!(connector.health(), ConnectorHealth::Running);
+        connector.contracts_changed.notify_one();
+        tokio::task::yield_now().await;
+        assert_eq!(connector.health(), ConnectorHealth::Running);
+
+        connector.stop();
+        assert_eq!(connector.health(), ConnectorHealth::Stopped);
+    }
+}
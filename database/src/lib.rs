@@ -1,20 +1,55 @@
+use async_trait::async_trait;
 use mongodb::{
-    bson::{doc, oid::ObjectId},
-    options::FindOptions,
-    results::DeleteResult,
+    bson::{doc, oid::ObjectId, to_bson, Document},
+    options::{FindOptions, IndexOptions, UpdateOptions},
+    results::{DeleteResult, UpdateResult},
 };
+#[cfg(any(test, feature = "test-support"))]
+pub mod memory;
 pub mod models;
 
-use crate::models::{ContractJson, EventsModel, SwapEvent, Token, TokenSwap};
+use crate::models::{
+    ConnectorCursor, ContractJson, EventsModel, KeyRotationModel, PendingEvent, SwapEvent, Token,
+    TokenSwap,
+};
 use mongodb::{
     bson::extjson::de::Error,
     results::{InsertManyResult, InsertOneResult},
-    Client, Collection,
+    Client, Collection, IndexModel,
 };
 
 use futures::stream::StreamExt;
+use serde::de::Error as _;
+use serde::ser::Error as _;
+use serde_json::Value;
 // use futures::stream::{StreamExt, TryStreamExt};
 
+/// Database of the node.
+pub const DATABASE: &str = "tango_db";
+
+/// Collections of the node in the order `init` takes them.
+pub const COLLECTIONS: [&str; 8] = [
+    "events",
+    "contracts",
+    "token_swap",
+    "swap_events",
+    "tokens",
+    "connector_cursors",
+    "pending_events",
+    "key_rotations",
+];
+
+#[cfg(any(test, feature = "test-support"))]
+pub use crate::memory::MemoryRepo;
+
+/// Database the tests of the workspace run against.
+#[cfg(any(test, feature = "test-support"))]
+pub const TEST_DB_URL: &str = "mongodb://localhost:27017/admin";
+
+/// Fields of an event document written by `upsert_event_attestation`, events can't use them.
+pub const ATTESTATION_FIELDS: [&str; 4] =
+    ["msg_hash", "signed_event", "threshold_signature", "signers"];
+
 #[derive(Clone)]
 pub struct MongoRepo {
     events: Collection<EventsModel>,
@@ -22,6 +57,9 @@ pub struct MongoRepo {
     token_swap: Collection<TokenSwap>,
     contracts: Collection<ContractJson>,
     tokens: Collection<Token>,
+    connector_cursors: Collection<ConnectorCursor>,
+    pending_events: Collection<PendingEvent>,
+    key_rotations: Collection<KeyRotationModel>,
 }
 
 impl MongoRepo {
@@ -33,15 +71,113 @@ impl MongoRepo {
         let token_swap: Collection<TokenSwap> = db.collection::<TokenSwap>(&collection[2]);
         let swap_event: Collection<SwapEvent> = db.collection::<SwapEvent>(&collection[3]);
         let tokens: Collection<Token> = db.collection::<Token>(&collection[4]);
+        let connector_cursors: Collection<ConnectorCursor> =
+            db.collection::<ConnectorCursor>(&collection[5]);
+        let pending_events: Collection<PendingEvent> =
+            db.collection::<PendingEvent>(&collection[6]);
+        let key_rotations: Collection<KeyRotationModel> =
+            db.collection::<KeyRotationModel>(&collection[7]);
         MongoRepo {
             events,
             contracts,
             token_swap,
             swap_event,
             tokens,
+            connector_cursors,
+            pending_events,
+            key_rotations,
         }
     }
 
+    /// Connects to the `COLLECTIONS` of `DATABASE`.
+    pub async fn connect(uri: &str) -> Self {
+        Self::init(uri, DATABASE, COLLECTIONS.to_vec()).await
+    }
+
+    /// Repo of the test database, shared by the tests of all crates.
+    #[cfg(any(test, feature = "test-support"))]
+    pub async fn connect_test() -> Self {
+        Self::connect(TEST_DB_URL).await
+    }
+
+    /// Creates the indexes the node relies on, existing indexes are left as they are.
+    /// Returns the names of the indexes.
+    pub async fn migrate(collection: &Self) -> Result<Vec<String>, mongodb::error::Error> {
+        let mut indexes = vec![];
+
+        // nodes upsert the attestation of an event concurrently, documents stored
+        // before events had a msg_hash are not covered
+        let event_msg_hash = IndexModel::builder()
+            .keys(doc! {"data.msg_hash": 1})
+            .options(
+                IndexOptions::builder()
+                    .name("event_msg_hash".to_string())
+                    .unique(true)
+                    .partial_filter_expression(doc! {"data.msg_hash": {"$exists": true}})
+                    .build(),
+            )
+            .build();
+        indexes.push(
+            collection
+                .events
+                .create_index(event_msg_hash, None)
+                .await?
+                .index_name,
+        );
+
+        // cursors were keyed by contract_address before substrate chains kept one
+        collection
+            .connector_cursors
+            .update_many(
+                doc! {"contract_address": {"$exists": true}},
+                doc! {"$rename": {"contract_address": "source"}},
+                None,
+            )
+            .await?;
+        // only databases migrated before have the index of the old key
+        let _ = collection
+            .connector_cursors
+            .drop_index("connector_cursor", None)
+            .await;
+
+        let connector_cursor = IndexModel::builder()
+            .keys(doc! {"source": 1, "topic": 1})
+            .options(
+                IndexOptions::builder()
+                    .name("connector_cursor_source".to_string())
+                    .unique(true)
+                    .build(),
+            )
+            .build();
+        indexes.push(
+            collection
+                .connector_cursors
+                .create_index(connector_cursor, None)
+                .await?
+                .index_name,
+        );
+
+        // a key takes over an account once
+        let key_rotation = IndexModel::builder()
+            .keys(doc! {"new_key": 1})
+            .options(
+                IndexOptions::builder()
+                    .name("key_rotation".to_string())
+                    .unique(true)
+                    .build(),
+            )
+            .build();
+        indexes.push(
+            collection
+                .key_rotations
+                .create_index(key_rotation, None)
+                .await?
+                .index_name,
+        );
+
+        Ok(indexes)
+    }
+
     pub async fn insert_event(
         collection: &Self,
         event: EventsModel,
@@ -49,6 +185,55 @@ impl MongoRepo {
         collection.events.insert_one(event, None).await
     }
 
+    /// Stores the attestation of an event, one document per event identified by `msg_hash`.
+    /// Event fields and the exact `signed_event` string are only written when the document
+    /// is created, the threshold signature is set and each individual signer is added to
+    /// the `signers` array of the document. Events with one of the `ATTESTATION_FIELDS` are
+    /// rejected. Relies on the unique index of `migrate` so nodes upserting at the same time
+    /// end up in one document.
+    pub async fn upsert_event_attestation(
+        collection: &Self,
+        msg_hash: String,
+        signed_event: &str,
+        threshold_signature: Option<Value>,
+        signer: Option<Value>,
+    ) -> Result<UpdateResult, mongodb::error::Error> {
+        let event = serde_json::from_str::<Value>(signed_event)
+            .map_err(|e| mongodb::bson::ser::Error::custom(format!("invalid event: {}", e)))?;
+        let mut set_on_insert = Document::new();
+        if let Value::Object(fields) = event {
+            for (key, value) in fields {
+                if ATTESTATION_FIELDS.contains(&key.as_str()) {
+                    return Err(mongodb::bson::ser::Error::custom(format!(
+                        "event field {} is reserved for the attestation",
+                        key
+                    ))
+                    .into());
+                }
+                set_on_insert.insert(format!("data.{}", key), to_bson(&value)?);
+            }
+        }
+        set_on_insert.insert("data.msg_hash", msg_hash.clone());
+        set_on_insert.insert("data.signed_event", signed_event);
+
+        let mut update = doc! { "$setOnInsert": set_on_insert };
+        if let Some(threshold_signature) = threshold_signature {
+            update.insert(
+                "$set",
+                doc! { "data.threshold_signature": to_bson(&threshold_signature)? },
+            );
+        }
+        if let Some(signer) = signer {
+            update.insert("$addToSet", doc! { "data.signers": to_bson(&signer)? });
+        }
+
+        let options = UpdateOptions::builder().upsert(true).build();
+        collection
+            .events
+            .update_one(doc! {"data.msg_hash": msg_hash}, update, options)
+            .await
+    }
+
     pub async fn get_event_data(collection: &Self) -> Result<Vec<EventsModel>, Error> {
         let mut cursor = collection
             .events
@@ -276,36 +461,242 @@ impl MongoRepo {
 
         Ok(result)
     }
+
+    /// Cursor of the connector listening to `topic` of `source`, if one was stored.
+    pub async fn get_connector_cursor(
+        collection: &Self,
+        source: &str,
+        topic: &str,
+    ) -> Result<Option<ConnectorCursor>, mongodb::error::Error> {
+        collection
+            .connector_cursors
+            .find_one(doc! {"source": source, "topic": topic}, None)
+            .await
+    }
+
+    pub async fn get_connector_cursors(
+        collection: &Self,
+    ) -> Result<Vec<ConnectorCursor>, mongodb::error::Error> {
+        let mut cursor = collection.connector_cursors.find(None, None).await?;
+
+        let mut data: Vec<ConnectorCursor> = Vec::new();
+        while let Some(doc) = cursor.next().await {
+            data.push(doc?);
+        }
+
+        Ok(data)
+    }
+
+    /// Moves the cursor of a source topic, the document is created on first use.
+    pub async fn upsert_connector_cursor(
+        collection: &Self,
+        cursor: ConnectorCursor,
+    ) -> Result<UpdateResult, mongodb::error::Error> {
+        let options = UpdateOptions::builder().upsert(true).build();
+        collection
+            .connector_cursors
+            .update_one(
+                doc! {"source": cursor.source, "topic": cursor.topic},
+                doc! {"$set": {"block_number": cursor.block_number}},
+                options,
+            )
+            .await
+    }
+
+    /// Stores events not signed before shutdown, an event stored before is kept once.
+    pub async fn upsert_pending_events(
+        collection: &Self,
+        events: Vec<PendingEvent>,
+    ) -> Result<(), mongodb::error::Error> {
+        let options = UpdateOptions::builder().upsert(true).build();
+        for event in events {
+            collection
+                .pending_events
+                .update_one(
+                    doc! {"msg_hash": event.msg_hash},
+                    doc! {"$set": {"event": event.event}},
+                    options.clone(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the persisted events, they stay stored until `delete_pending_event`.
+    pub async fn get_pending_events(
+        collection: &Self,
+    ) -> Result<Vec<PendingEvent>, mongodb::error::Error> {
+        let mut cursor = collection.pending_events.find(None, None).await?;
+
+        let mut data: Vec<PendingEvent> = Vec::new();
+        while let Some(doc) = cursor.next().await {
+            data.push(doc?);
+        }
+
+        Ok(data)
+    }
+
+    /// Removes a persisted event once its signature is stored.
+    pub async fn delete_pending_event(
+        collection: &Self,
+        msg_hash: &str,
+    ) -> Result<DeleteResult, mongodb::error::Error> {
+        collection
+            .pending_events
+            .delete_many(doc! {"msg_hash": msg_hash}, None)
+            .await
+    }
+
+    pub async fn insert_key_rotation(
+        collection: &Self,
+        rotation: KeyRotationModel,
+    ) -> Result<InsertOneResult, mongodb::error::Error> {
+        collection.key_rotations.insert_one(rotation, None).await
+    }
+
+    /// Account key rotations in the order they happened.
+    pub async fn get_key_rotations(
+        collection: &Self,
+    ) -> Result<Vec<KeyRotationModel>, mongodb::error::Error> {
+        let options = FindOptions::builder().sort(doc! {"rotated_at": 1}).build();
+        let mut cursor = collection.key_rotations.find(None, options).await?;
+
+        let mut data: Vec<KeyRotationModel> = Vec::new();
+        while let Some(doc) = cursor.next().await {
+            data.push(doc?);
+        }
+
+        Ok(data)
+    }
+}
+
+/// Storage the connectors and tss run on, so they can be tested against a `MemoryRepo`.
+/// `MongoRepo` implements it with the functions of the same name.
+#[async_trait]
+pub trait Repo: Send + Sync {
+    async fn get_contract_json(&self) -> Result<Vec<ContractJson>, mongodb::error::Error>;
+
+    async fn get_swap_data(&self) -> Result<Vec<TokenSwap>, mongodb::error::Error>;
+
+    /// Cursor of the connector listening to `topic` of `source`, if one was stored.
+    async fn get_connector_cursor(
+        &self,
+        source: &str,
+        topic: &str,
+    ) -> Result<Option<ConnectorCursor>, mongodb::error::Error>;
+
+    async fn get_connector_cursors(&self) -> Result<Vec<ConnectorCursor>, mongodb::error::Error>;
+
+    /// Moves the cursor of a source topic, it is created on first use.
+    async fn upsert_connector_cursor(
+        &self,
+        cursor: ConnectorCursor,
+    ) -> Result<(), mongodb::error::Error>;
+
+    /// Stores the attestation of an event, see `MongoRepo::upsert_event_attestation`.
+    async fn upsert_event_attestation(
+        &self,
+        msg_hash: String,
+        signed_event: &str,
+        threshold_signature: Option<Value>,
+        signer: Option<Value>,
+    ) -> Result<(), mongodb::error::Error>;
+
+    /// Stores events not signed before shutdown, an event stored before is kept once.
+    async fn upsert_pending_events(
+        &self,
+        events: Vec<PendingEvent>,
+    ) -> Result<(), mongodb::error::Error>;
+
+    async fn get_pending_events(&self) -> Result<Vec<PendingEvent>, mongodb::error::Error>;
+
+    async fn delete_pending_event(&self, msg_hash: &str) -> Result<(), mongodb::error::Error>;
+}
+
+#[async_trait]
+impl Repo for MongoRepo {
+    async fn get_contract_json(&self) -> Result<Vec<ContractJson>, mongodb::error::Error> {
+        MongoRepo::get_contract_json(self)
+            .await
+            .map_err(|e| mongodb::bson::de::Error::custom(e).into())
+    }
+
+    async fn get_swap_data(&self) -> Result<Vec<TokenSwap>, mongodb::error::Error> {
+        MongoRepo::get_swap_data(self)
+            .await
+            .map_err(|e| mongodb::bson::de::Error::custom(e).into())
+    }
+
+    async fn get_connector_cursor(
+        &self,
+        source: &str,
+        topic: &str,
+    ) -> Result<Option<ConnectorCursor>, mongodb::error::Error> {
+        MongoRepo::get_connector_cursor(self, source, topic).await
+    }
+
+    async fn get_connector_cursors(&self) -> Result<Vec<ConnectorCursor>, mongodb::error::Error> {
+        MongoRepo::get_connector_cursors(self).await
+    }
+
+    async fn upsert_connector_cursor(
+        &self,
+        cursor: ConnectorCursor,
+    ) -> Result<(), mongodb::error::Error> {
+        MongoRepo::upsert_connector_cursor(self, cursor)
+            .await
+            .map(|_| ())
+    }
+
+    async fn upsert_event_attestation(
+        &self,
+        msg_hash: String,
+        signed_event: &str,
+        threshold_signature: Option<Value>,
+        signer: Option<Value>,
+    ) -> Result<(), mongodb::error::Error> {
+        MongoRepo::upsert_event_attestation(self, msg_hash, signed_event, threshold_signature, signer)
+            .await
+            .map(|_| ())
+    }
+
+    async fn upsert_pending_events(
+        &self,
+        events: Vec<PendingEvent>,
+    ) -> Result<(), mongodb::error::Error> {
+        MongoRepo::upsert_pending_events(self, events).await
+    }
+
+    async fn get_pending_events(&self) -> Result<Vec<PendingEvent>, mongodb::error::Error> {
+        MongoRepo::get_pending_events(self).await
+    }
+
+    async fn delete_pending_event(&self, msg_hash: &str) -> Result<(), mongodb::error::Error> {
+        MongoRepo::delete_pending_event(self, msg_hash)
+            .await
+            .map(|_| ())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        models::{ContractJson, EventsModel},
+        models::{ContractJson, EventsModel, KeyRotationModel, PendingEvent},
         MongoRepo,
     };
-
-    async fn get_connection() -> MongoRepo {
-        let db_url = "mongodb://localhost:27017/admin";
-        let mut collections = Vec::new();
-        collections.push("events");
-        collections.push("contracts");
-        collections.push("token_swap");
-        collections.push("swap_events");
-        collections.push("tokens");
-        let connector = MongoRepo::init(&db_url, "tango_db", collections).await;
-        connector
-    }
+    use mongodb::bson::doc;
+    use mongodb::bson::oid::ObjectId;
+    use serde_json::json;
 
     #[tokio::test]
 
     async fn connecting_test() {
-        let _connector = get_connection().await;
+        let _connector = MongoRepo::connect_test().await;
     }
 
     #[tokio::test]
     async fn retrive_contracts() {
-        let _connector = get_connection().await;
+        let _connector = MongoRepo::connect_test().await;
         let connector_json = MongoRepo::get_contract_json(&_connector).await.unwrap();
 
         assert!(!connector_json.is_empty(), "No records found");
@@ -313,7 +704,7 @@ mod tests {
 
     #[tokio::test]
     async fn retrive_events() {
-        let _connector = get_connection().await;
+        let _connector = MongoRepo::connect_test().await;
         let _result = MongoRepo::get_event_data(&_connector).await.unwrap();
     }
     #[tokio::test]
@@ -325,7 +716,7 @@ mod tests {
             "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef".to_string(),
         );
         data.push(input);
-        let _connector = get_connection().await;
+        let _connector = MongoRepo::connect_test().await;
         let _result = MongoRepo::insert_contract_json(&_connector, data)
             .await
             .unwrap();
@@ -337,7 +728,165 @@ mod tests {
         let data = serde_json::to_value(msg).unwrap();
         let input = EventsModel::new(data);
 
-        let _connector = get_connection().await;
+        let _connector = MongoRepo::connect_test().await;
         let _result = MongoRepo::insert_event(&_connector, input).await.unwrap();
     }
+
+    //tests that persisted events stay stored once each until they are deleted by msg hash
+    #[tokio::test]
+    async fn keeps_pending_events_until_deleted() {
+        let connector = MongoRepo::connect_test().await;
+        let log_hash = ObjectId::new().to_hex();
+        let swap_hash = ObjectId::new().to_hex();
+        let stored = |pending: Vec<PendingEvent>| -> Vec<String> {
+            let mut hashes: Vec<String> = pending
+                .into_iter()
+                .map(|event| event.msg_hash)
+                .filter(|msg_hash| *msg_hash == log_hash || *msg_hash == swap_hash)
+                .collect();
+            hashes.sort();
+            hashes
+        };
+
+        let events = || {
+            vec![
+                PendingEvent::new(log_hash.clone(), r#"{"event_type":"log"}"#.into()),
+                PendingEvent::new(swap_hash.clone(), r#"{"event_type":"swap"}"#.into()),
+            ]
+        };
+        MongoRepo::upsert_pending_events(&connector, events())
+            .await
+            .unwrap();
+        MongoRepo::upsert_pending_events(&connector, events())
+            .await
+            .unwrap();
+        let mut both = vec![log_hash.clone(), swap_hash.clone()];
+        both.sort();
+        let pending = MongoRepo::get_pending_events(&connector).await.unwrap();
+        assert_eq!(stored(pending), both);
+
+        MongoRepo::delete_pending_event(&connector, &log_hash)
+            .await
+            .unwrap();
+        let pending = MongoRepo::get_pending_events(&connector).await.unwrap();
+        assert_eq!(stored(pending), vec![swap_hash.clone()]);
+        MongoRepo::delete_pending_event(&connector, &swap_hash)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn migrates_twice() {
+        let connector = MongoRepo::connect_test().await;
+        let indexes = MongoRepo::migrate(&connector).await.unwrap();
+        assert_eq!(
+            indexes,
+            vec!["event_msg_hash", "connector_cursor_source", "key_rotation"]
+        );
+        assert_eq!(MongoRepo::migrate(&connector).await.unwrap(), indexes);
+    }
+
+    //tests that cursors stored under contract_address are found by their source after migrating
+    #[tokio::test]
+    async fn migrates_cursors_to_source() {
+        let connector = MongoRepo::connect_test().await;
+        let contract_address = format!("0xcursor{}", std::process::id());
+        connector
+            .connector_cursors
+            .clone_with_type::<mongodb::bson::Document>()
+            .insert_one(
+                doc! {
+                    "contract_address": &contract_address,
+                    "topic": "0xddf2",
+                    "block_number": 7_i64,
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        MongoRepo::migrate(&connector).await.unwrap();
+        let cursor = MongoRepo::get_connector_cursor(&connector, &contract_address, "0xddf2")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cursor.block_number, 7);
+    }
+
+    //tests that attestations of all nodes end up in one document and a copy is refused
+    #[tokio::test]
+    async fn upserts_attestations_into_one_document() {
+        let connector = MongoRepo::connect_test().await;
+        MongoRepo::migrate(&connector).await.unwrap();
+        let msg_hash = format!("attestation-{}", std::process::id());
+        let event = r#"{"swap_price":"1700","event_type":"swap"}"#;
+
+        for signer in ["alice", "bob"] {
+            MongoRepo::upsert_event_attestation(
+                &connector,
+                msg_hash.clone(),
+                event,
+                None,
+                Some(json!({ "signer": signer })),
+            )
+            .await
+            .unwrap();
+        }
+        let filter = doc! {"data.msg_hash": &msg_hash};
+        assert_eq!(
+            connector
+                .events
+                .count_documents(filter.clone(), None)
+                .await
+                .unwrap(),
+            1
+        );
+        let stored = connector.events.find_one(filter, None).await.unwrap().unwrap();
+        assert_eq!(stored.data["signers"].as_array().unwrap().len(), 2);
+        assert_eq!(stored.data["swap_price"], "1700");
+        assert_eq!(stored.data["signed_event"], event);
+
+        let copy = EventsModel::new(json!({"msg_hash": msg_hash}));
+        assert!(MongoRepo::insert_event(&connector, copy).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_reserved_event_fields() {
+        let connector = MongoRepo::connect_test().await;
+        for field in crate::ATTESTATION_FIELDS {
+            let mut event = json!({"event_type": "log"});
+            event[field] = "forged".into();
+            assert!(MongoRepo::upsert_event_attestation(
+                &connector,
+                format!("reserved-{}-{}", field, std::process::id()),
+                &event.to_string(),
+                None,
+                None,
+            )
+            .await
+            .is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn records_key_rotations() {
+        let connector = MongoRepo::connect_test().await;
+        let new_key = format!("new-key-{}", std::process::id());
+        let rotation = KeyRotationModel::new(
+            "tngo".into(),
+            "sr25519".into(),
+            "old-key".into(),
+            new_key.clone(),
+            1_700_000_000,
+            "00".into(),
+        );
+        MongoRepo::insert_key_rotation(&connector, rotation)
+            .await
+            .unwrap();
+
+        let rotations = MongoRepo::get_key_rotations(&connector).await.unwrap();
+        assert!(rotations
+            .iter()
+            .any(|rotation| rotation.new_key == new_key && rotation.old_key == "old-key"));
+    }
 }
@@ -0,0 +1,236 @@
+use async_trait::async_trait;
+use mongodb::bson::oid::ObjectId;
+use serde::ser::Error as _;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::models::{ConnectorCursor, ContractJson, PendingEvent, TokenSwap};
+use crate::{Repo, ATTESTATION_FIELDS};
+
+/// `Repo` kept in memory, lets the tests of other crates run without a database.
+#[derive(Default)]
+pub struct MemoryRepo {
+    contracts: Mutex<Vec<ContractJson>>,
+    token_swap: Mutex<Vec<TokenSwap>>,
+    connector_cursors: Mutex<Vec<ConnectorCursor>>,
+    pending_events: Mutex<Vec<PendingEvent>>,
+    /// `data` of the event documents by msg hash
+    events: Mutex<BTreeMap<String, Map<String, Value>>>,
+}
+
+impl MemoryRepo {
+    pub fn new() -> Self {
+        MemoryRepo::default()
+    }
+
+    /// Adds a contract and returns its id.
+    pub fn insert_contract(&self, mut contract: ContractJson) -> String {
+        let id = *contract.id.get_or_insert_with(ObjectId::new);
+        self.contracts.lock().unwrap().push(contract);
+        id.to_hex()
+    }
+
+    pub fn delete_contract(&self, id: &str) {
+        self.contracts
+            .lock()
+            .unwrap()
+            .retain(|contract| contract.id.map(|id| id.to_hex()).as_deref() != Some(id));
+    }
+
+    pub fn insert_swap_data(&self, token_swap: Vec<TokenSwap>) {
+        self.token_swap.lock().unwrap().extend(token_swap);
+    }
+
+    /// `data` of the event document stored with `upsert_event_attestation`.
+    pub fn event_attestation(&self, msg_hash: &str) -> Option<Value> {
+        self.events
+            .lock()
+            .unwrap()
+            .get(msg_hash)
+            .map(|data| Value::Object(data.clone()))
+    }
+}
+
+#[async_trait]
+impl Repo for MemoryRepo {
+    async fn get_contract_json(&self) -> Result<Vec<ContractJson>, mongodb::error::Error> {
+        Ok(self.contracts.lock().unwrap().clone())
+    }
+
+    async fn get_swap_data(&self) -> Result<Vec<TokenSwap>, mongodb::error::Error> {
+        Ok(self.token_swap.lock().unwrap().clone())
+    }
+
+    async fn get_connector_cursor(
+        &self,
+        source: &str,
+        topic: &str,
+    ) -> Result<Option<ConnectorCursor>, mongodb::error::Error> {
+        Ok(self
+            .connector_cursors
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|cursor| cursor.source == source && cursor.topic == topic)
+            .cloned())
+    }
+
+    async fn get_connector_cursors(&self) -> Result<Vec<ConnectorCursor>, mongodb::error::Error> {
+        Ok(self.connector_cursors.lock().unwrap().clone())
+    }
+
+    async fn upsert_connector_cursor(
+        &self,
+        cursor: ConnectorCursor,
+    ) -> Result<(), mongodb::error::Error> {
+        let mut cursors = self.connector_cursors.lock().unwrap();
+        match cursors
+            .iter_mut()
+            .find(|stored| stored.source == cursor.source && stored.topic == cursor.topic)
+        {
+            Some(stored) => stored.block_number = cursor.block_number,
+            None => cursors.push(cursor),
+        }
+        Ok(())
+    }
+
+    /// Same document as `MongoRepo::upsert_event_attestation` writes.
+    async fn upsert_event_attestation(
+        &self,
+        msg_hash: String,
+        signed_event: &str,
+        threshold_signature: Option<Value>,
+        signer: Option<Value>,
+    ) -> Result<(), mongodb::error::Error> {
+        let event = serde_json::from_str::<Value>(signed_event)
+            .map_err(|e| mongodb::bson::ser::Error::custom(format!("invalid event: {}", e)))?;
+        let fields = match event {
+            Value::Object(fields) => fields,
+            _ => Map::new(),
+        };
+        if let Some(key) = fields
+            .keys()
+            .find(|key| ATTESTATION_FIELDS.contains(&key.as_str()))
+        {
+            return Err(mongodb::bson::ser::Error::custom(format!(
+                "event field {} is reserved for the attestation",
+                key
+            ))
+            .into());
+        }
+
+        let mut events = self.events.lock().unwrap();
+        let data = events.entry(msg_hash.clone()).or_insert_with(|| {
+            let mut data = fields;
+            data.insert("msg_hash".into(), Value::String(msg_hash));
+            data.insert("signed_event".into(), Value::String(signed_event.into()));
+            data
+        });
+        if let Some(threshold_signature) = threshold_signature {
+            data.insert("threshold_signature".into(), threshold_signature);
+        }
+        if let Some(signer) = signer {
+            let signers = data
+                .entry("signers")
+                .or_insert_with(|| Value::Array(vec![]));
+            if let Value::Array(signers) = signers {
+                if !signers.contains(&signer) {
+                    signers.push(signer);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn upsert_pending_events(
+        &self,
+        events: Vec<PendingEvent>,
+    ) -> Result<(), mongodb::error::Error> {
+        let mut pending_events = self.pending_events.lock().unwrap();
+        for event in events {
+            match pending_events
+                .iter_mut()
+                .find(|pending| pending.msg_hash == event.msg_hash)
+            {
+                Some(pending) => pending.event = event.event,
+                None => pending_events.push(event),
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_pending_events(&self) -> Result<Vec<PendingEvent>, mongodb::error::Error> {
+        Ok(self.pending_events.lock().unwrap().clone())
+    }
+
+    async fn delete_pending_event(&self, msg_hash: &str) -> Result<(), mongodb::error::Error> {
+        self.pending_events
+            .lock()
+            .unwrap()
+            .retain(|pending| pending.msg_hash != msg_hash);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn moves_stored_cursor() {
+        let repo = MemoryRepo::new();
+        assert!(repo
+            .get_connector_cursor("0x1", "t")
+            .await
+            .unwrap()
+            .is_none());
+
+        for block_number in [5, 7] {
+            repo.upsert_connector_cursor(ConnectorCursor::new(
+                "0x1".into(),
+                "t".into(),
+                block_number,
+            ))
+            .await
+            .unwrap();
+        }
+        let cursor = repo
+            .get_connector_cursor("0x1", "t")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cursor.block_number, 7);
+        assert_eq!(repo.get_connector_cursors().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn collects_attestations_of_event() {
+        let repo = MemoryRepo::new();
+        let event = r#"{"event_type":"swap"}"#;
+        for signer in ["a", "b", "a"] {
+            repo.upsert_event_attestation("hash".into(), event, None, Some(json!(signer)))
+                .await
+                .unwrap();
+        }
+        repo.upsert_event_attestation("hash".into(), event, Some(json!("sig")), None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            repo.event_attestation("hash").unwrap(),
+            json!({
+                "event_type": "swap",
+                "msg_hash": "hash",
+                "signed_event": event,
+                "signers": ["a", "b"],
+                "threshold_signature": "sig",
+            })
+        );
+        assert!(repo
+            .upsert_event_attestation("other".into(), r#"{"signers":[]}"#, None, None)
+            .await
+            .is_err());
+    }
+}
@@ -11,7 +11,7 @@ pub struct EventsModel {
     pub id: Option<ObjectId>,
     pub data: Value,
 }
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContractJson {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
@@ -36,7 +36,7 @@ impl EventsModel {
         EventsModel { data, id: None }
     }
 }
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenSwap {
     pub id: Option<ObjectId>,
     pub chain: String,
@@ -136,4 +136,80 @@ impl Token {
             token_endpoint,
         }
     }
-}
\ No newline at end of file
+}
+/// Event handed to tss but not signed when the node shut down, resubmitted on the next start.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingEvent {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub msg_hash: String,
+    pub event: String,
+}
+
+impl PendingEvent {
+    pub fn new(msg_hash: String, event: String) -> Self {
+        PendingEvent {
+            msg_hash,
+            event,
+            id: None,
+        }
+    }
+}
+
+/// Last block of a contract topic whose logs were all sent to tss.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectorCursor {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// contract address of an ethereum listener or endpoint of a substrate chain
+    pub source: String,
+    pub topic: String,
+    pub block_number: i64,
+}
+
+impl ConnectorCursor {
+    pub fn new(source: String, topic: String, block_number: i64) -> Self {
+        ConnectorCursor {
+            source,
+            topic,
+            block_number,
+            id: None,
+        }
+    }
+}
+
+/// Handover of a node account to a new key, signed by the old key over the account handover statement.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyRotationModel {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub key_type: String,
+    /// sr25519, ed25519 or ecdsa, the scheme of both keys
+    pub scheme: String,
+    pub old_key: String,
+    pub new_key: String,
+    /// unix seconds, end of the old key's window and start of the new key's
+    pub rotated_at: i64,
+    pub signature: String,
+}
+
+impl KeyRotationModel {
+    pub fn new(
+        key_type: String,
+        scheme: String,
+        old_key: String,
+        new_key: String,
+        rotated_at: i64,
+        signature: String,
+    ) -> Self {
+        KeyRotationModel {
+            key_type,
+            scheme,
+            old_key,
+            new_key,
+            rotated_at,
+            signature,
+            id: None,
+        }
+    }
+}
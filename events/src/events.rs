@@ -10,3 +10,11 @@ pub struct Event {
     pub event_type: EventType,
     pub data: Vec<u8>,
 }
+
+/// Message handed to the network by the node: gossiped to every peer, or sent
+/// to a single peer when only that peer needs it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutboundMessage {
+    Broadcast(Vec<u8>),
+    Direct { peer_id: String, data: Vec<u8> },
+}
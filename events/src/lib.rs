@@ -1 +1,3 @@
 pub mod events;
+pub mod network;
+pub mod tss;
@@ -0,0 +1,33 @@
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+/// Query answered by the network loop, the answer is sent back on the enclosed channel.
+#[derive(Debug)]
+pub enum NetworkCommand {
+    Status(oneshot::Sender<NetworkStatus>),
+}
+
+/// Connectivity of the node as seen by the swarm.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct NetworkStatus {
+    pub local_peer_id: String,
+    pub listen_addresses: Vec<String>,
+    pub peers: Vec<PeerStatus>,
+    pub mesh: Vec<TopicMesh>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PeerStatus {
+    pub peer_id: String,
+    /// remote addresses of the open connections
+    pub addresses: Vec<String>,
+    /// round trip time of the last successful ping
+    pub ping_rtt_ms: Option<u64>,
+}
+
+/// Gossipsub mesh members of a subscribed topic.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TopicMesh {
+    pub topic: String,
+    pub peers: Vec<String>,
+}
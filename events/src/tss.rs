@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+/// Query answered by the tss loop, the answer is sent back on the enclosed channel.
+#[derive(Debug)]
+pub enum TssCommand {
+    Status(oneshot::Sender<TssStatus>),
+}
+
+/// Key group and signing queues of the node as seen by the tss.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TssStatus {
+    pub local_peer_id: String,
+    /// keygen or signing state, e.g. StateFinished once the group key is known
+    pub state: String,
+    pub is_collector: bool,
+    pub is_aggregator: bool,
+    pub ciphersuite: String,
+    pub participants: u32,
+    pub threshold: u32,
+    /// other members of the key group
+    pub peers: Vec<String>,
+    /// hex encoded group key, none until keygen finished
+    pub group_key: Option<String>,
+    /// events waiting for their threshold signature
+    pub msg_pool: usize,
+    pub pending_signatures: usize,
+}
@@ -1,4 +1,4 @@
-use accounts::Account;
+use accounts::{Account, AccountScheme};
 use clap::Parser;
 use std::time::Instant;
 use std::{sync::Arc, time::Duration};
@@ -7,41 +7,57 @@ use env_logger::Env;
 use keystore::{commands::KeyTypeId, params::keystore_params::KeystoreParams};
 use libp2p::gossipsub::Topic;
 use network::network_handler;
+use network::get_bootnodes;
+use network::utils::committee::Committee;
 use network::utils::identity_handler::get_node_identity;
+use network::utils::peer_book::default_peer_book_path;
 use sc_keystore::LocalKeystore;
 use sc_service::config::KeystoreConfig;
 use sp_keystore::SyncCryptoStorePtr;
 use std::env;
 use tango_node::cli::Args;
+use tango_node::commands::{self, get_connection};
+use tango_node::config::NodeConfig;
 use tokio;
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{mpsc, watch, Mutex, Notify};
+use tokio::time;
+use tango_node::shutdown::{shutdown_signal, NETWORK_CLOSE_MARGIN};
+use tss::attestation::AttestationMode;
+use tss::ciphersuite::CiphersuiteId;
+use tss::msg_pool::MsgPoolConfig;
 use tss::tss_event_model::TSSData;
 use tss::tss_service::TssService;
+use events::events::OutboundMessage;
+use events::network::NetworkCommand;
+use events::tss::TssCommand;
 
-use tango_database::MongoRepo;
-
-async fn get_connection(db_url: String) -> MongoRepo {
-    let mut collections = Vec::new();
-    collections.push("events");
-    collections.push("contracts");
-    collections.push("token_swap");
-    collections.push("swap_events");
-    let connector = MongoRepo::init(&db_url, "tango_db", collections).await;
-
-    connector
-}
+use tango_database::{MongoRepo, Repo};
 use messages::gossip_message_handler::GossipEventHandler;
 
 #[tokio::main]
 async fn main() {
     // log
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+
+    // get the cli arguments and the effective configuration
+    let args = Args::parse();
+    let config = match NodeConfig::load(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("{}", e);
+            return;
+        }
+    };
+    if let Err(e) = config.validate() {
+        log::error!("{}", e);
+        return;
+    }
     let config_dir = env::current_dir().unwrap();
     log::info!("Tango node start up ");
 
     //Declare channels and variables
     //Channels
-    let (gossip_sender, gossip_receiver) = mpsc::channel::<Vec<u8>>(1000);
+    let (gossip_sender, gossip_receiver) = mpsc::channel::<OutboundMessage>(1000);
     let message_handler_to_gossip_sender = gossip_sender.clone();
     let tss_to_gossip_sender = gossip_sender.clone();
 
@@ -62,11 +78,8 @@ async fn main() {
         _ => unreachable!("keystore_config always returns path and password; qed"),
     };
 
-    // get the cli arguments
-    let args = Args::parse();
-
     // assign tss keytype and keystore
-    let key_type_option = Some(KeyTypeId::try_from(args.key_type.as_str()).unwrap());
+    let key_type_option = Some(KeyTypeId::try_from(config.tss.key_type.as_str()).unwrap());
     let keystore_option = Some(keystore.clone());
 
     let key_type = match key_type_option {
@@ -85,39 +98,125 @@ async fn main() {
         }
     };
 
-    // create account
-    let acc = match Account::new(&args.password.clone(), key_type, key_store){
-        Ok(acc) => acc,
+    // signature scheme used when this node collects the tss key group
+    let ciphersuite = match config.tss.ciphersuite.parse::<CiphersuiteId>() {
+        Ok(ciphersuite) => ciphersuite,
+        Err(e) => {
+            log::error!("Invalid ciphersuite: {}", e);
+            return;
+        }
+    };
+
+    // signatures stored with each verified event
+    let attestation_mode = match config.tss.attestation_mode.parse::<AttestationMode>() {
+        Ok(mode) => mode,
+        Err(e) => {
+            log::error!("Invalid attestation mode: {}", e);
+            return;
+        }
+    };
+
+    // nodes dialed on start, in addition to the ones in the peer book
+    let bootnodes = match get_bootnodes(&config.network.bootnodes) {
+        Ok(bootnodes) => bootnodes,
+        Err(e) => {
+            log::error!("Invalid bootnodes: {}", e);
+            return;
+        }
+    };
+    // a new node has a new identity every run, so there are no peers to remember
+    let peer_book_path = config.network.peer_book.clone().or_else(|| {
+        if config.network.new_node {
+            None
+        } else {
+            default_peer_book_path()
+        }
+    });
+
+    // permissioned committee, every peer may join without one
+    let committee = match config.network.committee.as_deref().map(Committee::load).transpose() {
+        Ok(committee) => committee,
+        Err(e) => {
+            log::error!("Unable to read committee: {}", e);
+            return;
+        }
+    };
+
+    // signature scheme of the account key
+    let account_scheme = match config.tss.account_scheme.parse::<AccountScheme>() {
+        Ok(scheme) => scheme,
         Err(e) => {
-            eprintln!("Error creating account: {:?}", e);
+            log::error!("Invalid account scheme: {}", e);
             return;
         }
     };
 
+    // the account key is reused across restarts, a new one is only generated when asked for
+    let acc = match Account::load(account_scheme, key_type, key_store.clone()) {
+        Ok(Some(acc)) => acc,
+        Ok(None) if config.tss.generate_account => {
+            match Account::generate(account_scheme, config.tss.password.as_deref().unwrap_or_default(), key_type, key_store) {
+                Ok(acc) => acc,
+                Err(e) => panic!("Error creating account: {:?}", e),
+            }
+        }
+        Ok(None) => {
+            log::error!(
+                "No {} account key of type {} in the keystore, run `tango-node account import` or `tango-node account generate`, or start with --generate-account=true",
+                account_scheme,
+                config.tss.key_type
+            );
+            return;
+        }
+        Err(e) => {
+            log::error!("Unable to load account: {}", e);
+            return;
+        }
+    };
+    log::info!("Account {} ({})", acc.accounts, account_scheme);
+
     // start the db instance
     // start the actix server mongo instance
-    let db_url = args.db_url.clone();
-    tokio::spawn(async move {
+    let db_url = config.database.url.clone();
+    let (network_command_sender, network_command_receiver) = mpsc::channel::<NetworkCommand>(16);
+    let (tss_command_sender, tss_command_receiver) = mpsc::channel::<TssCommand>(16);
+    // tss and server stop first, the network stays up while they drain
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+    let (network_shutdown_sender, network_shutdown_receiver) = watch::channel(false);
+    let grace = Duration::from_secs(config.tss.shutdown_grace_secs);
+    let server_shutdown = shutdown_receiver.clone();
+    let server_config = config.server.clone();
+    let server_task = tokio::spawn(async move {
         let connector = get_connection(db_url).await;
-        let ip = args.ip;
-        let port = args.port;
-        let origin = args.origin;
+        let ip = server_config.ip;
+        let port = server_config.port;
+        let origin = server_config.origin;
 
         server::start_server(
             Arc::new(Mutex::new(connector)),
+            Arc::new(Notify::new()),
+            network_command_sender,
+            tss_command_sender,
             ip,
             port,
             origin,
-            args.workers,
+            server_config.workers,
+            server_shutdown,
+            grace,
         )
         .await
         .unwrap()
     });
 
     // start connector
-    let conn_db_url = args.db_url.clone();
+    let conn_db_url = config.database.url.clone();
     //tss should take the event_receiver
     let connector = get_connection(conn_db_url.clone()).await;
+    if let Err(e) = MongoRepo::migrate(&connector).await {
+        log::error!("Unable to create database indexes: {}", e);
+        return;
+    }
+    let connector: Arc<dyn Repo> = Arc::new(connector);
     log::info!("Data fetched successfully from the contract database.",);
 
     // just for testing purpose will be removed later
@@ -143,7 +242,16 @@ async fn main() {
     });
 
     //Network creating or getting identity
-    let (peer_id, id_keys) = get_node_identity(args.new_node);
+    let (peer_id, id_keys) = get_node_identity(config.network.new_node);
+
+    // other nodes only take part in keygen with peers which registered their account
+    let registration = match commands::register_peer(&peer_id, &id_keys, &acc, key_type, keystore.clone()) {
+        Ok(registration) => registration,
+        Err(e) => {
+            log::error!("Unable to register peer {} for account {}: {}", peer_id, acc.accounts, e);
+            return;
+        }
+    };
 
     //////////////////////////
     //Handler struct to handle messages
@@ -157,36 +265,82 @@ async fn main() {
         message_handler_to_tss_receiver,
         tss_to_gossip_sender,
         event_receiver,
+        tss_command_receiver,
         acc,
+        registration,
         connector,
-        !args.new_node,
+        !config.network.new_node,
+        config.tss.collector.clone(),
         peer_id.to_string(),
-        (args.tss_nodes, args.tss_threshold),
+        (config.tss.nodes, config.tss.threshold),
         key_type_option,
         keystore_option,
+        ciphersuite,
+        attestation_mode,
+        config.tss.observation_tolerance_bps,
+        MsgPoolConfig {
+            max_len: config.tss.msg_pool_size,
+            ttl: Duration::from_secs(config.tss.msg_ttl_secs),
+        },
+        committee.as_ref().map(Committee::peer_ids),
     )
     .await;
 
-    tokio::spawn(async move { tss_service.run().await });
+    let tss_shutdown = shutdown_receiver.clone();
+    let tss_task = tokio::spawn(async move { tss_service.run(tss_shutdown, grace).await });
 
     ////////////////////////// Network Operations //////////////////////////
 
     // start the network
-    let network_topic = &Topic::new(args.p2p_topic);
-    let _ = network_handler::run(
+    let network_topic = &Topic::new(config.network.topic.clone());
+    let network = network_handler::run(
         network_topic,
         gossip_receiver,
+        network_command_receiver,
+        network_shutdown_receiver,
         &handler_message,
-        None,
-        //seed node from cli if something is passed
-        if args.seed_node.is_empty() {
-            None
-        } else {
-            Some(&args.seed_node)
-        },
+        config.network.explicit_peer.as_deref(),
+        &bootnodes,
+        peer_book_path.as_deref(),
+        committee.as_ref(),
         //specific port to run on
-        &args.node_port,
+        &config.network.port.to_string(),
         (peer_id, id_keys),
-    )
-    .await;
+    );
+    tokio::pin!(network);
+
+    let network_stopped = tokio::select! {
+        result = &mut network => {
+            if let Err(e) = result {
+                log::error!("Network stopped: {}", e);
+            }
+            true
+        }
+        _ = shutdown_signal() => false,
+    };
+    log::info!("Shutting down, waiting up to {}s for in-flight work", grace.as_secs());
+
+    // the tss signs what it already has and the server answers open requests
+    let _ = shutdown_sender.send(true);
+    let drain = async {
+        let _ = tss_task.await;
+        let _ = server_task.await;
+        let _ = network_shutdown_sender.send(true);
+    };
+    let close_network = async {
+        if !network_stopped {
+            if let Err(e) = (&mut network).await {
+                log::error!("Network stopped: {}", e);
+            }
+        }
+    };
+    if time::timeout(grace + NETWORK_CLOSE_MARGIN, async {
+        tokio::join!(drain, close_network)
+    })
+    .await
+    .is_err()
+    {
+        log::warn!("Shutdown grace period elapsed, exiting");
+    }
+    log::info!("tango node stopped");
 }
@@ -1,42 +1,65 @@
 use crate::tss_event_sender::handle_tss_event;
 use async_trait::async_trait;
 use borsh::BorshDeserialize;
-use events::events::{Event, EventType};
-use libp2p::gossipsub::GossipsubEvent;
+use events::events::{Event, EventType, OutboundMessage};
+use libp2p::gossipsub::{GossipsubEvent, MessageAcceptance};
+use libp2p::PeerId;
 use tokio::sync::mpsc;
 use tss::tss_event_model::TSSData;
 
 #[async_trait]
 pub trait MessageHandler: Send + Sync {
-    async fn handle_message(&self, event: GossipsubEvent);
+    /// Handles gossip events. For a received message the result decides whether gossipsub
+    /// forwards it (Accept), drops it (Ignore) or drops it and penalizes the sender (Reject).
+    async fn handle_message(&self, event: GossipsubEvent) -> MessageAcceptance;
+
+    /// message sent to this node only over the direct protocol
+    async fn handle_direct_message(&self, _peer_id: PeerId, _data: Vec<u8>) {}
 }
 
 pub struct GossipEventHandler {
-    pub gossip_sender: mpsc::Sender<Vec<u8>>,
+    pub gossip_sender: mpsc::Sender<OutboundMessage>,
     pub gossip_to_tss_sender: mpsc::Sender<TSSData>,
 }
 
 #[async_trait]
 impl MessageHandler for GossipEventHandler {
-    async fn handle_message(&self, event: GossipsubEvent) {
+    async fn handle_message(&self, event: GossipsubEvent) -> MessageAcceptance {
         match event {
             GossipsubEvent::Message {
                 propagation_source: _peer_id,
                 message_id: _id,
                 message,
-            } => {
-                if let Ok(event) = Event::try_from_slice(&message.data) {
-                    match event.event_type {
-                        EventType::TSSEvent => {
-                            //send event to tss event parser
-                            handle_tss_event(self.gossip_to_tss_sender.clone(), &event.data).await;
-                        }
-                    }
-                } else {
-                    log::error!("Unable to parse event data");
+            } => match message.source {
+                //author of the message, signed since gossip validates strictly
+                Some(source) => self.handle_event_data(&message.data, &source).await,
+                None => {
+                    log::warn!("Dropping gossip message without source");
+                    MessageAcceptance::Reject
+                }
+            },
+            _ => MessageAcceptance::Ignore,
+        }
+    }
+
+    async fn handle_direct_message(&self, peer_id: PeerId, data: Vec<u8>) {
+        self.handle_event_data(&data, &peer_id).await;
+    }
+}
+
+impl GossipEventHandler {
+    /// `sender` is the authenticated peer the message comes from, its tss data has to name it
+    async fn handle_event_data(&self, data: &[u8], sender: &PeerId) -> MessageAcceptance {
+        if let Ok(event) = Event::try_from_slice(data) {
+            match event.event_type {
+                EventType::TSSEvent => {
+                    //send event to tss event parser
+                    handle_tss_event(self.gossip_to_tss_sender.clone(), &event.data, sender).await
                 }
             }
-            _ => {}
+        } else {
+            log::error!("Unable to parse event data");
+            MessageAcceptance::Reject
         }
     }
 }
@@ -47,7 +70,7 @@ pub struct DummyHandleMessage;
 
 #[async_trait]
 impl MessageHandler for DummyHandleMessage {
-    async fn handle_message(&self, event: GossipsubEvent) {
+    async fn handle_message(&self, event: GossipsubEvent) -> MessageAcceptance {
         match event {
             GossipsubEvent::Message {
                 propagation_source: peer_id,
@@ -65,5 +88,6 @@ impl MessageHandler for DummyHandleMessage {
             }
             _ => {}
         }
+        MessageAcceptance::Accept
     }
 }
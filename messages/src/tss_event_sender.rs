@@ -1,13 +1,33 @@
 use borsh::BorshDeserialize;
+use libp2p::gossipsub::MessageAcceptance;
+use libp2p::PeerId;
 use tokio::sync::mpsc;
 use tss::tss_event_model::TSSData;
 
-pub async fn handle_tss_event(gossip_to_tss_sender: mpsc::Sender<TSSData>, data: &Vec<u8>) {
+/// Hands tss data to the tss, which then trusts its peer id. Data that does not decode or
+/// claims another peer than the libp2p `sender` is rejected, data the tss cannot take right
+/// now is ignored so it is neither forwarded nor penalized.
+pub async fn handle_tss_event(
+    gossip_to_tss_sender: mpsc::Sender<TSSData>,
+    data: &Vec<u8>,
+    sender: &PeerId,
+) -> MessageAcceptance {
     if let Ok(parsed_data) = TSSData::try_from_slice(data) {
+        if parsed_data.peer_id != sender.to_string() {
+            log::warn!(
+                "tss data of {} sent by {}, dropping",
+                parsed_data.peer_id,
+                sender
+            );
+            return MessageAcceptance::Reject;
+        }
         if let Err(e) = gossip_to_tss_sender.send(parsed_data).await {
             log::error!("error sending gossip to tss: {}", e);
+            return MessageAcceptance::Ignore;
         }
+        MessageAcceptance::Accept
     } else {
         log::error!("Unable to parse tss data");
+        MessageAcceptance::Reject
     }
 }
@@ -6,14 +6,18 @@ pub mod utils;
 
 ///////////
 /// Bootnodes are hosted on a public IP and with open port so other users can connect with it.
-/// Right now there is only 1 bootnode which is hosted on aws server with 52200 port open.
-/// we can read below line as we have an ipv4 as 100.24.209.156 who is listening on 52200 tcp port
-/// for p2p connection having its peer id as 12D3KooWFEXXy8iJfWk3ZG5883GiebFpg1QbNCivXKSE5VZ9YkHF.
-pub fn get_bootnodes() -> Vec<Multiaddr> {
-    ["/ip4/127.0.0.1/tcp/52200/p2p/12D3KooWFEXXy8iJfWk3ZG5883GiebFpg1QbNCivXKSE5VZ9YkHF"]
+/// They are passed as multiaddrs, e.g. /ip4/100.24.209.156/tcp/52200/p2p/12D3KooW... or
+/// /dns4/boot.example.com/tcp/52200/p2p/12D3KooW..., dns hosts are resolved when dialing.
+/// Bootnodes with a /p2p/ peer id are also added to kademlia, the others are only dialed.
+pub fn get_bootnodes(addresses: &[String]) -> Result<Vec<Multiaddr>, String> {
+    addresses
         .iter()
-        .filter_map(|s| Multiaddr::from_str(s).ok())
-        .collect::<Vec<_>>()
+        .map(|address| address.trim())
+        .filter(|address| !address.is_empty())
+        .map(|address| {
+            Multiaddr::from_str(address).map_err(|e| format!("invalid bootnode {}: {}", address, e))
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -22,15 +26,19 @@ mod tests {
 
     use super::*;
     use async_trait::async_trait;
-    use libp2p::gossipsub::{GossipsubEvent, Topic};
-    use message::gossip_message_handler::MessageHandler;
+    use events::events::OutboundMessage;
+    use events::network::NetworkCommand;
+    use libp2p::gossipsub::{GossipsubEvent, MessageAcceptance, Topic};
+    use libp2p::PeerId;
+    use message::gossip_message_handler::{DummyHandleMessage, MessageHandler};
     use once_cell::sync::Lazy;
     use std::sync::Mutex;
     use std::{thread, time::Duration};
-    use tokio::sync::mpsc;
+    use tokio::sync::{mpsc, oneshot, watch};
 
     static ARRAY_SEED: Lazy<Mutex<Vec<u8>>> = Lazy::new(|| Mutex::new(vec![]));
     static ARRAY_MDNS: Lazy<Mutex<Vec<u8>>> = Lazy::new(|| Mutex::new(vec![]));
+    static ARRAY_DIRECT: Lazy<Mutex<Vec<(PeerId, Vec<u8>)>>> = Lazy::new(|| Mutex::new(vec![]));
 
     /// Runs test to crate and run 2 nodes and then verify if both nodes exchanged data
     /// run with cargo test -- tests::communication_with_seed --exact --nocapture to see detailed output
@@ -46,7 +54,7 @@ mod tests {
 
         #[async_trait]
         impl MessageHandler for HandleMessage {
-            async fn handle_message(&self, event: GossipsubEvent) {
+            async fn handle_message(&self, event: GossipsubEvent) -> MessageAcceptance {
                 match event {
                     GossipsubEvent::Message {
                         propagation_source: _peer_id,
@@ -68,6 +76,7 @@ mod tests {
                     }
                     _ => log::info!("handler was not able to handle the event: {:?}", event),
                 }
+                MessageAcceptance::Accept
             }
         }
 
@@ -77,7 +86,7 @@ mod tests {
         //second node sending value
         let second_sending_val = "world".as_bytes().to_vec();
 
-        let (first_n_event_sender, first_n_event_rec) = mpsc::channel::<Vec<u8>>(100);
+        let (first_n_event_sender, first_n_event_rec) = mpsc::channel::<OutboundMessage>(100);
         let topic = Topic::new("test_case");
 
         let first_node_handler = HandleMessage {
@@ -96,8 +105,12 @@ mod tests {
                 let _ = network_handler::run(
                     &topic_clone1,
                     first_n_event_rec,
+                    mpsc::channel::<NetworkCommand>(1).1,
+                    watch::channel(false).1,
                     &first_node_handler,
                     None,
+                    &[],
+                    None,
                     None,
                     "39900",
                     (peer_id_1, id_keys_1),
@@ -106,7 +119,7 @@ mod tests {
             }));
         });
 
-        let (second_n_event_sender, second_n_event_rec) = mpsc::channel::<Vec<u8>>(100);
+        let (second_n_event_sender, second_n_event_rec) = mpsc::channel::<OutboundMessage>(100);
 
         let second_node_handler = HandleMessage {
             compare_val: first_sending_val.clone(),
@@ -121,9 +134,13 @@ mod tests {
                 let _ = network_handler::run(
                     &topic_clone2,
                     second_n_event_rec,
+                    mpsc::channel::<NetworkCommand>(1).1,
+                    watch::channel(false).1,
                     &second_node_handler,
                     None,
-                    Some("/ip4/127.0.0.1/tcp/39900"),
+                    &["/ip4/127.0.0.1/tcp/39900".parse().unwrap()],
+                    None,
+                    None,
                     "39911",
                     (peer_id_2, id_keys_2),
                 )
@@ -133,11 +150,17 @@ mod tests {
 
         tokio::time::sleep(Duration::from_millis(5000)).await;
 
-        if let Err(e) = first_n_event_sender.send(first_sending_val).await {
+        if let Err(e) = first_n_event_sender
+            .send(OutboundMessage::Broadcast(first_sending_val))
+            .await
+        {
             println!("error while sending value to node {}", e);
         }
 
-        if let Err(e) = second_n_event_sender.send(second_sending_val).await {
+        if let Err(e) = second_n_event_sender
+            .send(OutboundMessage::Broadcast(second_sending_val))
+            .await
+        {
             println!("error while sending value to node B {}", e);
         }
 
@@ -161,7 +184,7 @@ mod tests {
 
         #[async_trait]
         impl MessageHandler for HandleMessageMDNS {
-            async fn handle_message(&self, event: GossipsubEvent) {
+            async fn handle_message(&self, event: GossipsubEvent) -> MessageAcceptance {
                 match event {
                     GossipsubEvent::Message {
                         propagation_source: _peer_id,
@@ -182,6 +205,7 @@ mod tests {
                     }
                     _ => log::info!("handler was not able to handle the event: {:?}", event),
                 }
+                MessageAcceptance::Accept
             }
         }
 
@@ -191,7 +215,7 @@ mod tests {
         //second node sending value
         let second_sending_val = "world mdns".as_bytes().to_vec();
 
-        let (first_n_event_sender, first_n_event_rec) = mpsc::channel::<Vec<u8>>(32);
+        let (first_n_event_sender, first_n_event_rec) = mpsc::channel::<OutboundMessage>(32);
         let topic = Topic::new("test_mdns");
 
         let first_node_handler_mdns = HandleMessageMDNS {
@@ -209,8 +233,12 @@ mod tests {
                 let _ = network_handler::run(
                     &topic_clone1,
                     first_n_event_rec,
+                    mpsc::channel::<NetworkCommand>(1).1,
+                    watch::channel(false).1,
                     &first_node_handler_mdns,
                     None,
+                    &[],
+                    None,
                     None,
                     "0",
                     (peer_id_1, id_keys_1),
@@ -219,7 +247,7 @@ mod tests {
             }));
         });
 
-        let (second_n_event_sender, second_n_event_rec) = mpsc::channel::<Vec<u8>>(32);
+        let (second_n_event_sender, second_n_event_rec) = mpsc::channel::<OutboundMessage>(32);
 
         let second_node_handler_mdns = HandleMessageMDNS {
             compare_val: first_sending_val.clone(),
@@ -235,8 +263,12 @@ mod tests {
                 let _ = network_handler::run(
                     &topic_clone2,
                     second_n_event_rec,
+                    mpsc::channel::<NetworkCommand>(1).1,
+                    watch::channel(false).1,
                     &second_node_handler_mdns,
                     None,
+                    &[],
+                    None,
                     None,
                     "0",
                     (peer_id_2, id_keys_2),
@@ -247,11 +279,17 @@ mod tests {
 
         tokio::time::sleep(Duration::from_millis(5000)).await;
 
-        if let Err(e) = first_n_event_sender.send(first_sending_val).await {
+        if let Err(e) = first_n_event_sender
+            .send(OutboundMessage::Broadcast(first_sending_val))
+            .await
+        {
             println!("error while sending value to node {}", e);
         };
 
-        if let Err(e) = second_n_event_sender.send(second_sending_val).await {
+        if let Err(e) = second_n_event_sender
+            .send(OutboundMessage::Broadcast(second_sending_val))
+            .await
+        {
             println!("error while sending value to node B {}", e);
         };
 
@@ -260,4 +298,189 @@ mod tests {
         //verifying number of nodes data verified should be 2
         assert_eq!(ARRAY_MDNS.lock().unwrap().len(), 2);
     }
+
+    /// Runs 2 nodes and sends a message from the second one to the first one only
+    /// run with cargo test -- tests::direct_message_to_peer --exact --nocapture to see detailed output
+    #[tokio::test]
+    async fn direct_message_to_peer() {
+        pub struct HandleDirectMessage;
+
+        #[async_trait]
+        impl MessageHandler for HandleDirectMessage {
+            async fn handle_message(&self, event: GossipsubEvent) -> MessageAcceptance {
+                log::info!("gossip not expected in direct test: {:?}", event);
+                MessageAcceptance::Ignore
+            }
+
+            async fn handle_direct_message(&self, peer_id: PeerId, data: Vec<u8>) {
+                ARRAY_DIRECT.lock().unwrap().push((peer_id, data));
+            }
+        }
+
+        let topic = Topic::new("test_direct");
+        let topic_clone1 = topic.clone();
+        let topic_clone2 = topic.clone();
+
+        let (_first_n_event_sender, first_n_event_rec) = mpsc::channel::<OutboundMessage>(32);
+        let (peer_id_1, id_keys_1) = get_node_identity(true);
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let _ = runtime.block_on(runtime.spawn(async move {
+                let _ = network_handler::run(
+                    &topic_clone1,
+                    first_n_event_rec,
+                    mpsc::channel::<NetworkCommand>(1).1,
+                    watch::channel(false).1,
+                    &HandleDirectMessage,
+                    None,
+                    &[],
+                    None,
+                    None,
+                    "39920",
+                    (peer_id_1, id_keys_1),
+                )
+                .await;
+            }));
+        });
+
+        tokio::time::sleep(Duration::from_millis(2000)).await;
+
+        let (second_n_event_sender, second_n_event_rec) = mpsc::channel::<OutboundMessage>(32);
+        let (peer_id_2, id_keys_2) = get_node_identity(true);
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let _ = runtime.block_on(runtime.spawn(async move {
+                let _ = network_handler::run(
+                    &topic_clone2,
+                    second_n_event_rec,
+                    mpsc::channel::<NetworkCommand>(1).1,
+                    watch::channel(false).1,
+                    &HandleDirectMessage,
+                    None,
+                    &["/ip4/127.0.0.1/tcp/39920".parse().unwrap()],
+                    None,
+                    None,
+                    "39931",
+                    (peer_id_2, id_keys_2),
+                )
+                .await;
+            }));
+        });
+
+        tokio::time::sleep(Duration::from_millis(5000)).await;
+
+        let message = OutboundMessage::Direct {
+            peer_id: peer_id_1.to_string(),
+            data: "only for node one".as_bytes().to_vec(),
+        };
+        if let Err(e) = second_n_event_sender.send(message).await {
+            println!("error while sending value to node B {}", e);
+        }
+
+        tokio::time::sleep(Duration::from_millis(3000)).await;
+
+        let received = ARRAY_DIRECT.lock().unwrap().clone();
+        assert_eq!(
+            received,
+            vec![(peer_id_2, "only for node one".as_bytes().to_vec())]
+        );
+    }
+
+    /// Runs 2 nodes and asks the second one about its connections
+    /// run with cargo test -- tests::network_status_lists_connected_peers --exact --nocapture to see detailed output
+    #[tokio::test]
+    async fn network_status_lists_connected_peers() {
+        let topic_clone1 = Topic::new("status_test");
+        let topic_clone2 = topic_clone1.clone();
+
+        let (_first_n_event_sender, first_n_event_rec) = mpsc::channel::<OutboundMessage>(32);
+        let (peer_id_1, id_keys_1) = get_node_identity(true);
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let _ = runtime.block_on(runtime.spawn(async move {
+                let _ = network_handler::run(
+                    &topic_clone1,
+                    first_n_event_rec,
+                    mpsc::channel::<NetworkCommand>(1).1,
+                    watch::channel(false).1,
+                    &DummyHandleMessage,
+                    None,
+                    &[],
+                    None,
+                    None,
+                    "39940",
+                    (peer_id_1, id_keys_1),
+                )
+                .await;
+            }));
+        });
+
+        tokio::time::sleep(Duration::from_millis(2000)).await;
+
+        let (_second_n_event_sender, second_n_event_rec) = mpsc::channel::<OutboundMessage>(32);
+        let (command_sender, command_rec) = mpsc::channel::<NetworkCommand>(1);
+        let (peer_id_2, id_keys_2) = get_node_identity(true);
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let _ = runtime.block_on(runtime.spawn(async move {
+                let _ = network_handler::run(
+                    &topic_clone2,
+                    second_n_event_rec,
+                    command_rec,
+                    watch::channel(false).1,
+                    &DummyHandleMessage,
+                    None,
+                    &["/ip4/127.0.0.1/tcp/39940".parse().unwrap()],
+                    None,
+                    None,
+                    "39951",
+                    (peer_id_2, id_keys_2),
+                )
+                .await;
+            }));
+        });
+
+        tokio::time::sleep(Duration::from_millis(5000)).await;
+
+        let (reply, status) = oneshot::channel();
+        command_sender
+            .send(NetworkCommand::Status(reply))
+            .await
+            .unwrap();
+        let status = status.await.unwrap();
+
+        assert_eq!(status.local_peer_id, peer_id_2.to_base58());
+        assert!(status
+            .listen_addresses
+            .iter()
+            .any(|addr| addr.ends_with("/tcp/39951")));
+        let peer = status
+            .peers
+            .iter()
+            .find(|peer| peer.peer_id == peer_id_1.to_base58())
+            .expect("first node is connected");
+        assert!(peer
+            .addresses
+            .iter()
+            .any(|addr| addr.ends_with("/tcp/39940")));
+        assert_eq!(status.mesh.len(), 1);
+        assert_eq!(status.mesh[0].topic, "status_test");
+    }
+
+    #[test]
+    fn parses_bootnodes() {
+        let bootnodes = get_bootnodes(&[
+            "/ip4/127.0.0.1/tcp/52200/p2p/12D3KooWFEXXy8iJfWk3ZG5883GiebFpg1QbNCivXKSE5VZ9YkHF"
+                .into(),
+            " /dns4/boot.example.com/tcp/52200 ".into(),
+            "".into(),
+        ])
+        .unwrap();
+        assert_eq!(bootnodes.len(), 2);
+        assert_eq!(
+            bootnodes[1],
+            "/dns4/boot.example.com/tcp/52200".parse().unwrap()
+        );
+        assert!(get_bootnodes(&["127.0.0.1:52200".into()]).is_err());
+    }
 }
@@ -1,39 +1,60 @@
-use crate::get_bootnodes;
 use crate::utils::behaviour::{ComposedEvent, LocalNetworkBehaviour};
 use crate::utils::behaviour_protocols::{
-    build_gossip, build_identify, build_kademlia, build_mdns, build_ping,
+    build_direct, build_gossip, build_identify, build_kademlia, build_mdns, build_ping,
 };
+use crate::utils::committee::{is_allowed, Committee};
+use crate::utils::direct_protocol::{DirectRequest, DirectResponse};
+use crate::utils::peer_book::PeerBook;
 use crate::utils::transport::build_transport;
 
+use events::events::OutboundMessage;
+use events::network::{NetworkCommand, NetworkStatus, PeerStatus, TopicMesh};
 use libp2p::identity::Keypair;
 use libp2p::kad::store::MemoryStore;
 use libp2p::kad::{Kademlia, KademliaEvent, QueryResult};
 use libp2p::mdns::MdnsEvent;
 use libp2p::multiaddr::Protocol;
+use libp2p::request_response::{RequestId, RequestResponseEvent, RequestResponseMessage};
+use libp2p::swarm::dial_opts::DialOpts;
 use libp2p::{
     futures::StreamExt,
-    gossipsub::IdentTopic as Topic,
+    gossipsub::{GossipsubEvent, IdentTopic as Topic, MessageAcceptance},
     swarm::{SwarmBuilder, SwarmEvent},
     Multiaddr,
 };
 use libp2p::{identify, identity, ping, PeerId, Swarm};
 use message::gossip_message_handler::MessageHandler;
+use std::collections::HashMap;
 use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
 use tokio::sync::mpsc::Receiver;
+use tokio::sync::watch;
+
+/// sends of a direct message before it is dropped
+const MAX_DIRECT_ATTEMPTS: u32 = 3;
 
 /// Runs a new instance of tango node
 /// topic: context on which you want to publish events to other nodes
-/// recv: mpsc receiver to tell node to publish events
+/// recv: mpsc receiver to tell node to publish events or send them to a single peer
+/// commands: mpsc receiver of queries about the state of the network, e.g. connected peers
+/// shutdown: once it turns true the peer book is saved, connections are closed and run returns
 /// handler: external struct which implements MessageHandler to handle message
 /// explicit_peer: explicit address of a peer to add into gossip network
-/// seed_node: address of a node to dial explicitiy from swarm
+/// bootnodes: addresses of nodes to dial explicitly from swarm, see `get_bootnodes`
+/// peer_book: file of previously connected peers, dialed on start and updated while running
+/// committee: if provided only these peers can connect to the node
 /// listening_port: if provided node will try to start on this specific port
 pub async fn run(
     topic: &Topic,
-    mut recv: Receiver<Vec<u8>>,
+    mut recv: Receiver<OutboundMessage>,
+    mut commands: Receiver<NetworkCommand>,
+    mut shutdown: watch::Receiver<bool>,
     handler: &dyn MessageHandler,
     explicit_peer: Option<&str>,
-    seed_node: Option<&str>,
+    bootnodes: &[Multiaddr],
+    peer_book_path: Option<&Path>,
+    committee: Option<&Committee>,
     listening_port: &str,
     node_identity: (PeerId, Keypair),
 ) -> Result<(), Box<dyn Error>> {
@@ -53,11 +74,12 @@ pub async fn run(
     //swarm manager integration
     let mut swarm = {
         let behaviour = LocalNetworkBehaviour {
-            gossipsub: build_gossip(id_keys.clone())?,
+            gossipsub: build_gossip(id_keys.clone(), topic)?,
             mdns: build_mdns().await,
             kademlia: build_kademlia(peer_id.clone()),
             identify: build_identify(id_keys.public().clone()),
             ping: build_ping(),
+            direct: build_direct(),
             is_bootstrapped: false,
         };
 
@@ -89,17 +111,8 @@ pub async fn run(
     log::info!("listen_multiaddr is: {}", listen_multiaddr);
     swarm.listen_on(listen_multiaddr.parse().unwrap()).unwrap();
 
-    // Reach out to another node if specified
-    if let Some(to_dial) = seed_node {
-        let address: Multiaddr = to_dial.parse().expect("User to provide valid address.");
-        match swarm.dial(address.clone()) {
-            Ok(_) => log::info!("Dialed {:?}", address),
-            Err(e) => log::warn!("Dial {:?} failed: {:?}", address, e),
-        };
-    }
-
     //Bootstraping kademlia
-    for bootstrap in get_bootnodes() {
+    for bootstrap in bootnodes {
         log::info!("Adding {} as bootstrap", bootstrap);
         let mut addr = bootstrap.to_owned();
         let peer_id = match addr.pop() {
@@ -110,11 +123,19 @@ pub async fn run(
                     continue;
                 }
             },
+            // without a peer id the bootnode can only be dialed
             _ => {
-                log::warn!("Invalid peer id");
+                match swarm.dial(bootstrap.clone()) {
+                    Ok(_) => log::info!("Dialed {:?}", bootstrap),
+                    Err(e) => log::warn!("Dial {:?} failed: {:?}", bootstrap, e),
+                };
                 continue;
             }
         };
+        if !is_allowed(committee, &peer_id) {
+            log::warn!("Bootnode {} is not in the committee", peer_id);
+            continue;
+        }
         swarm
             .behaviour_mut()
             .kademlia
@@ -132,6 +153,33 @@ pub async fn run(
         }
     }
 
+    //Reconnecting to peers known from previous runs
+    let mut peer_book = match peer_book_path.map(PeerBook::load).transpose() {
+        Ok(peer_book) => peer_book.unwrap_or_default(),
+        Err(e) => {
+            log::warn!(
+                "Unable to read peer book, starting with an empty one: {}",
+                e
+            );
+            PeerBook::default()
+        }
+    };
+    log::info!("Peer book: dialing {} known peers", peer_book.len());
+    for (known_peer, addresses) in peer_book.peers() {
+        if known_peer == peer_id || addresses.is_empty() || !is_allowed(committee, &known_peer) {
+            continue;
+        }
+        for addr in addresses.iter() {
+            swarm
+                .behaviour_mut()
+                .kademlia
+                .add_address(&known_peer, addr.clone());
+        }
+        if let Err(e) = swarm.dial(DialOpts::peer_id(known_peer).addresses(addresses).build()) {
+            log::warn!("Peer book: dial of {} failed: {}", known_peer, e);
+        }
+    }
+
     // Bootstrap
     if let Err(e) = swarm.behaviour_mut().kademlia.bootstrap() {
         log::warn!("Failed to bootstrap node with error {}", e);
@@ -140,18 +188,59 @@ pub async fn run(
         log::info!("Bootstrap Done");
     }
 
+    //direct messages in flight with their attempts, sent again if they cannot be delivered
+    let mut pending_direct: HashMap<RequestId, (Vec<u8>, u32)> = HashMap::new();
+
+    //peers with open connections, reported on status queries
+    let mut connected: HashMap<PeerId, ConnectedPeer> = HashMap::new();
+    let mut closing = false;
+
     loop {
         tokio::select! {
             //received a message from inner program to execute message
             r = recv.recv() => {
-                if let Some(data) = r {
-                    if let Err(e) = swarm
-                    .behaviour_mut()
-                    .gossipsub
-                    // message format will be topic:message
-                    .publish((topic).clone(), data)
-                    {
-                        log::info!("Publish error: {:?}", e);
+                match r {
+                    Some(OutboundMessage::Broadcast(data)) => publish(&mut swarm, topic, data),
+                    Some(OutboundMessage::Direct { peer_id, data }) => match peer_id.parse::<PeerId>() {
+                        Ok(peer) => {
+                            let request_id = swarm
+                                .behaviour_mut()
+                                .direct
+                                .send_request(&peer, DirectRequest(data.clone()));
+                            pending_direct.insert(request_id, (data, 1));
+                        }
+                        Err(e) => {
+                            log::error!("Direct: dropping message to invalid peer id {}: {:?}", peer_id, e);
+                        }
+                    },
+                    None => {}
+                }
+            },
+
+            //node is shutting down, waiting for connections to close
+            Ok(()) = shutdown.changed(), if !closing => {
+                if *shutdown.borrow() {
+                    log::info!("Network: shutting down, closing {} connections", connected.len());
+                    if let Some(path) = peer_book_path {
+                        if let Err(e) = peer_book.save(path) {
+                            log::warn!("Peer book: unable to save: {}", e);
+                        }
+                    }
+                    if connected.is_empty() {
+                        return Ok(());
+                    }
+                    closing = true;
+                    for peer in connected.keys().copied().collect::<Vec<PeerId>>() {
+                        let _ = swarm.disconnect_peer_id(peer);
+                    }
+                }
+            },
+
+            //received a query about the network
+            Some(command) = commands.recv() => {
+                match command {
+                    NetworkCommand::Status(reply) => {
+                        let _ = reply.send(network_status(&swarm, &connected));
                     }
                 }
             },
@@ -166,7 +255,103 @@ pub async fn run(
 
                 //Handling gossip behaviour => received msg from network
                 SwarmEvent::Behaviour(ComposedEvent::Gossipsub(message)) => {
-                    let _ = handler.handle_message(message).await;
+                    let received = match &message {
+                        GossipsubEvent::Message { propagation_source, message_id, .. } => {
+                            Some((*propagation_source, message_id.clone()))
+                        }
+                        _ => None,
+                    };
+                    let acceptance = match &received {
+                        Some((propagation_source, _)) if !is_allowed(committee, propagation_source) => {
+                            log::warn!("Committee: dropping message of unknown peer {}", propagation_source);
+                            MessageAcceptance::Reject
+                        }
+                        _ => handler.handle_message(message).await,
+                    };
+
+                    //message is only forwarded to other peers once accepted
+                    if let Some((propagation_source, message_id)) = received {
+                        if let Err(e) = swarm.behaviour_mut().gossipsub.report_message_validation_result(
+                            &message_id,
+                            &propagation_source,
+                            acceptance,
+                        ) {
+                            log::warn!("Gossip: unable to report validation of {}: {:?}", message_id, e);
+                        }
+                    }
+                },
+
+                //message addressed to this node
+                SwarmEvent::Behaviour(ComposedEvent::Direct(event)) => {
+                    match event {
+                        RequestResponseEvent::Message { peer, message } => match message {
+                            RequestResponseMessage::Request { request, channel, .. } => {
+                                if !is_allowed(committee, &peer) {
+                                    log::warn!("Committee: dropping direct message of unknown peer {}", peer);
+                                    continue;
+                                }
+                                handler.handle_direct_message(peer, request.0).await;
+                                if swarm.behaviour_mut().direct.send_response(channel, DirectResponse).is_err() {
+                                    log::warn!("Direct: unable to acknowledge message of {}", peer);
+                                }
+                            },
+                            RequestResponseMessage::Response { request_id, .. } => {
+                                pending_direct.remove(&request_id);
+                            },
+                        },
+                        RequestResponseEvent::OutboundFailure { peer, request_id, error } => {
+                            match pending_direct.remove(&request_id) {
+                                Some((data, attempts)) if attempts < MAX_DIRECT_ATTEMPTS => {
+                                    log::warn!("Direct: sending to {} failed: {:?}, attempt {} of {}", peer, error, attempts, MAX_DIRECT_ATTEMPTS);
+                                    let request_id = swarm
+                                        .behaviour_mut()
+                                        .direct
+                                        .send_request(&peer, DirectRequest(data.clone()));
+                                    pending_direct.insert(request_id, (data, attempts + 1));
+                                }
+                                Some(_) => {
+                                    log::error!("Direct: dropping message to {} after {} attempts: {:?}", peer, MAX_DIRECT_ATTEMPTS, error);
+                                }
+                                None => {
+                                    log::warn!("Direct: sending to {} failed: {:?}", peer, error);
+                                }
+                            }
+                        },
+                        RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                            log::warn!("Direct: receiving from {} failed: {:?}", peer, error);
+                        },
+                        RequestResponseEvent::ResponseSent { .. } => {},
+                    }
+                },
+
+                //only committee members may stay connected
+                SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                    if !is_allowed(committee, &peer_id) {
+                        log::warn!("Committee: disconnecting unknown peer {}", peer_id);
+                        swarm.behaviour_mut().kademlia.remove_peer(&peer_id);
+                        let _ = swarm.disconnect_peer_id(peer_id);
+                    } else {
+                        connected
+                            .entry(peer_id)
+                            .or_default()
+                            .addresses
+                            .push(endpoint.get_remote_address().clone());
+                    }
+                },
+
+                SwarmEvent::ConnectionClosed { peer_id, endpoint, num_established, .. } => {
+                    if num_established == 0 {
+                        connected.remove(&peer_id);
+                        if closing && connected.is_empty() {
+                            log::info!("Network: all connections closed");
+                            return Ok(());
+                        }
+                    } else if let Some(peer) = connected.get_mut(&peer_id) {
+                        let address = endpoint.get_remote_address();
+                        if let Some(index) = peer.addresses.iter().position(|known| known == address) {
+                            peer.addresses.remove(index);
+                        }
+                    }
                 },
 
                 SwarmEvent::OutgoingConnectionError{peer_id, ..} => {
@@ -180,6 +365,9 @@ pub async fn run(
                     match event {
                         MdnsEvent::Discovered(nodes)=>{
                             for (peer_id, multiaddrr) in nodes {
+                                if !is_allowed(committee, &peer_id) {
+                                    continue;
+                                }
                                 swarm.behaviour_mut().kademlia.add_address(&peer_id, multiaddrr);
                                 swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
                             }
@@ -205,11 +393,20 @@ pub async fn run(
                 //adding detailed got by identify protocol in kademlia dht
                 SwarmEvent::Behaviour(ComposedEvent::Identify(event)) => {
                     match event{
-                        identify::IdentifyEvent::Received { peer_id, info } => {
+                        identify::IdentifyEvent::Received { peer_id, info } if is_allowed(committee, &peer_id) => {
+                            let mut changed = false;
                             for addr in info.listen_addrs{
+                                changed |= peer_book.insert(&peer_id, &addr);
                                 swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
                             }
                             log::info!("Identify: received peer id: {}", peer_id);
+
+                            //remembering peer so it is dialed again after a restart
+                            if let (true, Some(path)) = (changed, peer_book_path) {
+                                if let Err(e) = peer_book.save(path) {
+                                    log::warn!("Peer book: unable to save: {}", e);
+                                }
+                            }
                         },
                         _ => {}
                     }
@@ -230,6 +427,14 @@ pub async fn run(
                             log::warn!("ping: timeout to {}", peer);
                             swarm.behaviour_mut().kademlia.remove_peer(&peer);
                         }
+                        ping::PingEvent {
+                            peer,
+                            result: Result::Ok(ping::PingSuccess::Ping { rtt }),
+                        } => {
+                            if let Some(connected_peer) = connected.get_mut(&peer) {
+                                connected_peer.ping_rtt = Some(rtt);
+                            }
+                        }
                         _ => {}
                     }
                 },
@@ -241,6 +446,64 @@ pub async fn run(
     }
 }
 
+/// open connections of a peer
+#[derive(Debug, Default)]
+struct ConnectedPeer {
+    addresses: Vec<Multiaddr>,
+    ping_rtt: Option<Duration>,
+}
+
+///answers a status query from the swarm and the connections seen so far
+fn network_status(
+    swarm: &Swarm<LocalNetworkBehaviour>,
+    connected: &HashMap<PeerId, ConnectedPeer>,
+) -> NetworkStatus {
+    let mut peers: Vec<PeerStatus> = connected
+        .iter()
+        .map(|(peer_id, peer)| PeerStatus {
+            peer_id: peer_id.to_base58(),
+            addresses: peer.addresses.iter().map(|addr| addr.to_string()).collect(),
+            ping_rtt_ms: peer.ping_rtt.map(|rtt| rtt.as_millis() as u64),
+        })
+        .collect();
+    peers.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+
+    let gossipsub = &swarm.behaviour().gossipsub;
+    let mesh = gossipsub
+        .topics()
+        .map(|topic| {
+            let mut mesh_peers: Vec<String> = gossipsub
+                .mesh_peers(topic)
+                .map(|peer_id| peer_id.to_base58())
+                .collect();
+            mesh_peers.sort();
+            TopicMesh {
+                topic: topic.to_string(),
+                peers: mesh_peers,
+            }
+        })
+        .collect();
+
+    NetworkStatus {
+        local_peer_id: swarm.local_peer_id().to_base58(),
+        listen_addresses: swarm.listeners().map(|addr| addr.to_string()).collect(),
+        peers,
+        mesh,
+    }
+}
+
+///gossips data to every node subscribed to topic
+fn publish(swarm: &mut Swarm<LocalNetworkBehaviour>, topic: &Topic, data: Vec<u8>) {
+    if let Err(e) = swarm
+        .behaviour_mut()
+        .gossipsub
+        // message format will be topic:message
+        .publish(topic.clone(), data)
+    {
+        log::info!("Publish error: {:?}", e);
+    }
+}
+
 pub fn kad_event_handler(event: KademliaEvent, swarm: &mut Swarm<LocalNetworkBehaviour>) {
     match event {
         KademliaEvent::OutboundQueryCompleted { result, .. } => match result {
@@ -305,9 +568,9 @@ pub fn kad_event_handler(event: KademliaEvent, swarm: &mut Swarm<LocalNetworkBeh
 }
 
 ///list_peers for logging purpose
-pub fn list_peers(swarm: &mut Swarm<LocalNetworkBehaviour>) -> i8 {
+pub fn list_peers(swarm: &mut Swarm<LocalNetworkBehaviour>) -> usize {
     let kademlia: &mut Kademlia<MemoryStore> = &mut swarm.behaviour_mut().kademlia;
-    let mut count: i8 = 0;
+    let mut count: usize = 0;
     log::info!("================Kad Bucket=================");
     for bucket in kademlia.kbuckets() {
         if bucket.num_entries() > 0 {
@@ -1,9 +1,11 @@
+use crate::utils::direct_protocol::{DirectCodec, DirectRequest, DirectResponse};
 use libp2p::{
     gossipsub::{Gossipsub, GossipsubEvent},
     identify::{Identify, IdentifyEvent},
     kad::{store::MemoryStore, Kademlia, KademliaEvent},
     mdns::{Mdns, MdnsEvent},
     ping::{Ping, PingEvent},
+    request_response::{RequestResponse, RequestResponseEvent},
     NetworkBehaviour,
 };
 
@@ -15,6 +17,7 @@ pub enum ComposedEvent {
     Kademlia(KademliaEvent),
     Identify(IdentifyEvent),
     Ping(PingEvent),
+    Direct(RequestResponseEvent<DirectRequest, DirectResponse>),
 }
 
 ///Implementation of Network Behaviour that will be used in swarm to listen for events
@@ -26,6 +29,7 @@ pub struct LocalNetworkBehaviour {
     pub kademlia: Kademlia<MemoryStore>,
     pub identify: Identify,
     pub ping: Ping,
+    pub direct: RequestResponse<DirectCodec>,
     #[behaviour(ignore)]
     pub is_bootstrapped: bool,
 }
@@ -59,3 +63,9 @@ impl From<PingEvent> for ComposedEvent {
         ComposedEvent::Ping(event)
     }
 }
+
+impl From<RequestResponseEvent<DirectRequest, DirectResponse>> for ComposedEvent {
+    fn from(event: RequestResponseEvent<DirectRequest, DirectResponse>) -> Self {
+        ComposedEvent::Direct(event)
+    }
+}
@@ -1,39 +1,90 @@
+use crate::utils::direct_protocol::{DirectCodec, DirectProtocol};
 use libp2p::core::PublicKey;
-use libp2p::gossipsub::{Gossipsub, GossipsubMessage, MessageAuthenticity, MessageId};
+use libp2p::gossipsub::{
+    score_parameter_decay, Gossipsub, GossipsubMessage, IdentTopic as Topic, MessageAuthenticity,
+    MessageId, PeerScoreParams, PeerScoreThresholds, TopicScoreParams, ValidationMode,
+};
 use libp2p::identify::{Identify, IdentifyConfig};
 use libp2p::kad::store::MemoryStore;
 use libp2p::kad::{Kademlia, KademliaConfig, KademliaStoreInserts};
 use libp2p::mdns::{Mdns, MdnsConfig};
 use libp2p::ping::{Ping, PingConfig};
+use libp2p::request_response::{ProtocolSupport, RequestResponse, RequestResponseConfig};
 use libp2p::{gossipsub, identity, PeerId};
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
-use std::io::{self};
+use sha2::{Digest, Sha256};
+use std::io::{self, Error, ErrorKind};
 use std::time::Duration;
 
-///builds messaging protocol based on gossip
-pub fn build_gossip(local_key: identity::Keypair) -> io::Result<Gossipsub> {
-    let message_id_fn = |message: &GossipsubMessage| {
-        let mut s = DefaultHasher::new();
-        message.data.hash(&mut s);
-        MessageId::from(s.finish().to_string())
-    };
+const GOSSIP_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
 
+/// SHA-256 of the message data, so nobody can make two messages share an id
+pub fn message_id(message: &GossipsubMessage) -> MessageId {
+    MessageId::from(Sha256::digest(&message.data).to_vec())
+}
+
+///builds messaging protocol based on gossip
+/// messages are only forwarded once the message handler accepted them
+pub fn build_gossip(local_key: identity::Keypair, topic: &Topic) -> io::Result<Gossipsub> {
     // Set a custom gossipsub
     let gossipsub_config = gossipsub::GossipsubConfigBuilder::default()
-        .heartbeat_interval(Duration::from_secs(10)) // This is set to aid debugging by not cluttering the log space
-        .message_id_fn(message_id_fn) // content-address messages. No two messages of the
+        .heartbeat_interval(GOSSIP_HEARTBEAT_INTERVAL) // This is set to aid debugging by not cluttering the log space
+        .message_id_fn(message_id) // content-address messages. No two messages of the
         // same content will be propagated.
+        .validation_mode(ValidationMode::Strict)
+        .validate_messages()
         .build()
         .expect("Valid config");
 
-    let gossipsub: Gossipsub =
+    let mut gossipsub: Gossipsub =
         gossipsub::Gossipsub::new(MessageAuthenticity::Signed(local_key), gossipsub_config)
             .expect("Correct configuration");
 
+    let (score_params, score_thresholds) = build_peer_score(topic);
+    gossipsub
+        .with_peer_score(score_params, score_thresholds)
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+
     Ok(gossipsub)
 }
 
+/// Peer scoring for the tss topic. Traffic is bursty, keygen and signing rounds only,
+/// so missing deliveries are not penalized while every rejected message is, for about an hour.
+pub fn build_peer_score(topic: &Topic) -> (PeerScoreParams, PeerScoreThresholds) {
+    let topic_params = TopicScoreParams {
+        topic_weight: 1.0,
+        // up to 1 point for an hour in the mesh
+        time_in_mesh_weight: 1.0 / 360.0,
+        time_in_mesh_quantum: GOSSIP_HEARTBEAT_INTERVAL,
+        time_in_mesh_cap: 360.0,
+        // up to 10 points for delivering messages first
+        first_message_deliveries_weight: 0.2,
+        first_message_deliveries_decay: score_parameter_decay(Duration::from_secs(600)),
+        first_message_deliveries_cap: 50.0,
+        mesh_message_deliveries_weight: 0.0,
+        mesh_failure_penalty_weight: 0.0,
+        // penalty is squared, 3 rejected messages graylist a peer
+        invalid_message_deliveries_weight: -10.0,
+        invalid_message_deliveries_decay: score_parameter_decay(Duration::from_secs(3600)),
+        ..Default::default()
+    };
+
+    let mut params = PeerScoreParams {
+        topic_score_cap: 20.0,
+        ..Default::default()
+    };
+    params.topics.insert(topic.hash(), topic_params);
+
+    let thresholds = PeerScoreThresholds {
+        gossip_threshold: -10.0,
+        publish_threshold: -50.0,
+        graylist_threshold: -80.0,
+        accept_px_threshold: 10.0,
+        opportunistic_graft_threshold: 5.0,
+    };
+
+    (params, thresholds)
+}
+
 ///builds mdns behaviour to be use in swarm
 pub async fn build_mdns() -> Mdns {
     let mdns = Mdns::new(MdnsConfig::default()).await.unwrap();
@@ -66,3 +117,40 @@ pub fn build_identify(local_public_key: PublicKey) -> Identify {
         local_public_key,
     ))
 }
+
+///builds request response behaviour for messages addressed to one peer
+pub fn build_direct() -> RequestResponse<DirectCodec> {
+    let mut config = RequestResponseConfig::default();
+    config.set_request_timeout(Duration::from_secs(30));
+    RequestResponse::new(
+        DirectCodec,
+        std::iter::once((DirectProtocol, ProtocolSupport::Full)),
+        config,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_score_is_valid() {
+        let (params, thresholds) = build_peer_score(&Topic::new("event_parcel"));
+        assert!(params.validate().is_ok());
+        assert!(thresholds.validate().is_ok());
+    }
+
+    #[test]
+    fn message_id_is_sha256_of_data() {
+        let message = |data: &[u8]| GossipsubMessage {
+            source: None,
+            data: data.to_vec(),
+            sequence_number: None,
+            topic: Topic::new("event_parcel").hash(),
+        };
+        let id = message_id(&message(b"event"));
+        assert_eq!(id, message_id(&message(b"event")));
+        assert_ne!(id, message_id(&message(b"other event")));
+        assert_eq!(id.0, Sha256::digest(b"event").to_vec());
+    }
+}
@@ -0,0 +1,97 @@
+use libp2p::PeerId;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Peers of a permissioned committee, the only ones this node connects to and runs keygen with.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Committee {
+    members: HashSet<PeerId>,
+}
+
+impl Committee {
+    pub fn new(members: impl IntoIterator<Item = PeerId>) -> Self {
+        Committee {
+            members: members.into_iter().collect(),
+        }
+    }
+
+    /// Reads a committee file with one peer id per line, blank lines and `#` comments are skipped.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut members = HashSet::new();
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let peer_id = PeerId::from_str(line)
+                .map_err(|e| format!("invalid peer id on line {}: {}", number + 1, e))?;
+            members.insert(peer_id);
+        }
+        if members.is_empty() {
+            return Err(format!("committee file {} has no peers", path.display()).into());
+        }
+        Ok(Committee { members })
+    }
+
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        self.members.contains(peer_id)
+    }
+
+    /// members as used by the tss
+    pub fn peer_ids(&self) -> HashSet<String> {
+        self.members
+            .iter()
+            .map(|peer_id| peer_id.to_base58())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+}
+
+/// every peer is allowed without a committee
+pub fn is_allowed(committee: Option<&Committee>, peer_id: &PeerId) -> bool {
+    committee.map_or(true, |committee| committee.contains(peer_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::identity;
+
+    fn random_peer() -> PeerId {
+        PeerId::from(identity::Keypair::generate_ed25519().public())
+    }
+
+    #[test]
+    fn loads_committee_file() {
+        let (member, other) = (random_peer(), random_peer());
+        let path = std::env::temp_dir().join(format!("tango-committee-{}", member));
+        fs::write(
+            &path,
+            format!("# tango committee\n\n{}  # node one\n", member),
+        )
+        .unwrap();
+
+        let committee = Committee::load(&path).unwrap();
+        assert_eq!(committee, Committee::new([member]));
+        assert!(is_allowed(Some(&committee), &member));
+        assert!(!is_allowed(Some(&committee), &other));
+        assert!(is_allowed(None, &other));
+
+        fs::write(&path, "# nobody\n").unwrap();
+        assert!(Committee::load(&path).is_err());
+        fs::write(&path, "not-a-peer-id\n").unwrap();
+        assert!(Committee::load(&path).is_err());
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,86 @@
+use async_trait::async_trait;
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName};
+use libp2p::futures::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use libp2p::request_response::RequestResponseCodec;
+use std::io;
+
+/// Largest message accepted from a peer.
+const MAX_DIRECT_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Protocol for messages addressed to a single peer, e.g. secret shares and partial signatures.
+#[derive(Debug, Clone)]
+pub struct DirectProtocol;
+
+impl ProtocolName for DirectProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/tango/direct/1.0.0"
+    }
+}
+
+/// same encoded event that would otherwise be gossiped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectRequest(pub Vec<u8>);
+
+/// empty acknowledgement, sent once the request was handed to the message handler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectResponse;
+
+/// Length prefixed requests, the response carries no data.
+#[derive(Debug, Clone, Default)]
+pub struct DirectCodec;
+
+#[async_trait]
+impl RequestResponseCodec for DirectCodec {
+    type Protocol = DirectProtocol;
+    type Request = DirectRequest;
+    type Response = DirectResponse;
+
+    async fn read_request<T>(&mut self, _: &DirectProtocol, io: &mut T) -> io::Result<DirectRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_length_prefixed(io, MAX_DIRECT_MESSAGE_SIZE).await?;
+        if data.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(DirectRequest(data))
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _: &DirectProtocol,
+        io: &mut T,
+    ) -> io::Result<DirectResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_length_prefixed(io, 0).await?;
+        Ok(DirectResponse)
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &DirectProtocol,
+        io: &mut T,
+        DirectRequest(data): DirectRequest,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, data).await?;
+        io.close().await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &DirectProtocol,
+        io: &mut T,
+        _: DirectResponse,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, []).await?;
+        io.close().await
+    }
+}
@@ -137,6 +137,35 @@ fn validate_path(path: &str) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Path of the identity file, ~/tango/identity/identity.json
+pub fn identity_path() -> Result<String, Box<dyn Error>> {
+    get_keypair_path().ok_or_else(|| "Unable to get home path".into())
+}
+
+/// Reads the identity of this node without creating one.
+pub fn read_identity() -> Result<(PeerId, Keypair), Box<dyn Error>> {
+    let keypair_path = identity_path()?;
+    if !Path::new(&keypair_path).is_file() {
+        return Err(format!("No identity at {}", keypair_path).into());
+    }
+    read_config_from_file(Path::new(&keypair_path))
+}
+
+/// Creates a new identity and saves it, an existing identity is only replaced with `overwrite`
+/// since the node gets a new peer id.
+pub fn generate_identity(overwrite: bool) -> Result<PeerId, Box<dyn Error>> {
+    let keypair_path = identity_path()?;
+    validate_path(&keypair_path)?;
+    if Path::new(&keypair_path).is_file() && !overwrite {
+        return Err(format!("Identity already exists at {}", keypair_path).into());
+    }
+
+    let (local_peer_id, local_keypair) = create_new_identity();
+    let config = Zeroizing::new(Config::from_key_material(local_peer_id, &local_keypair)?);
+    write_config_to_file(&keypair_path, &config)?;
+    Ok(local_peer_id)
+}
+
 pub fn get_node_identity(new_node: bool) -> (PeerId, Keypair) {
     if new_node {
         // Create a random PeerId
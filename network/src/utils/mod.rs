@@ -1,4 +1,7 @@
 pub mod behaviour;
 pub mod behaviour_protocols;
+pub mod committee;
+pub mod direct_protocol;
 pub mod identity_handler;
+pub mod peer_book;
 pub mod transport;
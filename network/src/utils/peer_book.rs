@@ -0,0 +1,154 @@
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Peers kept in the book, the peer seen longest ago is dropped first.
+pub const MAX_PEERS: usize = 256;
+
+/// Addresses kept per peer, the oldest address is dropped first.
+pub const MAX_ADDRESSES_PER_PEER: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct PeerEntry {
+    peer_id: String,
+    addresses: Vec<String>,
+}
+
+/// Peers this node connected to with their listen addresses, persisted so a restarted
+/// node reconnects to the network without any bootnode.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerBook {
+    /// least recently seen peer first
+    peers: Vec<PeerEntry>,
+}
+
+impl PeerBook {
+    /// Reads the book from `path`, an empty book if there is none yet.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.is_file() {
+            return Ok(PeerBook::default());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the book to a temporary file first, so a crash never leaves half a book behind.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Records an address of `peer_id` and marks the peer as most recently seen.
+    /// Returns whether the book changed.
+    pub fn insert(&mut self, peer_id: &PeerId, address: &Multiaddr) -> bool {
+        let peer_id = peer_id.to_base58();
+        let address = address.to_string();
+
+        let position = self.peers.iter().position(|entry| entry.peer_id == peer_id);
+        let mut entry = match position {
+            Some(position) => self.peers.remove(position),
+            None => PeerEntry {
+                peer_id,
+                addresses: vec![],
+            },
+        };
+        let changed = position != Some(self.peers.len()) || !entry.addresses.contains(&address);
+
+        entry.addresses.retain(|known| known != &address);
+        entry.addresses.push(address);
+        if entry.addresses.len() > MAX_ADDRESSES_PER_PEER {
+            entry.addresses.remove(0);
+        }
+        self.peers.push(entry);
+        if self.peers.len() > MAX_PEERS {
+            self.peers.remove(0);
+        }
+        changed
+    }
+
+    /// Peers with their addresses, entries that no longer parse are skipped.
+    pub fn peers(&self) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        self.peers
+            .iter()
+            .filter_map(|entry| {
+                let peer_id = PeerId::from_str(&entry.peer_id).ok()?;
+                let addresses = entry
+                    .addresses
+                    .iter()
+                    .filter_map(|address| address.parse().ok())
+                    .collect();
+                Some((peer_id, addresses))
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+}
+
+/// Peer book next to the node identity, ~/tango/network/peers.json
+pub fn default_peer_book_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join("tango").join("network").join("peers.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::identity;
+
+    fn random_peer() -> PeerId {
+        PeerId::from(identity::Keypair::generate_ed25519().public())
+    }
+
+    #[test]
+    fn keeps_most_recent_peers_and_addresses() {
+        let mut book = PeerBook::default();
+        let peer = random_peer();
+        let address: Multiaddr = "/ip4/10.0.0.1/tcp/30333".parse().unwrap();
+        assert!(book.insert(&peer, &address));
+        assert!(!book.insert(&peer, &address));
+
+        for port in 0..MAX_ADDRESSES_PER_PEER as u16 {
+            let other: Multiaddr = format!("/dns4/node.example.com/tcp/{}", port)
+                .parse()
+                .unwrap();
+            book.insert(&peer, &other);
+        }
+        let (_, addresses) = &book.peers()[0];
+        assert_eq!(addresses.len(), MAX_ADDRESSES_PER_PEER);
+        assert!(!addresses.contains(&address));
+
+        for _ in 0..MAX_PEERS {
+            book.insert(&random_peer(), &address);
+        }
+        assert_eq!(book.len(), MAX_PEERS);
+        assert!(book.peers().iter().all(|(known, _)| known != &peer));
+    }
+
+    #[test]
+    fn survives_restart() {
+        let path = std::env::temp_dir()
+            .join(format!("tango-peer-book-{}", random_peer()))
+            .join("peers.json");
+        assert!(PeerBook::load(&path).unwrap().is_empty());
+
+        let mut book = PeerBook::default();
+        book.insert(&random_peer(), &"/ip4/10.0.0.1/tcp/30333".parse().unwrap());
+        book.save(&path).unwrap();
+        assert_eq!(PeerBook::load(&path).unwrap(), book);
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -9,11 +9,14 @@ use database::{
     models::{ContractJson, TokenSwap},
     MongoRepo,
 };
+use events::network::NetworkCommand;
+use events::tss::TssCommand;
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, watch, Mutex, Notify};
 // use std::sync::Mutex;
 use lazy_static::lazy_static;
 
@@ -50,6 +53,8 @@ struct IdInterface {
 }
 
 const MAX_SIZE: usize = 262_144;
+/// how long the network or tss loop may take to answer a status query
+const STATUS_TIMEOUT: Duration = Duration::from_secs(5);
 // #[get("/eventold")]
 // async fn eventsOld() -> impl Responder {
 //     // TODO get the event from db_conn
@@ -167,9 +172,20 @@ async fn get_tokens(db_conn: web::Data<Arc<Mutex<database::MongoRepo>>>) -> impl
     }
 }
 
+/// Handovers of node accounts to new keys, for mapping historic signatures to their key
+#[get("/account/rotations")]
+async fn get_key_rotations(db_conn: web::Data<Arc<Mutex<database::MongoRepo>>>) -> impl Responder {
+    let conn = db_conn.lock().await;
+    match MongoRepo::get_key_rotations(&conn).await {
+        Ok(rotations) => HttpResponse::Ok().json(rotations),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
 #[post("/contractjson")]
 async fn contractjson(
     db_conn: web::Data<Arc<Mutex<database::MongoRepo>>>,
+    contracts_changed: web::Data<Arc<Notify>>,
     mut payload: web::Payload,
 ) -> impl Responder {
     let mut body = web::BytesMut::new();
@@ -192,6 +208,8 @@ async fn contractjson(
         Ok(response_str) => response_str,
         Err(_) => todo!(),
     };
+    // contract listeners reload the contracts
+    contracts_changed.notify_one();
 
     match serde_json::to_string(&data.inserted_ids) {
         Ok(response_str) => Ok(response_str),
@@ -315,6 +333,7 @@ async fn tokens(
 #[post("/removeContract")]
 async fn remove_contract(
     db_conn: web::Data<Arc<Mutex<database::MongoRepo>>>,
+    contracts_changed: web::Data<Arc<Notify>>,
     mut payload: web::Payload,
 ) -> impl Responder {
     let mut body = web::BytesMut::new();
@@ -337,6 +356,8 @@ async fn remove_contract(
         Ok(response_str) => response_str,
         Err(_) => todo!(),
     };
+    // contract listeners reload the contracts
+    contracts_changed.notify_one();
 
     match serde_json::to_string(&data.deleted_count) {
         Ok(response_str) => Ok(response_str),
@@ -399,17 +420,56 @@ async fn exchangerates(
     return Ok(result);
 }
 
+#[get("/network/status")]
+async fn network_status(
+    network_commands: web::Data<mpsc::Sender<NetworkCommand>>,
+) -> impl Responder {
+    let (reply, status) = oneshot::channel();
+    if network_commands
+        .send(NetworkCommand::Status(reply))
+        .await
+        .is_err()
+    {
+        return HttpResponse::ServiceUnavailable().body("network is not running");
+    }
+
+    match tokio::time::timeout(STATUS_TIMEOUT, status).await {
+        Ok(Ok(status)) => HttpResponse::Ok().json(status),
+        _ => HttpResponse::ServiceUnavailable().body("network did not answer"),
+    }
+}
+
+#[get("/tss/status")]
+async fn tss_status(tss_commands: web::Data<mpsc::Sender<TssCommand>>) -> impl Responder {
+    let (reply, status) = oneshot::channel();
+    if tss_commands.send(TssCommand::Status(reply)).await.is_err() {
+        return HttpResponse::ServiceUnavailable().body("tss is not running");
+    }
+
+    match tokio::time::timeout(STATUS_TIMEOUT, status).await {
+        Ok(Ok(status)) => HttpResponse::Ok().json(status),
+        _ => HttpResponse::ServiceUnavailable().body("tss did not answer"),
+    }
+}
+
 #[get("/")]
 async fn echo() -> impl Responder {
     HttpResponse::Ok().body("server is live")
 }
 
+/// Runs the http server until `shutdown` turns true, requests in progress then get
+/// `shutdown_grace` to finish.
 pub async fn start_server(
     db_conn: Arc<Mutex<database::MongoRepo>>,
+    contracts_changed: Arc<Notify>,
+    network_commands: mpsc::Sender<NetworkCommand>,
+    tss_commands: mpsc::Sender<TssCommand>,
     ip: String,
     port: u16,
     origin: String,
     workers_num: usize,
+    mut shutdown: watch::Receiver<bool>,
+    shutdown_grace: Duration,
 ) -> Result<(), std::io::Error> {
     // let args = Args::parse();
 
@@ -429,6 +489,9 @@ pub async fn start_server(
         App::new()
             .wrap(cors)
             .app_data(web::Data::new(db_conn.clone()))
+            .app_data(web::Data::new(contracts_changed.clone()))
+            .app_data(web::Data::new(network_commands.clone()))
+            .app_data(web::Data::new(tss_commands.clone()))
             .service(events)
             .service(get_contracts)
             .service(contractjson)
@@ -441,11 +504,28 @@ pub async fn start_server(
             .service(get_swap_events)
             .service(tokens)
             .service(get_tokens)
+            .service(get_key_rotations)
+            .service(network_status)
+            .service(tss_status)
             .service(echo)
     })
     .workers(workers_num)
+    // the node handles signals and stops every subsystem
+    .disable_signals()
+    .shutdown_timeout(shutdown_grace.as_secs())
     .bind((ip, port))?
     .run();
-    server.await.unwrap();
-    Ok(())
+
+    let handle = server.handle();
+    tokio::spawn(async move {
+        while shutdown.changed().await.is_ok() {
+            if *shutdown.borrow() {
+                log::info!("Server: shutting down, draining requests");
+                handle.stop(true).await;
+                break;
+            }
+        }
+    });
+
+    server.await
 }
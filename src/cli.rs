@@ -1,65 +1,241 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 /// Tango Node
+///
+/// Settings come from the built-in defaults, the `--config` file, `TANGO_*` environment
+/// variables and the flags of `run`, each overriding the previous one.
 #[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[clap(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true
+)]
 pub struct Args {
-    /// Topic
-    #[clap(short, long, default_value = "event_parcel")]
-    pub p2p_topic: String,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
 
-    /// Seed node url
-    #[clap(short, long, default_value = "/ip4/127.0.0.1/tcp/12345")]
-    pub seed_node: String,
+    /// TOML config file with network, tss, connectors, database and server sections
+    #[clap(short = 'c', long, env = "TANGO_CONFIG", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Flags of `run`, the node also runs without any subcommand
+    #[clap(flatten)]
+    pub run: RunArgs,
+}
+
+impl Args {
+    /// Node flags, given to `run` or without a subcommand.
+    pub fn run_args(&self) -> &RunArgs {
+        match &self.command {
+            Some(Command::Run(run)) => run,
+            _ => &self.run,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug, Default)]
+pub struct RunArgs {
+    /// Topic [default: event_parcel]
+    #[clap(short, long, env = "TANGO_P2P_TOPIC")]
+    pub p2p_topic: Option<String>,
+
+    /// Bootnodes to dial, comma separated multiaddrs, /dns4/ and /dns6/ hosts are resolved
+    #[clap(
+        short = 's',
+        long,
+        alias = "seed-node",
+        env = "TANGO_BOOTNODES",
+        value_delimiter = ','
+    )]
+    pub bootnodes: Option<Vec<String>>,
+
+    /// Peers connected to before, dialed on start; defaults to ~/tango/network/peers.json unless --new-node
+    #[clap(long, env = "TANGO_PEER_BOOK")]
+    pub peer_book: Option<PathBuf>,
+
+    /// File of committee peer ids, one per line; only these peers can connect and join keygen
+    #[clap(long, env = "TANGO_COMMITTEE")]
+    pub committee: Option<PathBuf>,
 
     /// Explicit peer
-    #[clap(short = 'x', long, default_value = "")]
-    pub explicit_peer: String,
+    #[clap(short = 'x', long, env = "TANGO_EXPLICIT_PEER")]
+    pub explicit_peer: Option<String>,
 
-    /// Node Port
-    #[clap(short, long, default_value = "0")]
-    pub node_port: String,
+    /// Node Port [default: 0]
+    #[clap(short, long, env = "TANGO_NODE_PORT")]
+    pub node_port: Option<u16>,
 
     // Run new node i.e. with new Peer ID (p2p)
-    #[clap(short = 'N', long, parse(try_from_str), default_value = "false")]
-    pub new_node: bool,
+    #[clap(short = 'N', long, parse(try_from_str), env = "TANGO_NEW_NODE")]
+    pub new_node: Option<bool>,
+
+    /// db url [default: mongodb://localhost:27017/admin]
+    #[clap(short, long, env = "TANGO_DB_URL")]
+    pub db_url: Option<String>,
+
+    /// Account password, required to generate an account
+    #[clap(short = 'P', long, env = "TANGO_PASSWORD", hide_env_values = true)]
+    pub password: Option<String>,
+
+    /// Signature scheme of the account: sr25519, ed25519 or ecdsa [default: sr25519]
+    #[clap(long, env = "TANGO_ACCOUNT_SCHEME")]
+    pub account_scheme: Option<String>,
+
+    /// Generate an account key on start when the keystore has none [default: false]
+    #[clap(long, parse(try_from_str), env = "TANGO_GENERATE_ACCOUNT")]
+    pub generate_account: Option<bool>,
+
+    /// Total number of tss participants [default: 3]
+    #[clap(long, env = "TANGO_TSS_NODES")]
+    pub tss_nodes: Option<u32>,
+
+    /// Threshold for tss [default: 2]
+    #[clap(long, env = "TANGO_TSS_THRESHOLD")]
+    pub tss_threshold: Option<u32>,
+
+    /// Peer id of the tss collector; by default the node started without --new-node
+    #[clap(long, env = "TANGO_COLLECTOR")]
+    pub collector: Option<String>,
+
+    /// Allowed CORS origin [default: http://localhost:3000]
+    #[clap(short, long, env = "TANGO_ORIGIN")]
+    pub origin: Option<String>,
 
-    /// db url
-    #[clap(short, long, default_value = "mongodb://localhost:27017/admin")]
-    pub db_url: String,
+    /// Http server binding ip address [default: 127.0.0.1]
+    #[clap(short, long, env = "TANGO_IP")]
+    pub ip: Option<String>,
 
-    //account password
-    #[clap(short = 'P', long, default_value = "tango1234")]
-    pub password: String,
+    /// Signature scheme of the tss key group: ristretto255, secp256k1 or ed25519 [default: ristretto255]
+    #[clap(long, env = "TANGO_CIPHERSUITE")]
+    pub ciphersuite: Option<String>,
 
-    // total number of tss participants
-    #[clap(long, default_value_t = 0)]
-    pub tss_nodes: u32,
+    /// Signatures stored with a verified event: threshold, individual or both [default: threshold]
+    #[clap(long, env = "TANGO_ATTESTATION_MODE")]
+    pub attestation_mode: Option<String>,
 
-    // threshold for tss
-    #[clap(long, default_value_t = 0)]
-    pub tss_threshold: u32,
+    /// key type for keystore [default: tngo]
+    #[clap(short, long, env = "TANGO_KEY_TYPE")]
+    pub key_type: Option<String>,
 
-    #[clap(short, long, default_value = "http://localhost:3000")]
-    pub origin: String,
+    /// Http server port [default: 8080]
+    #[clap(long, env = "TANGO_PORT")]
+    pub port: Option<u16>,
 
-    /// Http server binding ip address
-    #[clap(short, long, default_value = "127.0.0.1")]
-    pub ip: String,
+    /// How many workers for http server [default: 1]
+    #[clap(short, long, env = "TANGO_WORKERS")]
+    pub workers: Option<usize>,
 
-    /// key type for keystore
-    #[clap(short, long, default_value = "tngo")]
-    pub key_type: String,
+    /// Largest difference between a proposed swap price and the own observation that is signed, in basis points [default: 50]
+    #[clap(long, env = "TANGO_OBSERVATION_TOLERANCE_BPS")]
+    pub observation_tolerance_bps: Option<u64>,
 
-    /// Http server port, default is 8080
-    #[clap(long, default_value = "8080")]
-    pub port: u16,
+    /// Events kept for signing; connectors wait while the pool is full [default: 10000]
+    #[clap(long, env = "TANGO_MSG_POOL_SIZE")]
+    pub msg_pool_size: Option<usize>,
 
-    /// How many workers for http server
-    #[clap(short, long, default_value = "1")]
-    pub workers: usize,
+    /// Seconds an event may take to reach threshold before it is evicted [default: 600]
+    #[clap(long, env = "TANGO_MSG_TTL_SECS")]
+    pub msg_ttl_secs: Option<u64>,
+
+    /// Seconds in-flight signing and http requests get to finish on shutdown [default: 30]
+    #[clap(long, env = "TANGO_SHUTDOWN_GRACE_SECS")]
+    pub shutdown_grace_secs: Option<u64>,
+
+    /// Blocks built on top of an ethereum log before it is signed
+    #[clap(long, env = "TANGO_ETH_CONFIRMATIONS")]
+    pub eth_confirmations: Option<u64>,
+
+    /// Substrate nodes followed by the polkadot connector, comma separated
+    #[clap(long, env = "TANGO_SUBSTRATE_ENDPOINTS", value_delimiter = ',')]
+    pub substrate_endpoints: Option<Vec<String>>,
+
+    /// Substrate runtime events to sign, comma separated Pallet::Variant or Pallet::* [default: Balances::Transfer]
+    #[clap(long, env = "TANGO_SUBSTRATE_EVENTS", value_delimiter = ',')]
+    pub substrate_events: Option<Vec<String>>,
+
+    /// Connectors to run, comma separated: ethereum, swap, polkadot [default: polkadot]
+    #[clap(short, long, env = "TANGO_BLOCKCHAIN", value_delimiter = ',')]
+    pub blockchain: Option<Vec<String>>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Start the node: network, tss, connectors and http server
+    Run(RunArgs),
+    /// Inspect the node configuration
+    #[clap(subcommand)]
+    Config(ConfigCommand),
+    /// libp2p identity of the node
+    #[clap(subcommand)]
+    Identity(IdentityCommand),
+    /// Account which signs events
+    #[clap(subcommand)]
+    Account(AccountCommand),
+    /// Key group of a running node, queried over its http server
+    #[clap(subcommand)]
+    Tss(TssCommand),
+    /// Verify the attestations of an event document as returned by GET /event
+    Verify {
+        /// JSON file with the event document
+        event: PathBuf,
+    },
+    /// Database maintenance
+    #[clap(subcommand)]
+    Db(DbCommand),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the effective configuration as TOML and check it
+    Print,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IdentityCommand {
+    /// Print the peer id and the identity file
+    Show,
+    /// Create a new identity, the node joins the network with a new peer id
+    Generate {
+        /// Replace an existing identity
+        #[clap(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AccountCommand {
+    /// Print the current account and the keys of the configured key type in the keystore
+    Show,
+    /// Generate a new key in the keystore and make it the current account
+    Generate,
+    /// Hand the current account over to a new key, the handover is signed by the current key
+    /// and recorded in the database; restart the node afterwards
+    Rotate,
+    /// Import a key into the keystore and make it the current account
+    Import {
+        /// Mnemonic or hex seed, optionally with a derivation path, e.g. "<mnemonic>//tango"
+        #[clap(env = "TANGO_SURI", hide_env_values = true)]
+        suri: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TssCommand {
+    /// Print the key group state and signing queues
+    Status,
+    /// Print the group key, or write it with its ciphersuite and params as JSON
+    ExportGroupKey {
+        /// JSON file to write the group key to
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+}
 
-    // block chain
-    #[clap(short, long, default_value = "polkadot")]
-    pub blockchain: String,
+#[derive(Subcommand, Debug)]
+pub enum DbCommand {
+    /// Create the indexes the node relies on, safe to run again
+    Migrate,
 }
@@ -0,0 +1,645 @@
+use crate::cli::{AccountCommand, Command, ConfigCommand, DbCommand, IdentityCommand, TssCommand};
+use crate::config::NodeConfig;
+use accounts::{Account, AccountScheme, Registration, ACCOUNT_PATH};
+use events::tss::TssStatus;
+use keystore::params::keystore_params::KeystoreParams;
+use libp2p::identity::Keypair;
+use libp2p::PeerId;
+use network::utils::identity_handler::{generate_identity, identity_path, read_identity};
+use sc_keystore::LocalKeystore;
+use sc_service::config::KeystoreConfig;
+use serde_json::Value;
+use sp_core::crypto::KeyTypeId;
+use sp_keystore::SyncCryptoStorePtr;
+use std::convert::TryFrom;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tango_database::models::KeyRotationModel;
+use tango_database::MongoRepo;
+use tss::attestation::verify_event_document;
+
+/// Runs an operator command, every command except `run` which starts the node.
+pub async fn execute(command: &Command, config: &NodeConfig) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Run(_) => Err("run starts the node, it is not an operator command".into()),
+        Command::Config(ConfigCommand::Print) => {
+            print!("{}", config.to_toml()?);
+            config.validate()?;
+            Ok(())
+        }
+        Command::Identity(command) => identity(command),
+        Command::Account(command) => account(command, config).await,
+        Command::Tss(command) => tss(command, config).await,
+        Command::Verify { event } => verify(event),
+        Command::Db(DbCommand::Migrate) => migrate(config).await,
+    }
+}
+
+fn keystore_config() -> Result<KeystoreConfig, Box<dyn Error>> {
+    let config_dir = env::current_dir()?;
+    let (_, config) = KeystoreParams::default()
+        .keystore_config(&config_dir)
+        .map_err(|e| format!("Unable to configure keystore: {:?}", e))?;
+    Ok(config)
+}
+
+/// Keystore of the node in the working directory.
+pub fn open_keystore() -> Result<SyncCryptoStorePtr, Box<dyn Error>> {
+    match keystore_config()? {
+        KeystoreConfig::Path { path, password } => {
+            let keystore = LocalKeystore::open(path, password)
+                .map_err(|e| format!("Unable to open keystore: {}", e))?;
+            Ok(Arc::new(keystore))
+        }
+        _ => unreachable!("keystore_config always returns path and password; qed"),
+    }
+}
+
+/// Directory of the keystore opened by `open_keystore`.
+pub fn keystore_path() -> Result<PathBuf, Box<dyn Error>> {
+    match keystore_config()? {
+        KeystoreConfig::Path { path, .. } => Ok(path),
+        _ => unreachable!("keystore_config always returns path and password; qed"),
+    }
+}
+
+/// Registration of the node, the account signs the peer id and the identity key signs the account.
+pub fn register_peer(
+    peer_id: &PeerId,
+    id_keys: &Keypair,
+    account: &Account,
+    key_type: KeyTypeId,
+    keystore: SyncCryptoStorePtr,
+) -> Result<Registration, Box<dyn Error>> {
+    Registration::new(
+        peer_id.to_string(),
+        account,
+        key_type,
+        keystore,
+        |statement| id_keys.sign(statement).map_err(Into::into),
+    )
+}
+
+pub async fn get_connection(db_url: String) -> MongoRepo {
+    MongoRepo::connect(&db_url).await
+}
+
+fn identity(command: &IdentityCommand) -> Result<(), Box<dyn Error>> {
+    let peer_id = match command {
+        IdentityCommand::Show => read_identity()?.0,
+        IdentityCommand::Generate { force } => generate_identity(*force)?,
+    };
+    println!("peer id:  {}", peer_id);
+    println!("identity: {}", identity_path()?);
+    Ok(())
+}
+
+async fn account(command: &AccountCommand, config: &NodeConfig) -> Result<(), Box<dyn Error>> {
+    let key_type = KeyTypeId::try_from(config.tss.key_type.as_str())
+        .map_err(|_| format!("Invalid key type {}", config.tss.key_type))?;
+    let scheme = config.tss.account_scheme.parse::<AccountScheme>()?;
+    let keystore = open_keystore()?;
+
+    match command {
+        AccountCommand::Show => {
+            match Account::read_account_from_file(ACCOUNT_PATH, scheme) {
+                Ok(account) => println!("current account: {}", account),
+                Err(_) => println!("current account: none, {} not found", ACCOUNT_PATH),
+            }
+            let keys = Account::keystore_keys(scheme, key_type, keystore);
+            println!(
+                "{} {} keys of type {} in the keystore",
+                keys.len(),
+                scheme,
+                config.tss.key_type
+            );
+            for key in keys {
+                println!("  {}", key);
+            }
+        }
+        AccountCommand::Generate => {
+            let account = Account::generate(scheme, account_password(config)?, key_type, keystore)?;
+            println!("current account: {}", account.accounts);
+        }
+        AccountCommand::Rotate => {
+            let current = Account::load(scheme, key_type, keystore.clone())?
+                .ok_or("No account to rotate, generate or import one first")?;
+            let rotation = current.rotate(account_password(config)?, key_type, keystore)?;
+
+            // verifiers learn about the new key before it signs anything
+            let connection = get_connection(config.database.url.clone()).await;
+            let record = KeyRotationModel::new(
+                rotation.key_type.clone(),
+                scheme.to_string(),
+                rotation.old_key.to_string(),
+                rotation.new_key.to_string(),
+                i64::try_from(rotation.rotated_at)?,
+                hex::encode(&rotation.signature),
+            );
+            MongoRepo::insert_key_rotation(&connection, record).await?;
+
+            let account = rotation.apply(&keystore_path()?)?;
+            println!(
+                "rotated account {} to {}",
+                rotation.old_key, account.accounts
+            );
+            println!("restart the node to sign with the new key");
+        }
+        AccountCommand::Import { suri } => {
+            let account = Account::import(scheme, suri, key_type, keystore)?;
+            println!("current account: {}", account.accounts);
+        }
+    }
+    Ok(())
+}
+
+fn account_password(config: &NodeConfig) -> Result<&str, Box<dyn Error>> {
+    let password = config
+        .tss
+        .password
+        .as_deref()
+        .filter(|password| !password.is_empty())
+        .ok_or("tss.password is not set, use the config file, TANGO_PASSWORD or --password")?;
+    Ok(password)
+}
+
+async fn tss(command: &TssCommand, config: &NodeConfig) -> Result<(), Box<dyn Error>> {
+    let status = tss_status(config).await?;
+    match command {
+        TssCommand::Status => {
+            let role = match (status.is_collector, status.is_aggregator) {
+                (_, true) => "aggregator",
+                (true, false) => "collector",
+                _ => "participant",
+            };
+            println!("peer id:     {}", status.local_peer_id);
+            println!("state:       {}", status.state);
+            println!("role:        {}", role);
+            println!("ciphersuite: {}", status.ciphersuite);
+            println!(
+                "key group:   {} of {} nodes",
+                status.threshold, status.participants
+            );
+            println!("peers:       {}", status.peers.join(", "));
+            println!(
+                "group key:   {}",
+                status
+                    .group_key
+                    .as_deref()
+                    .unwrap_or("none, keygen not finished")
+            );
+            println!(
+                "msg pool:    {} events, {} awaiting partial signatures",
+                status.msg_pool, status.pending_signatures
+            );
+        }
+        TssCommand::ExportGroupKey { output } => {
+            let group_key = status
+                .group_key
+                .as_ref()
+                .ok_or("Keygen has not finished, there is no group key yet")?;
+            match output {
+                Some(path) => {
+                    let export = serde_json::json!({
+                        "ciphersuite": status.ciphersuite,
+                        "group_key": group_key,
+                        "participants": status.participants,
+                        "threshold": status.threshold,
+                    });
+                    fs::write(path, serde_json::to_vec_pretty(&export)?)?;
+                    println!("group key written to {}", path.display());
+                }
+                None => println!("{}", group_key),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Status of the tss of the configured node, asked over its http server.
+async fn tss_status(config: &NodeConfig) -> Result<TssStatus, Box<dyn Error>> {
+    // a server listening on every interface is reached over loopback
+    let host = match config.server.ip.as_str() {
+        "0.0.0.0" | "::" => "127.0.0.1",
+        ip => ip,
+    };
+    let url = format!("http://{}:{}/tss/status", host, config.server.port);
+    let response = reqwest::get(&url)
+        .await
+        .map_err(|e| format!("Node is not reachable at {}: {}", url, e))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("{} answered {}: {}", url, status, body).into());
+    }
+    Ok(response.json::<TssStatus>().await?)
+}
+
+fn verify(path: &Path) -> Result<(), Box<dyn Error>> {
+    let document: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let verified = verify_event_document(&document)?;
+    println!("event {} verified", verified.msg_hash);
+    if let Some(ciphersuite) = verified.threshold {
+        println!("  threshold signature of the {} key group", ciphersuite);
+    }
+    for signer in verified.signers {
+        println!("  signed by {} ({})", signer, signer.scheme());
+    }
+    for registration in verified.registrations {
+        println!(
+            "  peer {} registered account {}",
+            registration.peer_id, registration.account
+        );
+    }
+    Ok(())
+}
+
+async fn migrate(config: &NodeConfig) -> Result<(), Box<dyn Error>> {
+    let connection = get_connection(config.database.url.clone()).await;
+    for index in MongoRepo::migrate(&connection).await? {
+        println!("index {} is in place", index);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use accounts::AccountKey;
+    use connector::chain_connector::{queue_depth, send_event};
+    use connector::polkadot::{substrate_event_msg, DEFAULT_SUBSTRATE_ENDPOINT};
+    use std::collections::HashSet;
+    use subxt::ext::scale_value::{self, Composite};
+    use tango_database::models::PendingEvent;
+    use tango_database::{MemoryRepo, Repo};
+    use tokio::sync::{mpsc, oneshot, watch};
+    use tokio::time::{self, Duration};
+    use tss::attestation::AttestationMode;
+    use tss::ciphersuite::CiphersuiteId;
+    use tss::msg_pool::MsgPoolConfig;
+    use tss::tss_event_model::{TSSData, TSSEventType};
+    use tss::tss_service::TssService;
+    use tss::utils::{get_publish_peer_id_msg, get_receive_params_msg};
+
+    /// ed25519 account of //Alice in an in-memory keystore
+    fn test_account() -> (Account, KeyTypeId, SyncCryptoStorePtr) {
+        let keystore: SyncCryptoStorePtr = Arc::new(LocalKeystore::in_memory());
+        let key_type = KeyTypeId::try_from("tngo").unwrap();
+        let account = Account {
+            accounts: AccountKey::from_suri(AccountScheme::Ed25519, "//Alice").unwrap(),
+        };
+        sp_keystore::SyncCryptoStore::insert_unknown(
+            &*keystore,
+            key_type,
+            "//Alice",
+            &account.accounts.to_raw_vec(),
+        )
+        .unwrap();
+        (account, key_type, keystore)
+    }
+
+    //tests that a libp2p identity key can register the peer for an account
+    #[test]
+    fn registers_libp2p_identity() {
+        let (account, key_type, keystore) = test_account();
+        let id_keys = Keypair::generate_ed25519();
+        let peer_id = PeerId::from(id_keys.public());
+
+        let registration =
+            register_peer(&peer_id, &id_keys, &account, key_type, keystore.clone()).unwrap();
+        assert_eq!(registration.peer_id, peer_id.to_string());
+        assert_eq!(registration.account, account.accounts);
+
+        let other = Keypair::generate_ed25519();
+        assert!(register_peer(&peer_id, &other, &account, key_type, keystore).is_err());
+    }
+
+    /// peer id of a new libp2p identity and its json registration
+    fn test_peer() -> (String, String) {
+        let (account, key_type, keystore) = test_account();
+        let id_keys = Keypair::generate_ed25519();
+        let peer_id = PeerId::from(id_keys.public());
+        let registration = register_peer(&peer_id, &id_keys, &account, key_type, keystore).unwrap();
+        (
+            peer_id.to_string(),
+            serde_json::to_string(&registration).unwrap(),
+        )
+    }
+
+    /// tss service of a single node that has not run keygen
+    struct TestTss {
+        peer_id: PeerId,
+        event_sender: mpsc::Sender<String>,
+        /// tss data as handed over by the network, its peer id is the authenticated sender
+        gossip_to_tss_sender: mpsc::Sender<TSSData>,
+        command_sender: mpsc::Sender<events::tss::TssCommand>,
+        handle: tokio::task::JoinHandle<()>,
+    }
+
+    impl TestTss {
+        async fn spawn(
+            is_collector: bool,
+            committee: Option<HashSet<String>>,
+            msg_pool: MsgPoolConfig,
+        ) -> Self {
+            Self::spawn_with_repo(
+                is_collector,
+                committee,
+                msg_pool,
+                Arc::new(MemoryRepo::new()),
+            )
+            .await
+        }
+
+        /// tss service storing into `connection`
+        async fn spawn_with_repo(
+            is_collector: bool,
+            committee: Option<HashSet<String>>,
+            msg_pool: MsgPoolConfig,
+            connection: Arc<dyn Repo>,
+        ) -> Self {
+            let (account, key_type, keystore) = test_account();
+            let id_keys = Keypair::generate_ed25519();
+            let peer_id = PeerId::from(id_keys.public());
+            let registration =
+                register_peer(&peer_id, &id_keys, &account, key_type, keystore.clone()).unwrap();
+
+            let (event_sender, event_receiver) = mpsc::channel(10);
+            let (gossip_to_tss_sender, gossip_to_tss_receiver) = mpsc::channel(10);
+            let (tss_to_gossip_sender, _tss_to_gossip_receiver) = mpsc::channel(10);
+            let (command_sender, command_receiver) = mpsc::channel(10);
+            let mut tss_service = TssService::new(
+                gossip_to_tss_receiver,
+                tss_to_gossip_sender,
+                event_receiver,
+                command_receiver,
+                account,
+                registration,
+                connection,
+                is_collector,
+                None,
+                peer_id.to_string(),
+                (3, 2),
+                Some(key_type),
+                Some(keystore),
+                CiphersuiteId::default(),
+                AttestationMode::default(),
+                50,
+                msg_pool,
+                committee,
+            )
+            .await;
+            let handle = tokio::spawn(async move {
+                let (_shutdown_sender, shutdown) = watch::channel(false);
+                tss_service.run(shutdown, Duration::from_secs(1)).await;
+            });
+            TestTss {
+                peer_id,
+                event_sender,
+                gossip_to_tss_sender,
+                command_sender,
+                handle,
+            }
+        }
+
+        async fn status(&self) -> TssStatus {
+            let (reply, answer) = oneshot::channel();
+            self.command_sender
+                .send(events::tss::TssCommand::Status(reply))
+                .await
+                .unwrap();
+            answer.await.unwrap()
+        }
+
+        /// hands tss data of `source` to tss
+        async fn receive(&self, source: &str, tss_event_type: TSSEventType, tss_data: Vec<u8>) {
+            self.gossip_to_tss_sender
+                .send(TSSData {
+                    peer_id: source.to_string(),
+                    tss_event_type,
+                    tss_data,
+                })
+                .await
+                .unwrap();
+        }
+
+        /// waits until `msg_pool` events are in the pool
+        async fn wait_for_msg_pool(&self, msg_pool: usize) -> TssStatus {
+            time::timeout(Duration::from_secs(5), async {
+                loop {
+                    let status = self.status().await;
+                    if status.msg_pool >= msg_pool {
+                        return status;
+                    }
+                    time::sleep(Duration::from_millis(50)).await;
+                }
+            })
+            .await
+            .expect("event not taken by tss")
+        }
+    }
+
+    /// balance transfer of a substrate chain at `block_number`
+    fn transfer_event(block_number: u32) -> String {
+        // fields as decoded with the metadata of the chain
+        let fields = Composite::named(vec![
+            (
+                "from",
+                scale_value::Value::string("5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY"),
+            ),
+            ("amount", scale_value::Value::u128(10_000_000_000)),
+        ]);
+        substrate_event_msg(
+            DEFAULT_SUBSTRATE_ENDPOINT,
+            "Balances",
+            "Transfer",
+            block_number,
+            "0x11",
+            2,
+            serde_json::to_value(fields).unwrap(),
+        )
+    }
+
+    //tests that an event of the substrate connector is taken into the msg pool of tss
+    #[tokio::test]
+    async fn substrate_event_reaches_tss() {
+        let tss = TestTss::spawn(false, None, MsgPoolConfig::default()).await;
+        send_event(&tss.event_sender, transfer_event(7))
+            .await
+            .unwrap();
+
+        let status = tss.wait_for_msg_pool(1).await;
+        assert_eq!(status.msg_pool, 1);
+        assert_eq!(status.local_peer_id, tss.peer_id.to_string());
+        tss.handle.abort();
+    }
+
+    //tests that tss leaves events in the connector queue while its msg pool is full
+    #[tokio::test]
+    async fn full_msg_pool_stops_taking_events() {
+        let tss = TestTss::spawn(
+            false,
+            None,
+            MsgPoolConfig {
+                max_len: 1,
+                ttl: Duration::from_secs(600),
+            },
+        )
+        .await;
+        send_event(&tss.event_sender, transfer_event(7))
+            .await
+            .unwrap();
+        tss.wait_for_msg_pool(1).await;
+        send_event(&tss.event_sender, transfer_event(8))
+            .await
+            .unwrap();
+
+        time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(tss.status().await.msg_pool, 1);
+        assert_eq!(queue_depth(&tss.event_sender), 1);
+        tss.handle.abort();
+    }
+
+    //tests that params and peer ids are only taken from committee members, checked on the
+    //peer that sent them and not on the peer they name
+    #[tokio::test]
+    async fn ignores_keygen_msgs_of_non_members() {
+        let (member, member_registration) = test_peer();
+        let (outsider, outsider_registration) = test_peer();
+        let committee = HashSet::from([member.clone()]);
+        let peer_id_msg = |peer_id: &str, registration: &str| {
+            get_publish_peer_id_msg(peer_id.to_string(), registration.to_string()).unwrap()
+        };
+
+        let collector =
+            TestTss::spawn(true, Some(committee.clone()), MsgPoolConfig::default()).await;
+        for (source, peer_id, registration) in [
+            (&outsider, &outsider, &outsider_registration),
+            (&outsider, &member, &member_registration),
+            (&member, &member, &member_registration),
+        ] {
+            collector
+                .receive(
+                    source,
+                    TSSEventType::ReceivePeerIDForIndex,
+                    peer_id_msg(peer_id, registration),
+                )
+                .await;
+        }
+        let peers = time::timeout(Duration::from_secs(5), async {
+            loop {
+                let status = collector.status().await;
+                if !status.peers.is_empty() {
+                    return status.peers;
+                }
+                time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("peer id of member not taken");
+        assert_eq!(peers, vec![member.clone()]);
+        collector.handle.abort();
+
+        let params_msg = |peer_id: &str, registration: &str| {
+            get_receive_params_msg(
+                peer_id.to_string(),
+                frost_dalek::Parameters { n: 3, t: 2 },
+                CiphersuiteId::default(),
+                registration.to_string(),
+            )
+            .unwrap()
+        };
+        let node = TestTss::spawn(false, Some(committee), MsgPoolConfig::default()).await;
+        node.receive(
+            &outsider,
+            TSSEventType::ReceiveParams,
+            params_msg(&outsider, &outsider_registration),
+        )
+        .await;
+        node.receive(
+            &outsider,
+            TSSEventType::ReceiveParams,
+            params_msg(&member, &member_registration),
+        )
+        .await;
+        time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(node.status().await.state, "Empty");
+
+        node.receive(
+            &member,
+            TSSEventType::ReceiveParams,
+            params_msg(&member, &member_registration),
+        )
+        .await;
+        time::sleep(Duration::from_millis(500)).await;
+        let status = node.status().await;
+        assert_eq!(status.state, "ReceivedParams");
+        assert_eq!(status.peers, vec![member]);
+        node.handle.abort();
+    }
+
+    //tests that a registration replayed by another committee member is refused, it has to
+    //come from the peer it registers
+    #[tokio::test]
+    async fn rejects_replayed_registration() {
+        let (member, member_registration) = test_peer();
+        let (replayer, _) = test_peer();
+        let committee = HashSet::from([member.clone(), replayer.clone()]);
+
+        let collector =
+            TestTss::spawn(true, Some(committee.clone()), MsgPoolConfig::default()).await;
+        collector
+            .receive(
+                &replayer,
+                TSSEventType::ReceivePeerIDForIndex,
+                get_publish_peer_id_msg(replayer.clone(), member_registration.clone()).unwrap(),
+            )
+            .await;
+        time::sleep(Duration::from_millis(500)).await;
+        assert!(collector.status().await.peers.is_empty());
+        collector.handle.abort();
+
+        let node = TestTss::spawn(false, Some(committee), MsgPoolConfig::default()).await;
+        node.receive(
+            &replayer,
+            TSSEventType::ReceiveParams,
+            get_receive_params_msg(
+                replayer.clone(),
+                frost_dalek::Parameters { n: 3, t: 2 },
+                CiphersuiteId::default(),
+                member_registration,
+            )
+            .unwrap(),
+        )
+        .await;
+        time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(node.status().await.state, "Empty");
+        node.handle.abort();
+    }
+
+    //tests that after a restart the events persisted at shutdown stay stored and out of msg
+    //pool while keygen has not finished
+    #[tokio::test]
+    async fn keeps_pending_events_until_keygen() {
+        let connection = Arc::new(MemoryRepo::new());
+        let msg_hash = mongodb::bson::oid::ObjectId::new().to_hex();
+        connection
+            .upsert_pending_events(vec![PendingEvent::new(msg_hash.clone(), transfer_event(9))])
+            .await
+            .unwrap();
+
+        let tss =
+            TestTss::spawn_with_repo(false, None, MsgPoolConfig::default(), connection.clone())
+                .await;
+        time::sleep(Duration::from_millis(500)).await;
+        let status = tss.status().await;
+        assert_eq!(status.state, "Empty");
+        assert_eq!(status.msg_pool, 0);
+        let pending = connection.get_pending_events().await.unwrap();
+        assert!(pending.iter().any(|event| event.msg_hash == msg_hash));
+        tss.handle.abort();
+    }
+}
@@ -0,0 +1,464 @@
+use crate::cli::{Args, RunArgs};
+use accounts::AccountScheme;
+use connector::chain_connector::ConnectorRegistry;
+use connector::event_listener::DEFAULT_CONFIRMATIONS;
+use connector::polkadot::{EventFilter, DEFAULT_SUBSTRATE_ENDPOINT};
+use network::get_bootnodes;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tss::attestation::AttestationMode;
+use tss::ciphersuite::CiphersuiteId;
+use tss::consensus::DEFAULT_OBSERVATION_TOLERANCE_BPS;
+use tss::msg_pool::{DEFAULT_MSG_POOL_SIZE, DEFAULT_MSG_TTL};
+use tss::{DEFUALT_TSS_THRESHOLD, DEFUALT_TSS_TOTAL_NODES};
+
+/// Configuration of a node. Built-in defaults are overridden by the TOML file given with
+/// `--config`, then by `TANGO_*` environment variables and finally by command line flags.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    pub network: NetworkConfig,
+    pub tss: TssConfig,
+    pub connectors: ConnectorsConfig,
+    pub database: DatabaseConfig,
+    pub server: ServerConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// gossip topic events are published on
+    pub topic: String,
+    /// p2p listening port, 0 picks a free one
+    pub port: u16,
+    /// run with a new identity every start
+    pub new_node: bool,
+    /// multiaddrs dialed on start, /dns4/ and /dns6/ hosts are resolved
+    pub bootnodes: Vec<String>,
+    /// defaults to ~/tango/network/peers.json unless new_node
+    pub peer_book: Option<PathBuf>,
+    /// file of committee peer ids, one per line
+    pub committee: Option<PathBuf>,
+    pub explicit_peer: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TssConfig {
+    /// total number of tss participants
+    pub nodes: u32,
+    pub threshold: u32,
+    /// peer id of the collector, by default the node which is not a new node
+    pub collector: Option<String>,
+    /// ristretto255, secp256k1 or ed25519
+    pub ciphersuite: String,
+    /// threshold, individual or both
+    pub attestation_mode: String,
+    /// key type of the account in the keystore
+    pub key_type: String,
+    /// account password, there is no default
+    pub password: Option<String>,
+    /// generate an account key on start when the keystore has none of `key_type`
+    pub generate_account: bool,
+    /// sr25519, ed25519 or ecdsa
+    pub account_scheme: String,
+    pub observation_tolerance_bps: u64,
+    pub msg_pool_size: usize,
+    pub msg_ttl_secs: u64,
+    /// seconds in-flight signing gets to finish on shutdown
+    pub shutdown_grace_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectorsConfig {
+    /// connectors to run: ethereum, swap, polkadot
+    pub enabled: Vec<String>,
+    pub eth_confirmations: u64,
+    pub substrate_endpoints: Vec<String>,
+    /// Pallet::Variant or Pallet::*
+    pub substrate_events: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub ip: String,
+    pub port: u16,
+    /// allowed CORS origin
+    pub origin: String,
+    pub workers: usize,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            topic: "event_parcel".to_string(),
+            port: 0,
+            new_node: false,
+            bootnodes: vec![],
+            peer_book: None,
+            committee: None,
+            explicit_peer: None,
+        }
+    }
+}
+
+impl Default for TssConfig {
+    fn default() -> Self {
+        TssConfig {
+            nodes: DEFUALT_TSS_TOTAL_NODES,
+            threshold: DEFUALT_TSS_THRESHOLD,
+            collector: None,
+            ciphersuite: "ristretto255".to_string(),
+            attestation_mode: "threshold".to_string(),
+            key_type: "tngo".to_string(),
+            password: None,
+            generate_account: false,
+            account_scheme: "sr25519".to_string(),
+            observation_tolerance_bps: DEFAULT_OBSERVATION_TOLERANCE_BPS,
+            msg_pool_size: DEFAULT_MSG_POOL_SIZE,
+            msg_ttl_secs: DEFAULT_MSG_TTL.as_secs(),
+            shutdown_grace_secs: 30,
+        }
+    }
+}
+
+impl Default for ConnectorsConfig {
+    fn default() -> Self {
+        ConnectorsConfig {
+            enabled: vec!["polkadot".to_string()],
+            eth_confirmations: DEFAULT_CONFIRMATIONS,
+            substrate_endpoints: vec![DEFAULT_SUBSTRATE_ENDPOINT.to_string()],
+            substrate_events: vec!["Balances::Transfer".to_string()],
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: "mongodb://localhost:27017/admin".to_string(),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            ip: "127.0.0.1".to_string(),
+            port: 8080,
+            origin: "http://localhost:3000".to_string(),
+            workers: 1,
+        }
+    }
+}
+
+impl NodeConfig {
+    /// Effective configuration of `args`: defaults, then the config file, then environment and flags.
+    /// The result is not validated yet.
+    pub fn load(args: &Args) -> Result<Self, Box<dyn Error>> {
+        let mut config = match &args.config {
+            Some(path) => NodeConfig::from_file(path)?,
+            None => NodeConfig::default(),
+        };
+        config.apply_args(args.run_args());
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("unable to read config {}: {}", path.display(), e))?;
+        let config = toml::from_str(&content)
+            .map_err(|e| format!("invalid config {}: {}", path.display(), e))?;
+        Ok(config)
+    }
+
+    /// Environment variables are resolved by clap, so a flag wins over its variable.
+    pub fn apply_args(&mut self, args: &RunArgs) {
+        fn set<T: Clone>(target: &mut T, value: &Option<T>) {
+            if let Some(value) = value {
+                *target = value.clone();
+            }
+        }
+
+        set(&mut self.network.topic, &args.p2p_topic);
+        set(&mut self.network.port, &args.node_port);
+        set(&mut self.network.new_node, &args.new_node);
+        set(&mut self.network.bootnodes, &args.bootnodes);
+        if args.peer_book.is_some() {
+            self.network.peer_book = args.peer_book.clone();
+        }
+        if args.committee.is_some() {
+            self.network.committee = args.committee.clone();
+        }
+        if args.explicit_peer.is_some() {
+            self.network.explicit_peer = args.explicit_peer.clone();
+        }
+
+        set(&mut self.tss.nodes, &args.tss_nodes);
+        set(&mut self.tss.threshold, &args.tss_threshold);
+        if args.collector.is_some() {
+            self.tss.collector = args.collector.clone();
+        }
+        set(&mut self.tss.ciphersuite, &args.ciphersuite);
+        set(&mut self.tss.attestation_mode, &args.attestation_mode);
+        set(&mut self.tss.key_type, &args.key_type);
+        if args.password.is_some() {
+            self.tss.password = args.password.clone();
+        }
+        set(&mut self.tss.generate_account, &args.generate_account);
+        set(&mut self.tss.account_scheme, &args.account_scheme);
+        set(
+            &mut self.tss.observation_tolerance_bps,
+            &args.observation_tolerance_bps,
+        );
+        set(&mut self.tss.msg_pool_size, &args.msg_pool_size);
+        set(&mut self.tss.msg_ttl_secs, &args.msg_ttl_secs);
+        set(&mut self.tss.shutdown_grace_secs, &args.shutdown_grace_secs);
+
+        set(&mut self.connectors.enabled, &args.blockchain);
+        set(
+            &mut self.connectors.eth_confirmations,
+            &args.eth_confirmations,
+        );
+        set(
+            &mut self.connectors.substrate_endpoints,
+            &args.substrate_endpoints,
+        );
+        set(
+            &mut self.connectors.substrate_events,
+            &args.substrate_events,
+        );
+
+        set(&mut self.database.url, &args.db_url);
+
+        set(&mut self.server.ip, &args.ip);
+        set(&mut self.server.port, &args.port);
+        set(&mut self.server.origin, &args.origin);
+        set(&mut self.server.workers, &args.workers);
+    }
+
+    /// Checks every section, all problems are reported at once.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut problems = vec![];
+
+        if self.network.topic.trim().is_empty() {
+            problems.push("network.topic is empty".to_string());
+        }
+        if let Err(e) = get_bootnodes(&self.network.bootnodes) {
+            problems.push(format!("network.bootnodes: {}", e));
+        }
+
+        if self.tss.nodes < DEFUALT_TSS_TOTAL_NODES {
+            problems.push(format!(
+                "tss.nodes is {}, at least {} nodes are needed",
+                self.tss.nodes, DEFUALT_TSS_TOTAL_NODES
+            ));
+        }
+        if self.tss.threshold < 1 || self.tss.threshold > self.tss.nodes {
+            problems.push(format!(
+                "tss.threshold is {}, it must be between 1 and tss.nodes ({})",
+                self.tss.threshold, self.tss.nodes
+            ));
+        }
+        if let Err(e) = self.tss.ciphersuite.parse::<CiphersuiteId>() {
+            problems.push(format!("tss.ciphersuite: {}", e));
+        }
+        if let Err(e) = self.tss.attestation_mode.parse::<AttestationMode>() {
+            problems.push(format!("tss.attestation_mode: {}", e));
+        }
+        if let Err(e) = self.tss.account_scheme.parse::<AccountScheme>() {
+            problems.push(format!("tss.account_scheme: {}", e));
+        }
+        if self.tss.key_type.len() != 4 || !self.tss.key_type.is_ascii() {
+            problems.push(format!(
+                "tss.key_type {:?} must be 4 ascii characters",
+                self.tss.key_type
+            ));
+        }
+        match &self.tss.password {
+            Some(password) if !password.is_empty() => {}
+            _ if !self.tss.generate_account => {}
+            _ => problems.push(
+                "tss.password is not set, it is required by tss.generate_account, use the config file, TANGO_PASSWORD or --password"
+                    .to_string(),
+            ),
+        }
+        if self.tss.msg_pool_size == 0 {
+            problems.push("tss.msg_pool_size must be at least 1".to_string());
+        }
+        if self.tss.msg_ttl_secs == 0 {
+            problems.push("tss.msg_ttl_secs must be at least 1".to_string());
+        }
+
+        let known = ConnectorRegistry::with_defaults().names();
+        for name in self.connectors.enabled.iter() {
+            if !known.contains(&name.as_str()) {
+                problems.push(format!(
+                    "connectors.enabled: unknown connector {}, available are {}",
+                    name,
+                    known.join(", ")
+                ));
+            }
+        }
+        if let Err(e) = self.substrate_event_filters() {
+            problems.push(format!("connectors.substrate_events: {}", e));
+        }
+
+        if !self.database.url.starts_with("mongodb://")
+            && !self.database.url.starts_with("mongodb+srv://")
+        {
+            problems.push(format!(
+                "database.url {} is not a mongodb:// url",
+                self.database.url
+            ));
+        }
+
+        if self.server.workers == 0 {
+            problems.push("server.workers must be at least 1".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "invalid configuration:\n  {}",
+                problems.join("\n  ")
+            ))
+        }
+    }
+
+    pub fn substrate_event_filters(&self) -> Result<Vec<EventFilter>, String> {
+        self.connectors
+            .substrate_events
+            .iter()
+            .map(|filter| filter.parse())
+            .collect()
+    }
+
+    /// The configuration as TOML, the password is masked.
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        let mut printed = self.clone();
+        if printed.tss.password.is_some() {
+            printed.tss.password = Some("********".to_string());
+        }
+        toml::to_string_pretty(&printed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::sync::{Mutex, MutexGuard};
+
+    //the tests change the environment, which clap reads while parsing
+    static ENV: Mutex<()> = Mutex::new(());
+
+    /// removes the TANGO_* variables of the environment running the tests, clap reads them;
+    /// no other test reads or changes the environment while the guard is held
+    fn clear_tango_env() -> MutexGuard<'static, ()> {
+        let guard = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        for (key, _) in std::env::vars() {
+            if key.starts_with("TANGO_") {
+                std::env::remove_var(key);
+            }
+        }
+        guard
+    }
+
+    #[test]
+    fn flags_override_config_file() {
+        let _env = clear_tango_env();
+        let path = std::env::temp_dir().join(format!("tango-config-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            r#"
+[network]
+topic = "file_topic"
+port = 30333
+
+[tss]
+nodes = 5
+threshold = 3
+password = "from-file"
+
+[server]
+port = 9090
+"#,
+        )
+        .unwrap();
+
+        let args = Args::parse_from([
+            "tango-node",
+            "--config",
+            path.to_str().unwrap(),
+            "--node-port",
+            "30334",
+            "--tss-threshold",
+            "4",
+        ]);
+        let config = NodeConfig::load(&args).unwrap();
+        assert_eq!(config.network.topic, "file_topic");
+        assert_eq!(config.network.port, 30334);
+        assert_eq!((config.tss.nodes, config.tss.threshold), (5, 4));
+        assert_eq!(config.server.port, 9090);
+        assert_eq!(config.database, DatabaseConfig::default());
+        assert!(config.validate().is_ok());
+
+        let printed = config.to_toml().unwrap();
+        assert!(!printed.contains("from-file"));
+        let reloaded: NodeConfig = toml::from_str(&printed).unwrap();
+        assert_eq!(reloaded.network, config.network);
+
+        fs::write(&path, "[tss]\nthreshhold = 2\n").unwrap();
+        assert!(NodeConfig::from_file(&path).is_err());
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn run_subcommand_takes_node_flags() {
+        let _env = clear_tango_env();
+        let args = Args::parse_from(["tango-node", "run", "--tss-nodes", "7"]);
+        assert_eq!(NodeConfig::load(&args).unwrap().tss.nodes, 7);
+        assert!(Args::try_parse_from(["tango-node", "--tss-nodes", "7", "db", "migrate"]).is_err());
+    }
+
+    #[test]
+    fn reports_every_invalid_setting() {
+        let mut config = NodeConfig::default();
+        config.tss.threshold = 4;
+        config.tss.ciphersuite = "rsa".to_string();
+        config.tss.account_scheme = "rsa".to_string();
+        config.connectors.enabled = vec!["bitcoin".to_string()];
+        config.database.url = "localhost:27017".to_string();
+        config.tss.generate_account = true;
+
+        let error = config.validate().unwrap_err();
+        for setting in [
+            "tss.threshold",
+            "tss.ciphersuite",
+            "tss.account_scheme",
+            "tss.password",
+            "connectors.enabled",
+            "database.url",
+        ] {
+            assert!(
+                error.contains(setting),
+                "{} not reported in {}",
+                setting,
+                error
+            );
+        }
+    }
+}
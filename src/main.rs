@@ -1,88 +1,91 @@
-use accounts::Account;
+use accounts::{Account, AccountScheme};
 use clap::Parser;
-use connector::ethereum::SwapToken;
-use connector::polkadot;
+use connector::chain_connector::{ConnectorContext, ConnectorRegistry};
 use env_logger::Env;
 use libp2p::gossipsub::Topic;
 use network::network_handler;
+use network::get_bootnodes;
+use network::utils::committee::Committee;
 use network::utils::identity_handler::get_node_identity;
-use std::{sync::Arc, time::Duration};
-// use sc_cli::KeystoreParams;
-use keystore::params::keystore_params::KeystoreParams;
-use sc_keystore::LocalKeystore;
-use sc_service::config::KeystoreConfig;
+use network::utils::peer_book::default_peer_book_path;
+use std::sync::Arc;
+use std::time::Duration;
 use sp_core::crypto::KeyTypeId;
-use sp_keystore::SyncCryptoStorePtr;
 use std::convert::TryFrom;
-use std::env;
-use tango_database::MongoRepo;
-use tango_node::cli::Args;
+use tango_node::cli::{Args, Command};
+use tango_node::commands::{self, get_connection, open_keystore};
+use tango_node::config::NodeConfig;
 use tokio;
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{mpsc, watch, Mutex, Notify};
+use tokio::time;
+use tango_node::shutdown::{shutdown_signal, NETWORK_CLOSE_MARGIN};
+use tss::attestation::AttestationMode;
+use tss::ciphersuite::CiphersuiteId;
+use tss::msg_pool::MsgPoolConfig;
 use tss::tss_event_model::TSSData;
 use tss::tss_service::TssService;
-use web3::transports::Http;
-#[derive(Debug)]
-pub struct BLOCKCHAIN {
-    pub polkadot: String,
-    pub ethereum: String,
-}
-impl BLOCKCHAIN {
-    fn new(polka: &str, eth: &str) -> Self {
-        BLOCKCHAIN {
-            ethereum: String::from(eth),
-            polkadot: String::from(polka),
-        }
-    }
-}
-async fn get_connection(db_url: String) -> MongoRepo {
-    let mut collections = Vec::new();
-    collections.push("events");
-    collections.push("contracts");
-    collections.push("token_swap");
-    collections.push("swap_events");
-    collections.push("tokens");
-    let connector = MongoRepo::init(&db_url, "tango_db", collections).await;
-
-    connector
-}
+use tango_database::{MongoRepo, Repo};
+use events::events::OutboundMessage;
+use events::network::NetworkCommand;
+use events::tss::TssCommand;
 use messages::gossip_message_handler::GossipEventHandler;
 
 #[tokio::main]
 async fn main() {
     // log
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-    let config_dir = env::current_dir().unwrap();
+
+    // get the cli arguments and the effective configuration
+    let args = Args::parse();
+    let config = match NodeConfig::load(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("{}", e);
+            return;
+        }
+    };
+
+    // operator commands do not start the node
+    match &args.command {
+        None | Some(Command::Run(_)) => {}
+        Some(command) => {
+            if let Err(e) = commands::execute(command, &config).await {
+                log::error!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+    if let Err(e) = config.validate() {
+        log::error!("{}", e);
+        return;
+    }
     log::info!("tango node start up ");
 
     //Declare channels and variables
     //Channels
-    let (gossip_sender, gossip_receiver) = mpsc::channel::<Vec<u8>>(1000);
+    let (gossip_sender, gossip_receiver) = mpsc::channel::<OutboundMessage>(1000);
     let message_handler_to_gossip_sender = gossip_sender.clone();
     let tss_to_gossip_sender = gossip_sender.clone();
 
     let (message_handler_to_tss_sender, message_handler_to_tss_receiver) =
         mpsc::channel::<TSSData>(100);
     let (event_sender, event_receiver) = mpsc::channel::<String>(1000);
+    let contracts_changed = Arc::new(Notify::new());
+    let (network_command_sender, network_command_receiver) = mpsc::channel::<NetworkCommand>(16);
+    let (tss_command_sender, tss_command_receiver) = mpsc::channel::<TssCommand>(16);
 
     //Keystore
-    let keystore_params = KeystoreParams::default();
-
-    let keystore = match keystore_params.keystore_config(&config_dir).unwrap() {
-        (_, KeystoreConfig::Path { path, password }) => {
-            // let public = with_crypto_scheme!(self.scheme, to_vec(&suri, password.clone()))?;
-            let keystore: SyncCryptoStorePtr =
-                Arc::new(LocalKeystore::open(path, password).unwrap());
-            keystore
+    let keystore = match open_keystore() {
+        Ok(keystore) => keystore,
+        Err(e) => {
+            log::error!("{}", e);
+            return;
         }
-        _ => unreachable!("keystore_config always returns path and password; qed"),
     };
 
-    // get the cli arguments
-    let args = Args::parse();
-
     // assign tss keytype and keystore
-    let key_type_option = Some(KeyTypeId::try_from(args.key_type.as_str()).unwrap());
+    let key_type_option = Some(KeyTypeId::try_from(config.tss.key_type.as_str()).unwrap());
     let keystore_option = Some(keystore.clone());
 
     let key_type = match key_type_option {
@@ -101,96 +104,159 @@ async fn main() {
         }
     };
 
-    // create account
-    let acc = match Account::new(&args.password.clone(), key_type, key_store){
-        Ok(acc) => acc,
-        Err(e) => panic!("Error creating account: {:?}", e),
+    // signature scheme used when this node collects the tss key group
+    let ciphersuite = match config.tss.ciphersuite.parse::<CiphersuiteId>() {
+        Ok(ciphersuite) => ciphersuite,
+        Err(e) => {
+            log::error!("Invalid ciphersuite: {}", e);
+            return;
+        }
+    };
+
+    // signatures stored with each verified event
+    let attestation_mode = match config.tss.attestation_mode.parse::<AttestationMode>() {
+        Ok(mode) => mode,
+        Err(e) => {
+            log::error!("Invalid attestation mode: {}", e);
+            return;
+        }
+    };
+
+    // nodes dialed on start, in addition to the ones in the peer book
+    let bootnodes = match get_bootnodes(&config.network.bootnodes) {
+        Ok(bootnodes) => bootnodes,
+        Err(e) => {
+            log::error!("Invalid bootnodes: {}", e);
+            return;
+        }
+    };
+    // a new node has a new identity every run, so there are no peers to remember
+    let peer_book_path = config.network.peer_book.clone().or_else(|| {
+        if config.network.new_node {
+            None
+        } else {
+            default_peer_book_path()
+        }
+    });
+
+    // permissioned committee, every peer may join without one
+    let committee = match config.network.committee.as_deref().map(Committee::load).transpose() {
+        Ok(committee) => committee,
+        Err(e) => {
+            log::error!("Unable to read committee: {}", e);
+            return;
+        }
+    };
+
+    // signature scheme of the account key
+    let account_scheme = match config.tss.account_scheme.parse::<AccountScheme>() {
+        Ok(scheme) => scheme,
+        Err(e) => {
+            log::error!("Invalid account scheme: {}", e);
+            return;
+        }
+    };
+
+    // the account key is reused across restarts, a new one is only generated when asked for
+    let acc = match Account::load(account_scheme, key_type, key_store.clone()) {
+        Ok(Some(acc)) => acc,
+        Ok(None) if config.tss.generate_account => {
+            match Account::generate(account_scheme, config.tss.password.as_deref().unwrap_or_default(), key_type, key_store) {
+                Ok(acc) => acc,
+                Err(e) => {
+                    log::error!("Error creating account: {:?}", e);
+                    return;
+                }
+            }
+        }
+        Ok(None) => {
+            log::error!(
+                "No {} account key of type {} in the keystore, run `tango-node account import` or `tango-node account generate`, or start with --generate-account=true",
+                account_scheme,
+                config.tss.key_type
+            );
+            return;
+        }
+        Err(e) => {
+            log::error!("Unable to load account: {}", e);
+            return;
+        }
     };
+    log::info!("Account {} ({})", acc.accounts, account_scheme);
 
     // start the db instance
     // start the actix server mongo instance
-    let db_url = args.db_url.clone();
-    tokio::spawn(async move {
+    let db_url = config.database.url.clone();
+    let server_contracts_changed = contracts_changed.clone();
+    // tss and server stop first, the network stays up while they drain
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+    let (network_shutdown_sender, network_shutdown_receiver) = watch::channel(false);
+    let grace = Duration::from_secs(config.tss.shutdown_grace_secs);
+    let server_shutdown = shutdown_receiver.clone();
+    let server_config = config.server.clone();
+    let server_task = tokio::spawn(async move {
         let connector = get_connection(db_url).await;
-        let ip = args.ip;
-        let port = args.port;
-        let origin = args.origin;
+        let ip = server_config.ip;
+        let port = server_config.port;
+        let origin = server_config.origin;
 
         server::start_server(
             Arc::new(Mutex::new(connector)),
+            server_contracts_changed,
+            network_command_sender,
+            tss_command_sender,
             ip,
             port,
             origin,
-            args.workers,
+            server_config.workers,
+            server_shutdown,
+            grace,
         )
         .await
         .unwrap()
     });
-    let selected_chain = BLOCKCHAIN::new("polkadot", "ethereum");
-    let blockchain = args.blockchain.clone();
 
     // start connector
-    let conn_db_url = args.db_url.clone();
+    let conn_db_url = config.database.url.clone();
     //tss should take the event_receiver
     let connector = get_connection(conn_db_url.clone()).await;
-    // get polkadot on-chain accounts data.
-    if blockchain == selected_chain.polkadot {
-        log::info!("Polkadot chain connected.");
-        let polkadot_event_sender = event_sender.clone();
-        tokio::spawn(async move {
-            tokio::time::sleep(Duration::from_secs(10)).await;
-            let arguments = polkadot::Polkadot {
-                sender: polkadot_event_sender,
-            };
-
-            let _ = polkadot::Polkadot::get_accounts(&arguments).await;
-        });
-    } else if blockchain == selected_chain.ethereum {
-        log::info!("Etherum chain connected.");
-        log::info!("Data fetched successfully from the contract database.",);
-        /////////////////////// event data fetch  removed
-        //
-        // Start the swap token data thread.
-        let connection = connector.clone();
-        let event_sender_cloned = event_sender.clone();
-        tokio::spawn(async move {
-            let mut swap_index = 0;
-            loop {
-                tokio::time::sleep(Duration::from_millis(10000)).await;
-                swap_index += 1;
-                let swap_data = MongoRepo::get_swap_data(&connection.clone()).await;
-                match swap_data {
-                    Ok(swap_data) => {
-                        if swap_data.len() > 0 {
-                            let end_point = Http::new(&swap_data[0].chain_endpoint);
-                            match end_point {
-                                Ok(end_point) => {
-                                    let websocket = web3::Web3::new(end_point);
-                                    let arguments = SwapToken {
-                                        web_socket: websocket,
-                                        connection: connection.clone(),
-                                        sender: event_sender_cloned.clone(),
-                                    };
-                                    let _ = SwapToken::swap_thread_handler(&arguments, swap_index)
-                                        .await;
-                                }
-                                Err(msg) => {
-                                    println!("Endpoint not valid {}", msg);
-                                }
-                            }
-                        }
-                    }
-                    Err(msg) => {
-                        println!("Database connection Error {}", msg);
-                    }
-                }
-            }
-        });
-        ///////////////////////
+    // attestations of an event are upserted by msg_hash, which needs its unique index
+    if let Err(e) = MongoRepo::migrate(&connector).await {
+        log::error!("Unable to create database indexes: {}", e);
+        return;
+    }
+    let connector: Arc<dyn Repo> = Arc::new(connector);
+
+    // start every selected connector, all of them send events to tss
+    let connector_context = ConnectorContext {
+        connection: connector.clone(),
+        sender: event_sender.clone(),
+        eth_confirmations: config.connectors.eth_confirmations,
+        substrate_endpoints: config.connectors.substrate_endpoints.clone(),
+        substrate_events: config
+            .substrate_event_filters()
+            .expect("substrate events are validated"),
+        contracts_changed: contracts_changed.clone(),
+    };
+    let mut connector_registry = ConnectorRegistry::with_defaults();
+    for name in config.connectors.enabled.iter() {
+        if let Err(e) = connector_registry.start(name, &connector_context) {
+            log::error!("Unable to start connector: {}", e);
+            return;
+        }
     }
 
     //Network creating or getting identity
-    let (peer_id, id_keys) = get_node_identity(args.new_node);
+    let (peer_id, id_keys) = get_node_identity(config.network.new_node);
+
+    // other nodes only take part in keygen with peers which registered their account
+    let registration = match commands::register_peer(&peer_id, &id_keys, &acc, key_type, keystore.clone()) {
+        Ok(registration) => registration,
+        Err(e) => {
+            log::error!("Unable to register peer {} for account {}: {}", peer_id, acc.accounts, e);
+            return;
+        }
+    };
 
     //////////////////////////
     //Handler struct to handle messages
@@ -204,36 +270,83 @@ async fn main() {
         message_handler_to_tss_receiver,
         tss_to_gossip_sender,
         event_receiver,
+        tss_command_receiver,
         acc,
+        registration,
         connector,
-        !args.new_node,
+        !config.network.new_node,
+        config.tss.collector.clone(),
         peer_id.to_string(),
-        (args.tss_nodes, args.tss_threshold),
+        (config.tss.nodes, config.tss.threshold),
         key_type_option,
         keystore_option,
+        ciphersuite,
+        attestation_mode,
+        config.tss.observation_tolerance_bps,
+        MsgPoolConfig {
+            max_len: config.tss.msg_pool_size,
+            ttl: Duration::from_secs(config.tss.msg_ttl_secs),
+        },
+        committee.as_ref().map(Committee::peer_ids),
     )
     .await;
 
-    tokio::spawn(async move { tss_service.run().await });
+    let tss_shutdown = shutdown_receiver.clone();
+    let tss_task = tokio::spawn(async move { tss_service.run(tss_shutdown, grace).await });
 
     ////////////////////////// Network Operations //////////////////////////
 
     // start the network
-    let network_topic = &Topic::new(args.p2p_topic);
-    let _ = network_handler::run(
+    let network_topic = &Topic::new(config.network.topic.clone());
+    let network = network_handler::run(
         network_topic,
         gossip_receiver,
+        network_command_receiver,
+        network_shutdown_receiver,
         &handler_message,
-        None,
-        //seed node from cli if something is passed
-        if args.seed_node.is_empty() {
-            None
-        } else {
-            Some(&args.seed_node)
-        },
+        config.network.explicit_peer.as_deref(),
+        &bootnodes,
+        peer_book_path.as_deref(),
+        committee.as_ref(),
         //specific port to run on
-        &args.node_port,
+        &config.network.port.to_string(),
         (peer_id, id_keys),
-    )
-    .await;
+    );
+    tokio::pin!(network);
+
+    let network_stopped = tokio::select! {
+        result = &mut network => {
+            if let Err(e) = result {
+                log::error!("Network stopped: {}", e);
+            }
+            true
+        }
+        _ = shutdown_signal() => false,
+    };
+    log::info!("Shutting down, waiting up to {}s for in-flight work", grace.as_secs());
+
+    // no new events, the tss signs what it already has and the server answers open requests
+    connector_registry.stop_all();
+    let _ = shutdown_sender.send(true);
+    let drain = async {
+        let _ = tss_task.await;
+        let _ = server_task.await;
+        let _ = network_shutdown_sender.send(true);
+    };
+    let close_network = async {
+        if !network_stopped {
+            if let Err(e) = (&mut network).await {
+                log::error!("Network stopped: {}", e);
+            }
+        }
+    };
+    if time::timeout(grace + NETWORK_CLOSE_MARGIN, async {
+        tokio::join!(drain, close_network)
+    })
+    .await
+    .is_err()
+    {
+        log::warn!("Shutdown grace period elapsed, exiting");
+    }
+    log::info!("tango node stopped");
 }
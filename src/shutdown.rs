@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+/// Time the network gets to close its connections after the tss and the server stopped.
+pub const NETWORK_CLOSE_MARGIN: Duration = Duration::from_secs(5);
+
+/// Resolves on Ctrl-C or, on unix, SIGTERM.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                log::error!("Unable to listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                return;
+            }
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {},
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
@@ -0,0 +1,485 @@
+use crate::ciphersuite::{verify_with_group_key, CiphersuiteId};
+use crate::SIGNING_CONTEXT;
+use accounts::{AccountKey, AccountScheme, Registration};
+use frost_dalek::compute_message_hash;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sp_core::hashing::keccak_256;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use tango_database::{Repo, ATTESTATION_FIELDS};
+
+/// Decides which signatures are stored with an event once its threshold signature verifies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttestationMode {
+    /// only the threshold signature of the key group
+    Threshold,
+    /// only the account signature of each node, collected in the `signers` array
+    Individual,
+    /// threshold signature and the account signature of each node
+    Both,
+}
+
+impl AttestationMode {
+    pub fn with_threshold(&self) -> bool {
+        matches!(self, AttestationMode::Threshold | AttestationMode::Both)
+    }
+
+    pub fn with_individual(&self) -> bool {
+        matches!(self, AttestationMode::Individual | AttestationMode::Both)
+    }
+}
+
+impl Default for AttestationMode {
+    fn default() -> Self {
+        AttestationMode::Threshold
+    }
+}
+
+impl FromStr for AttestationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "threshold" => Ok(AttestationMode::Threshold),
+            "individual" => Ok(AttestationMode::Individual),
+            "both" => Ok(AttestationMode::Both),
+            _ => Err(format!("Unknown attestation mode {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for AttestationMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttestationMode::Threshold => write!(f, "threshold"),
+            AttestationMode::Individual => write!(f, "individual"),
+            AttestationMode::Both => write!(f, "both"),
+        }
+    }
+}
+
+/// threshold signature of the key group over an event
+#[derive(Debug, Clone, Serialize)]
+pub struct ThresholdAttestation {
+    pub ciphersuite: String,
+    pub group_key: String,
+    pub signature: String,
+    /// registrations of the key group as known to the storing node
+    pub participants: Vec<Registration>,
+}
+
+/// account signature of a single node over an event
+#[derive(Debug, Clone, Serialize)]
+pub struct IndividualAttestation {
+    /// sr25519, ed25519 or ecdsa
+    pub scheme: String,
+    /// ss58 address of the account
+    pub signer: String,
+    /// hex encoded, ecdsa signs the keccak-256 hash of the event
+    pub signature: String,
+    /// ecdsa only, 0x prefixed address EVM contracts recover from `eth_signature`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eth_address: Option<String>,
+    /// ecdsa only, 0x prefixed `signature` with v of 27 or 28 as `ecrecover` takes it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eth_signature: Option<String>,
+    /// peer of the node which holds the account
+    pub registration: Registration,
+}
+
+impl IndividualAttestation {
+    /// Attestation of `account` signing `msg`, with the Ethereum address and signature of
+    /// an ecdsa account.
+    pub fn new(
+        account: &AccountKey,
+        signature: &[u8],
+        msg: &str,
+        registration: Registration,
+    ) -> Self {
+        let (eth_address, eth_signature) = match account {
+            AccountKey::Ecdsa(_) => match eth_signature(signature, msg.as_bytes()) {
+                Some((address, signature)) => (Some(address), Some(signature)),
+                None => (None, None),
+            },
+            _ => (None, None),
+        };
+        IndividualAttestation {
+            scheme: account.scheme().to_string(),
+            signer: account.to_string(),
+            signature: hex::encode(signature),
+            eth_address,
+            eth_signature,
+            registration,
+        }
+    }
+}
+
+/// Ethereum address of the key which made an ecdsa `signature` over the keccak-256 hash
+/// of `msg`, the last 20 bytes of the keccak-256 hash of its uncompressed public key, and
+/// the signature with v moved from 0 or 1 to 27 or 28. None if no key recovers.
+pub fn eth_signature(signature: &[u8], msg: &[u8]) -> Option<(String, String)> {
+    let mut signature: [u8; 65] = signature.try_into().ok()?;
+    let public = sp_io::crypto::secp256k1_ecdsa_recover(&signature, &keccak_256(msg)).ok()?;
+    let address = &keccak_256(&public)[12..];
+    if signature[64] < 27 {
+        signature[64] += 27;
+    }
+    Some((
+        format!("0x{}", hex::encode(address)),
+        format!("0x{}", hex::encode(signature)),
+    ))
+}
+
+/// stores event with its attestation, all nodes write into the same document of the event
+pub async fn store_attestation(
+    connector: Arc<dyn Repo>,
+    msg: &str,
+    msg_hash: &[u8; 64],
+    threshold: Option<ThresholdAttestation>,
+    individual: Option<IndividualAttestation>,
+) -> Result<(), Box<dyn Error>> {
+    let threshold = match threshold {
+        Some(threshold) => Some(serde_json::to_value(threshold)?),
+        None => None,
+    };
+    let individual = match individual {
+        Some(individual) => Some(serde_json::to_value(individual)?),
+        None => None,
+    };
+
+    connector
+        .upsert_event_attestation(hex::encode(msg_hash), msg, threshold, individual)
+        .await?;
+    Ok(())
+}
+
+/// Attestations of an event document which verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedEvent {
+    pub msg_hash: String,
+    /// ciphersuite of the threshold signature, if the event has one
+    pub threshold: Option<String>,
+    /// accounts of the individual signatures
+    pub signers: Vec<AccountKey>,
+    /// verified registrations of the key group and the signers, one per peer
+    pub registrations: Vec<Registration>,
+}
+
+/// Verifies the attestations of an event document as stored by `store_attestation`, the
+/// document itself or its `data`. Signatures are checked over the stored `signed_event`,
+/// whose fields have to be the ones of the document. Documents stored without it have the
+/// signed event rebuilt out of their fields. Either must match the stored `msg_hash`
+/// before any signature is checked.
+pub fn verify_event_document(document: &Value) -> Result<VerifiedEvent, String> {
+    let data = document.get("data").unwrap_or(document);
+    let fields = data.as_object().ok_or("event document is not an object")?;
+    let msg_hash = fields
+        .get("msg_hash")
+        .and_then(Value::as_str)
+        .ok_or("event document has no msg_hash")?;
+
+    let event: Map<String, Value> = fields
+        .iter()
+        .filter(|(key, _)| !ATTESTATION_FIELDS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    let msg = match fields.get("signed_event") {
+        Some(signed_event) => {
+            let msg = signed_event
+                .as_str()
+                .ok_or("signed_event is not a string")?
+                .to_string();
+            let signed_fields = serde_json::from_str::<Value>(&msg)
+                .map_err(|e| format!("invalid signed_event: {}", e))?;
+            if signed_fields != Value::Object(event) {
+                return Err("event fields differ from the signed event".to_string());
+            }
+            msg
+        }
+        // stored before the signed event was kept, fields are in the signed order
+        None => serde_json::to_string(&event).map_err(|e| e.to_string())?,
+    };
+    if hex::encode(compute_message_hash(&SIGNING_CONTEXT, msg.as_bytes())) != msg_hash {
+        return Err(format!(
+            "event does not match msg_hash {}, it was changed after signing",
+            msg_hash
+        ));
+    }
+
+    let mut registrations: Vec<Registration> = vec![];
+    let threshold = match fields.get("threshold_signature") {
+        Some(threshold) => {
+            let ciphersuite = threshold
+                .get("ciphersuite")
+                .and_then(Value::as_str)
+                .ok_or("threshold signature has no ciphersuite")?;
+            let id = ciphersuite.parse::<CiphersuiteId>()?;
+            let group_key = hex_field(threshold, "group_key")?;
+            let signature = hex_field(threshold, "signature")?;
+            verify_with_group_key(id, &SIGNING_CONTEXT, &group_key, msg.as_bytes(), &signature)
+                .map_err(|e| format!("threshold signature: {}", e))?;
+            // stored before registrations were exchanged
+            for participant in threshold
+                .get("participants")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                add_registration(&mut registrations, participant)?;
+            }
+            Some(ciphersuite.to_string())
+        }
+        None => None,
+    };
+
+    let mut signers = vec![];
+    for signer in fields
+        .get("signers")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let scheme = match signer.get("scheme").and_then(Value::as_str) {
+            Some(scheme) => scheme.parse::<AccountScheme>()?,
+            // stored before accounts had a scheme
+            None => AccountScheme::Sr25519,
+        };
+        let address = signer
+            .get("signer")
+            .and_then(Value::as_str)
+            .ok_or("invalid signer: no account")?;
+        let account = AccountKey::from_ss58check(scheme, address)
+            .map_err(|e| format!("invalid signer: {}", e))?;
+        let signature = signer
+            .get("signature")
+            .and_then(Value::as_str)
+            .ok_or(format!("invalid signature of {}: missing", account))?;
+        let signature = hex::decode(signature.trim_start_matches("0x"))
+            .map_err(|e| format!("invalid signature of {}: {}", account, e))?;
+        if !account.verify(&signature, msg.as_bytes()) {
+            return Err(format!("signature of {} is invalid", account));
+        }
+        if let Some(eth_address) = signer.get("eth_address").and_then(Value::as_str) {
+            let recovered = eth_signature(&signature, msg.as_bytes()).map(|(address, _)| address);
+            if recovered.as_deref() != Some(eth_address) {
+                return Err(format!(
+                    "eth address {} is not the one of {}",
+                    eth_address, account
+                ));
+            }
+        }
+        if let Some(registration) = signer.get("registration") {
+            let registration = add_registration(&mut registrations, registration)?;
+            if registration.account != account {
+                return Err(format!(
+                    "signer {} is stored with the registration of {}",
+                    account, registration.account
+                ));
+            }
+        }
+        signers.push(account);
+    }
+
+    if threshold.is_none() && signers.is_empty() {
+        return Err("event has no attestation".to_string());
+    }
+    Ok(VerifiedEvent {
+        msg_hash: msg_hash.to_string(),
+        threshold,
+        signers,
+        registrations,
+    })
+}
+
+/// verifies a stored registration and keeps the first one of each peer
+fn add_registration(
+    registrations: &mut Vec<Registration>,
+    value: &Value,
+) -> Result<Registration, String> {
+    let registration =
+        Registration::deserialize(value).map_err(|e| format!("invalid registration: {}", e))?;
+    registration.verify()?;
+    if !registrations
+        .iter()
+        .any(|known| known.peer_id == registration.peer_id)
+    {
+        registrations.push(registration.clone());
+    }
+    Ok(registration)
+}
+
+fn hex_field(value: &Value, field: &str) -> Result<Vec<u8>, String> {
+    let encoded = value
+        .get(field)
+        .and_then(Value::as_str)
+        .ok_or(format!("threshold signature has no {}", field))?;
+    hex::decode(encoded).map_err(|e| format!("invalid {}: {}", field, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use accounts::registration::ed25519_peer_id;
+    use serde_json::json;
+    use sp_core::{ecdsa, ed25519, sr25519, Pair};
+
+    #[test]
+    fn verifies_signers_of_stored_event() {
+        let pair = sr25519::Pair::from_string("//Alice", None).unwrap();
+        let ecdsa_pair = ecdsa::Pair::from_string("//Bob", None).unwrap();
+        let peer = ed25519::Pair::from_string("//peer", None).unwrap();
+        let peer_id = ed25519_peer_id(&peer.public());
+        let account = AccountKey::Ecdsa(ecdsa_pair.public());
+        let registration = Registration {
+            peer_id: peer_id.clone(),
+            account,
+            account_signature: ecdsa_pair
+                .sign_prehashed(&keccak_256(&Registration::account_statement(&peer_id)))
+                .0
+                .to_vec(),
+            peer_signature: peer
+                .sign(&Registration::peer_statement(&account))
+                .0
+                .to_vec(),
+        };
+        let msg = r#"{"event_type":"transfer","amount":"10"}"#;
+        let mut data = serde_json::from_str::<Value>(msg).unwrap();
+        data["msg_hash"] =
+            hex::encode(compute_message_hash(&SIGNING_CONTEXT, msg.as_bytes())).into();
+        // the first signer was stored before accounts had a scheme
+        data["signers"] = json!([{
+            "signer": pair.public(),
+            "signature": pair.sign(msg.as_bytes()),
+        }, {
+            "scheme": "ecdsa",
+            "signer": AccountKey::Ecdsa(ecdsa_pair.public()).to_string(),
+            "signature": hex::encode(ecdsa_pair.sign_prehashed(&keccak_256(msg.as_bytes()))),
+            "registration": registration,
+        }]);
+
+        let verified = verify_event_document(&json!({ "data": data })).unwrap();
+        assert_eq!(verified.threshold, None);
+        assert_eq!(
+            verified.signers,
+            vec![
+                AccountKey::Sr25519(pair.public()),
+                AccountKey::Ecdsa(ecdsa_pair.public())
+            ]
+        );
+        assert_eq!(verified.registrations, vec![registration.clone()]);
+
+        // the registration of another account does not vouch for the signer
+        let mut borrowed = data.clone();
+        borrowed["signers"][0]["registration"] = json!(registration);
+        assert!(verify_event_document(&borrowed).is_err());
+
+        let mut wrong_scheme = data.clone();
+        wrong_scheme["signers"][1]["scheme"] = "sr25519".into();
+        assert!(verify_event_document(&wrong_scheme).is_err());
+
+        let mut tampered = data.clone();
+        tampered["amount"] = "11".into();
+        assert!(verify_event_document(&tampered).is_err());
+
+        let mut unsigned = data;
+        unsigned["signers"] = json!([]);
+        assert!(verify_event_document(&unsigned).is_err());
+    }
+
+    //tests that the exact signed string verifies whatever the order of the stored fields
+    #[test]
+    fn verifies_stored_signed_event() {
+        let pair = sr25519::Pair::from_string("//Alice", None).unwrap();
+        let msg = r#"{"swap_price":"1700","event_type":"swap","swap_index":3}"#;
+        let data = json!({
+            "event_type": "swap",
+            "swap_index": 3,
+            "swap_price": "1700",
+            "msg_hash": hex::encode(compute_message_hash(&SIGNING_CONTEXT, msg.as_bytes())),
+            "signed_event": msg,
+            "signers": [{
+                "scheme": "sr25519",
+                "signer": pair.public(),
+                "signature": pair.sign(msg.as_bytes()),
+            }],
+        });
+        let verified = verify_event_document(&data).unwrap();
+        assert_eq!(verified.signers, vec![AccountKey::Sr25519(pair.public())]);
+
+        let mut tampered = data.clone();
+        tampered["swap_price"] = "1800".into();
+        assert!(verify_event_document(&tampered).is_err());
+
+        let mut added = data;
+        added["swap_to"] = "0xdAC1".into();
+        assert!(verify_event_document(&added).is_err());
+    }
+
+    //tests that the ethereum address recovers from the stored 27/28 signature of an ecdsa signer
+    #[test]
+    fn recovers_eth_address_of_ecdsa_signer() {
+        let pair = ecdsa::Pair::from_string("//Bob", None).unwrap();
+        let peer = ed25519::Pair::from_string("//peer", None).unwrap();
+        let peer_id = ed25519_peer_id(&peer.public());
+        let account = AccountKey::Ecdsa(pair.public());
+        let registration = Registration {
+            peer_id: peer_id.clone(),
+            account,
+            account_signature: pair
+                .sign_prehashed(&keccak_256(&Registration::account_statement(&peer_id)))
+                .0
+                .to_vec(),
+            peer_signature: peer
+                .sign(&Registration::peer_statement(&account))
+                .0
+                .to_vec(),
+        };
+        let msg = r#"{"event_type":"transfer","amount":"10"}"#;
+        let signature = pair.sign_prehashed(&keccak_256(msg.as_bytes()));
+        let attestation = IndividualAttestation::new(&account, &signature.0, msg, registration);
+        let stored = serde_json::to_value(&attestation).unwrap();
+
+        let eth_signature = hex::decode(
+            stored["eth_signature"]
+                .as_str()
+                .unwrap()
+                .trim_start_matches("0x"),
+        )
+        .unwrap();
+        let eth_signature: [u8; 65] = eth_signature.try_into().unwrap();
+        assert_eq!(eth_signature[..64], signature.0[..64]);
+        assert!(eth_signature[64] == 27 || eth_signature[64] == 28);
+        let public =
+            sp_io::crypto::secp256k1_ecdsa_recover(&eth_signature, &keccak_256(msg.as_bytes()))
+                .unwrap();
+        assert_eq!(
+            stored["eth_address"],
+            format!("0x{}", hex::encode(&keccak_256(&public)[12..]))
+        );
+
+        let mut data = serde_json::from_str::<Value>(msg).unwrap();
+        data["msg_hash"] =
+            hex::encode(compute_message_hash(&SIGNING_CONTEXT, msg.as_bytes())).into();
+        data["signers"] = json!([stored]);
+        assert!(verify_event_document(&data).is_ok());
+
+        let mut other_address = data;
+        other_address["signers"][0]["eth_address"] = format!("0x{}", hex::encode([0u8; 20])).into();
+        assert!(verify_event_document(&other_address).is_err());
+
+        // other schemes have no ethereum address
+        let sr25519 = sr25519::Pair::from_string("//Alice", None).unwrap();
+        let stored = serde_json::to_value(IndividualAttestation::new(
+            &AccountKey::Sr25519(sr25519.public()),
+            &sr25519.sign(msg.as_bytes()).0,
+            msg,
+            attestation.registration,
+        ))
+        .unwrap();
+        assert!(stored.get("eth_address").is_none());
+        assert!(stored.get("eth_signature").is_none());
+    }
+}
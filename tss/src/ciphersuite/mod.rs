@@ -0,0 +1,365 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use frost_dalek::Parameters;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+pub mod rfc9591;
+pub mod ristretto255;
+
+/// Signature scheme used by a TSS key group.
+/// The collector announces it together with the tss params so every participant
+/// of the key group runs keygen and signing with the same ciphersuite.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CiphersuiteId {
+    /// frost-dalek over ristretto255
+    #[default]
+    Ristretto255,
+    /// FROST(secp256k1, SHA-256) from RFC 9591, cheap to verify on EVM chains
+    Secp256k1,
+    /// FROST(Ed25519, SHA-512) from RFC 9591
+    Ed25519,
+}
+
+impl FromStr for CiphersuiteId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ristretto255" => Ok(CiphersuiteId::Ristretto255),
+            "secp256k1" => Ok(CiphersuiteId::Secp256k1),
+            "ed25519" => Ok(CiphersuiteId::Ed25519),
+            _ => Err(format!("Unknown ciphersuite {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for CiphersuiteId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CiphersuiteId::Ristretto255 => write!(f, "ristretto255"),
+            CiphersuiteId::Secp256k1 => write!(f, "secp256k1"),
+            CiphersuiteId::Ed25519 => write!(f, "ed25519"),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CiphersuiteError {
+    #[error("unable to encode {0}")]
+    Encode(&'static str),
+    #[error("unable to decode {0}")]
+    Decode(&'static str),
+    #[error("{0} not found in local state")]
+    MissingState(&'static str),
+    #[error("{0}")]
+    Protocol(String),
+    #[error("node is not in signers list")]
+    NotSigner,
+    #[error("signing nonces exhausted")]
+    NoncesExhausted,
+}
+
+/// Keygen and signing operations of a threshold signature scheme.
+///
+/// Every value exchanged between nodes is an opaque encoded blob so `TssService`
+/// can drive the DKG and signing rounds without knowing the underlying curve.
+/// Implementations keep their own key material and reset it on `reset`.
+pub trait Ciphersuite: Send + Sync {
+    fn id(&self) -> CiphersuiteId;
+
+    /// creates the local participant and returns it encoded for publishing
+    fn new_participant(
+        &mut self,
+        params: Parameters,
+        index: u32,
+    ) -> Result<Vec<u8>, CiphersuiteError>;
+
+    /// adds a participant of another node, returns number of other participants received
+    fn add_participant(&mut self, data: &[u8]) -> Result<usize, CiphersuiteError>;
+
+    /// runs keygen round one, returns encoded secret shares keyed by receiver index
+    fn round_one(&mut self) -> Result<HashMap<u32, Vec<u8>>, CiphersuiteError>;
+
+    /// picks the local secret share from shares published by another node,
+    /// returns number of secret shares received
+    fn add_secret_share(
+        &mut self,
+        shares: &HashMap<u32, Vec<u8>>,
+    ) -> Result<usize, CiphersuiteError>;
+
+    /// finishes keygen and returns the group key
+    fn finish_keygen(&mut self) -> Result<Vec<u8>, CiphersuiteError>;
+
+    /// generates signing commitments of this node and returns them encoded for publishing
+    fn generate_commitment(&mut self) -> Result<Vec<u8>, CiphersuiteError>;
+
+    /// adds commitments of another node, returns number of other commitments received
+    fn add_commitment(&mut self, data: &[u8]) -> Result<usize, CiphersuiteError>;
+
+    /// used by aggregator to pick threshold many signers for a message, itself included,
+    /// fails with `NoncesExhausted` once the commitments of the signers are used up
+    fn signers(&mut self, msg: &[u8]) -> Result<Vec<u8>, CiphersuiteError>;
+
+    /// used by aggregator when nonces are exhausted, drops the commitments of other nodes
+    /// and generates new local ones. Other nodes publish new commitments with `generate_commitment`.
+    fn refresh_commitments(&mut self) -> Result<(), CiphersuiteError>;
+
+    /// makes partial signature of msg, fails with `NotSigner` if node is not in signers list
+    fn partial_sign(&mut self, msg: &[u8], signers: &[u8]) -> Result<Vec<u8>, CiphersuiteError>;
+
+    /// used by aggregator to make threshold signature out of partial signatures
+    fn aggregate(
+        &self,
+        msg: &[u8],
+        signers: &[u8],
+        partial_signatures: &[Vec<u8>],
+    ) -> Result<Vec<u8>, CiphersuiteError>;
+
+    /// verifies threshold signature of msg against the group key
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> Result<(), CiphersuiteError>;
+
+    fn group_key(&self) -> Option<Vec<u8>>;
+
+    /// drops all key material
+    fn reset(&mut self);
+}
+
+/// make ciphersuite of given type, context is bound into every signature
+pub fn new_ciphersuite(id: CiphersuiteId, context: &[u8]) -> Box<dyn Ciphersuite> {
+    match id {
+        CiphersuiteId::Ristretto255 => Box::new(ristretto255::Ristretto255::new(context)),
+        CiphersuiteId::Secp256k1 => Box::new(rfc9591::Secp256k1::new(id, context)),
+        CiphersuiteId::Ed25519 => Box::new(rfc9591::Ed25519::new(id, context)),
+    }
+}
+
+/// verifies a threshold signature of msg against a group key, e.g. one stored with an event,
+/// without running keygen first
+pub fn verify_with_group_key(
+    id: CiphersuiteId,
+    context: &[u8],
+    group_key: &[u8],
+    msg: &[u8],
+    signature: &[u8],
+) -> Result<(), CiphersuiteError> {
+    match id {
+        CiphersuiteId::Ristretto255 => {
+            ristretto255::Ristretto255::verify_with_group_key(context, group_key, msg, signature)
+        }
+        CiphersuiteId::Secp256k1 => {
+            rfc9591::Secp256k1::verify_with_group_key(context, group_key, msg, signature)
+        }
+        CiphersuiteId::Ed25519 => {
+            rfc9591::Ed25519::verify_with_group_key(context, group_key, msg, signature)
+        }
+    }
+}
+
+/// picks `count` candidates starting at `offset`, wrapping around at the end.
+/// the aggregator passes the nonce index as offset so the signers change with every message
+/// and a node which is offline only misses some of the signatures.
+pub(crate) fn rotate<T>(candidates: Vec<T>, offset: usize, count: usize) -> Vec<T> {
+    if candidates.is_empty() {
+        return candidates;
+    }
+    let start = offset % candidates.len();
+    let mut candidates = candidates;
+    candidates.rotate_left(start);
+    candidates.truncate(count);
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTEXT: &[u8] = b"TANGOS-EVENT-DATA-SIGNING";
+
+    /// runs keygen for n nodes, node 0 is the aggregator and gets the commitments of the others
+    fn keygen(id: CiphersuiteId, params: Parameters) -> (Vec<Box<dyn Ciphersuite>>, Vec<u8>) {
+        let mut nodes: Vec<Box<dyn Ciphersuite>> =
+            (0..params.n).map(|_| new_ciphersuite(id, CONTEXT)).collect();
+
+        let participants: Vec<Vec<u8>> = nodes
+            .iter_mut()
+            .enumerate()
+            .map(|(i, node)| node.new_participant(params, i as u32 + 1).unwrap())
+            .collect();
+        for (i, node) in nodes.iter_mut().enumerate() {
+            for (j, participant) in participants.iter().enumerate() {
+                if i != j {
+                    node.add_participant(participant).unwrap();
+                }
+            }
+        }
+
+        let shares: Vec<HashMap<u32, Vec<u8>>> =
+            nodes.iter_mut().map(|node| node.round_one().unwrap()).collect();
+        for (i, node) in nodes.iter_mut().enumerate() {
+            for (j, share) in shares.iter().enumerate() {
+                if i != j {
+                    node.add_secret_share(share).unwrap();
+                }
+            }
+        }
+
+        let group_keys: Vec<Vec<u8>> = nodes
+            .iter_mut()
+            .map(|node| node.finish_keygen().unwrap())
+            .collect();
+        assert!(group_keys.iter().all(|key| key == &group_keys[0]));
+
+        share_commitments(&mut nodes);
+        (nodes, group_keys[0].clone())
+    }
+
+    fn share_commitments(nodes: &mut [Box<dyn Ciphersuite>]) {
+        let commitments: Vec<Vec<u8>> = nodes
+            .iter_mut()
+            .map(|node| node.generate_commitment().unwrap())
+            .collect();
+        for commitment in commitments.iter().skip(1) {
+            nodes[0].add_commitment(commitment).unwrap();
+        }
+    }
+
+    /// node 0 picks the signers, every node signs if it was picked and node 0 aggregates
+    fn sign(
+        nodes: &mut [Box<dyn Ciphersuite>],
+        t: u32,
+        msg: &[u8],
+    ) -> Result<Vec<u8>, CiphersuiteError> {
+        let signers = nodes[0].signers(msg)?;
+        let mut partial_signatures = vec![];
+        for node in nodes.iter_mut() {
+            match node.partial_sign(msg, &signers) {
+                Ok(partial_signature) => partial_signatures.push(partial_signature),
+                Err(CiphersuiteError::NotSigner) => {}
+                Err(e) => panic!("partial signature failed: {}", e),
+            }
+        }
+        assert_eq!(partial_signatures.len(), t as usize);
+        nodes[0].aggregate(msg, &signers, &partial_signatures)
+    }
+
+    /// indices of the nodes node 0 picks to sign msg
+    fn picked_signers(nodes: &mut [Box<dyn Ciphersuite>], msg: &[u8]) -> Vec<usize> {
+        let signers = nodes[0].signers(msg).unwrap();
+        nodes
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, node)| node.partial_sign(msg, &signers).ok().map(|_| i))
+            .collect()
+    }
+
+    /// every other node gets picked as signer over a few messages
+    fn rotate_signers(id: CiphersuiteId) {
+        let params = Parameters { n: 4, t: 2 };
+        let (mut nodes, _) = keygen(id, params);
+
+        let picked: Vec<Vec<usize>> = (0..params.n)
+            .map(|i| picked_signers(&mut nodes, format!("event {}", i).as_bytes()))
+            .collect();
+        assert!(picked.iter().all(|signers| signers.len() == params.t as usize));
+        assert!(picked.iter().all(|signers| signers.contains(&0)));
+        for node in 1..params.n as usize {
+            assert!(picked.iter().any(|signers| signers.contains(&node)));
+        }
+    }
+
+    /// signs with exactly t of n nodes, the aggregator being one of them
+    fn sign_and_verify(id: CiphersuiteId, params: Parameters) {
+        let (mut nodes, group_key) = keygen(id, params);
+
+        let msg = br#"{"event_type":"swap","swap_price":"1700"}"#;
+        let signature = sign(&mut nodes, params.t, msg).unwrap();
+
+        for node in nodes.iter() {
+            assert!(node.verify(msg, &signature).is_ok());
+            assert!(node.verify(b"tampered", &signature).is_err());
+        }
+
+        assert!(verify_with_group_key(id, CONTEXT, &group_key, msg, &signature).is_ok());
+        assert!(verify_with_group_key(id, CONTEXT, &group_key, b"tampered", &signature).is_err());
+        assert!(verify_with_group_key(id, b"other", &group_key, msg, &signature).is_err());
+    }
+
+    #[test]
+    fn ristretto255_threshold_signature() {
+        sign_and_verify(CiphersuiteId::Ristretto255, Parameters { n: 3, t: 2 });
+        sign_and_verify(CiphersuiteId::Ristretto255, Parameters { n: 4, t: 3 });
+    }
+
+    #[test]
+    fn secp256k1_threshold_signature() {
+        sign_and_verify(CiphersuiteId::Secp256k1, Parameters { n: 3, t: 2 });
+        sign_and_verify(CiphersuiteId::Secp256k1, Parameters { n: 4, t: 3 });
+    }
+
+    #[test]
+    fn ed25519_threshold_signature() {
+        sign_and_verify(CiphersuiteId::Ed25519, Parameters { n: 3, t: 2 });
+        sign_and_verify(CiphersuiteId::Ed25519, Parameters { n: 4, t: 3 });
+    }
+
+    #[test]
+    fn ristretto255_rotate_signers() {
+        rotate_signers(CiphersuiteId::Ristretto255);
+    }
+
+    #[test]
+    fn secp256k1_rotate_signers() {
+        rotate_signers(CiphersuiteId::Secp256k1);
+    }
+
+    #[test]
+    fn rotate_wraps_around() {
+        assert_eq!(rotate(vec![2, 3, 4], 0, 2), vec![2, 3]);
+        assert_eq!(rotate(vec![2, 3, 4], 2, 2), vec![4, 2]);
+        assert_eq!(rotate(vec![2, 3, 4], 4, 2), vec![3, 4]);
+        assert_eq!(rotate(vec![2, 3], 1, 5), vec![3, 2]);
+        assert!(rotate(Vec::<u32>::new(), 1, 2).is_empty());
+    }
+
+    //tests that signing stops once the nonce batch is used up and goes on after a refresh
+    #[test]
+    fn ristretto255_refresh_exhausted_nonces() {
+        let params = Parameters { n: 3, t: 2 };
+        let (mut nodes, _) = keygen(CiphersuiteId::Ristretto255, params);
+
+        for i in 0..ristretto255::NONCE_BATCH_SIZE {
+            let msg = format!("event {}", i);
+            assert!(sign(&mut nodes, params.t, msg.as_bytes()).is_ok());
+        }
+        assert!(matches!(
+            nodes[0].signers(b"one more"),
+            Err(CiphersuiteError::NoncesExhausted)
+        ));
+
+        nodes[0].refresh_commitments().unwrap();
+        share_commitments(&mut nodes);
+        let signature = sign(&mut nodes, params.t, b"one more").unwrap();
+        assert!(nodes[1].verify(b"one more", &signature).is_ok());
+    }
+
+    #[test]
+    fn secp256k1_refresh_exhausted_nonces() {
+        let params = Parameters { n: 3, t: 2 };
+        let (mut nodes, _) = keygen(CiphersuiteId::Secp256k1, params);
+
+        for i in 0..rfc9591::NONCE_BATCH_SIZE {
+            let msg = format!("event {}", i);
+            assert!(sign(&mut nodes, params.t, msg.as_bytes()).is_ok());
+        }
+        assert!(matches!(
+            nodes[0].signers(b"one more"),
+            Err(CiphersuiteError::NoncesExhausted)
+        ));
+
+        nodes[0].refresh_commitments().unwrap();
+        share_commitments(&mut nodes);
+        let signature = sign(&mut nodes, params.t, b"one more").unwrap();
+        assert!(nodes[1].verify(b"one more", &signature).is_ok());
+    }
+}
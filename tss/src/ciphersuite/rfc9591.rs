@@ -0,0 +1,388 @@
+use frost_core::{
+    keys::{
+        dkg::{self, round1, round2},
+        KeyPackage, PublicKeyPackage,
+    },
+    round1::{SigningCommitments, SigningNonces},
+    round2::SignatureShare,
+    Ciphersuite as FrostCiphersuite, Group, Identifier, Signature, SigningPackage, VerifyingKey,
+};
+use frost_dalek::Parameters;
+use rand_core::OsRng;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::ciphersuite::{rotate, Ciphersuite, CiphersuiteError, CiphersuiteId};
+
+/// FROST(secp256k1, SHA-256)
+pub type Secp256k1 = Rfc9591<frost_secp256k1::Secp256K1Sha256>;
+/// FROST(Ed25519, SHA-512)
+pub type Ed25519 = Rfc9591<frost_ed25519::Ed25519Sha512>;
+
+/// number of signing nonces each node precomputes after keygen.
+/// every message consumes one nonce so a nonce is never used twice.
+pub const NONCE_BATCH_SIZE: usize = 256;
+
+/// RFC 9591 FROST keygen and signing for any ciphersuite of the `frost-core` crate
+pub struct Rfc9591<C: FrostCiphersuite> {
+    id: CiphersuiteId,
+    context: Vec<u8>,
+    params: Option<Parameters>,
+    local_index: Option<u32>,
+    round1_secret: Option<round1::SecretPackage<C>>,
+    round1_packages: BTreeMap<Identifier<C>, round1::Package<C>>,
+    round2_secret: Option<round2::SecretPackage<C>>,
+    round2_packages: BTreeMap<Identifier<C>, round2::Package<C>>,
+    participant_index: BTreeMap<Identifier<C>, u32>,
+    key_package: Option<KeyPackage<C>>,
+    public_key_package: Option<PublicKeyPackage<C>>,
+    local_nonces: Vec<Option<SigningNonces<C>>>,
+    local_commitments: Vec<SigningCommitments<C>>,
+    others_commitments: BTreeMap<u32, Vec<SigningCommitments<C>>>,
+    next_nonce: usize,
+}
+
+impl<C: FrostCiphersuite> Rfc9591<C> {
+    pub fn new(id: CiphersuiteId, context: &[u8]) -> Self {
+        Rfc9591 {
+            id,
+            context: context.to_vec(),
+            params: None,
+            local_index: None,
+            round1_secret: None,
+            round1_packages: BTreeMap::new(),
+            round2_secret: None,
+            round2_packages: BTreeMap::new(),
+            participant_index: BTreeMap::new(),
+            key_package: None,
+            public_key_package: None,
+            local_nonces: vec![],
+            local_commitments: vec![],
+            others_commitments: BTreeMap::new(),
+            next_nonce: 0,
+        }
+    }
+
+    /// message actually signed, context is prepended for domain separation
+    fn signing_msg(&self, msg: &[u8]) -> Vec<u8> {
+        [&self.context[..], msg].concat()
+    }
+
+    /// verifies a threshold signature against a group key as returned by `group_key`
+    pub fn verify_with_group_key(
+        context: &[u8],
+        group_key: &[u8],
+        msg: &[u8],
+        signature: &[u8],
+    ) -> Result<(), CiphersuiteError> {
+        let group_key = <C::Group as Group>::Serialization::try_from(group_key.to_vec())
+            .map_err(|_| CiphersuiteError::Decode("group key"))?;
+        let verifying_key = VerifyingKey::<C>::deserialize(group_key)
+            .map_err(|_| CiphersuiteError::Decode("group key"))?;
+        let signature: Signature<C> = decode(signature, "threshold signature")?;
+
+        verifying_key
+            .verify(&[context, msg].concat(), &signature)
+            .map_err(|_| CiphersuiteError::Protocol("threshold signature invalid".into()))
+    }
+
+    /// builds signing package out of signers list published by aggregator
+    fn signing_package(
+        &self,
+        msg: &[u8],
+        signers: &[(u32, SigningCommitments<C>)],
+    ) -> Result<SigningPackage<C>, CiphersuiteError> {
+        let mut commitments = BTreeMap::new();
+        for (index, commitment) in signers {
+            commitments.insert(identifier::<C>(*index)?, *commitment);
+        }
+        Ok(SigningPackage::new(commitments, &self.signing_msg(msg)))
+    }
+}
+
+fn to_u16(value: u32, name: &str) -> Result<u16, CiphersuiteError> {
+    u16::try_from(value)
+        .map_err(|_| CiphersuiteError::Protocol(format!("{} {} exceeds u16", name, value)))
+}
+
+fn identifier<C: FrostCiphersuite>(index: u32) -> Result<Identifier<C>, CiphersuiteError> {
+    u16::try_from(index)
+        .ok()
+        .and_then(|index| Identifier::<C>::try_from(index).ok())
+        .ok_or(CiphersuiteError::Protocol(format!(
+            "invalid participant index {}",
+            index
+        )))
+}
+
+fn encode<T: Serialize>(data: &T, name: &'static str) -> Result<Vec<u8>, CiphersuiteError> {
+    serde_json::to_vec(data).map_err(|_| CiphersuiteError::Encode(name))
+}
+
+fn decode<T: DeserializeOwned>(data: &[u8], name: &'static str) -> Result<T, CiphersuiteError> {
+    serde_json::from_slice(data).map_err(|_| CiphersuiteError::Decode(name))
+}
+
+//the group elements of a ciphersuite are only known to be thread safe for concrete ciphersuites
+impl<C: FrostCiphersuite> Ciphersuite for Rfc9591<C>
+where
+    Rfc9591<C>: Send + Sync,
+{
+    fn id(&self) -> CiphersuiteId {
+        self.id
+    }
+
+    fn new_participant(
+        &mut self,
+        params: Parameters,
+        index: u32,
+    ) -> Result<Vec<u8>, CiphersuiteError> {
+        let (secret_package, package) = dkg::part1(
+            identifier::<C>(index)?,
+            to_u16(params.n, "number of nodes")?,
+            to_u16(params.t, "threshold")?,
+            OsRng,
+        )
+        .map_err(|e| CiphersuiteError::Protocol(format!("keygen part1 failed: {:?}", e)))?;
+
+        self.params = Some(params);
+        self.local_index = Some(index);
+        self.round1_secret = Some(secret_package);
+
+        encode(&(index, package), "participant")
+    }
+
+    fn add_participant(&mut self, data: &[u8]) -> Result<usize, CiphersuiteError> {
+        let (index, package): (u32, round1::Package<C>) = decode(data, "participant")?;
+        let identifier = identifier::<C>(index)?;
+
+        self.participant_index.insert(identifier, index);
+        self.round1_packages.insert(identifier, package);
+        Ok(self.round1_packages.len())
+    }
+
+    fn round_one(&mut self) -> Result<HashMap<u32, Vec<u8>>, CiphersuiteError> {
+        let local_index = self
+            .local_index
+            .ok_or(CiphersuiteError::MissingState("local index"))?;
+        let secret_package = self
+            .round1_secret
+            .take()
+            .ok_or(CiphersuiteError::MissingState("round one secret package"))?;
+
+        let (round2_secret, packages) = dkg::part2(secret_package, &self.round1_packages)
+            .map_err(|e| CiphersuiteError::Protocol(format!("keygen part2 failed: {:?}", e)))?;
+
+        let mut distributed_hashmap = HashMap::new();
+        for (identifier, package) in packages {
+            let index = self
+                .participant_index
+                .get(&identifier)
+                .ok_or(CiphersuiteError::MissingState("participant index"))?;
+            distributed_hashmap.insert(*index, encode(&(local_index, package), "secret share")?);
+        }
+
+        self.round2_secret = Some(round2_secret);
+        Ok(distributed_hashmap)
+    }
+
+    fn add_secret_share(
+        &mut self,
+        shares: &HashMap<u32, Vec<u8>>,
+    ) -> Result<usize, CiphersuiteError> {
+        let local_index = self
+            .local_index
+            .ok_or(CiphersuiteError::MissingState("local index"))?;
+        let data = shares
+            .get(&local_index)
+            .ok_or(CiphersuiteError::MissingState("secret share for local index"))?;
+
+        let (sender, package): (u32, round2::Package<C>) = decode(data, "secret share")?;
+        self.round2_packages.insert(identifier::<C>(sender)?, package);
+        Ok(self.round2_packages.len())
+    }
+
+    fn finish_keygen(&mut self) -> Result<Vec<u8>, CiphersuiteError> {
+        let round2_secret = self
+            .round2_secret
+            .as_ref()
+            .ok_or(CiphersuiteError::MissingState("round two secret package"))?;
+
+        let (key_package, public_key_package) =
+            dkg::part3(round2_secret, &self.round1_packages, &self.round2_packages).map_err(
+                |e| CiphersuiteError::Protocol(format!("keygen part3 failed: {:?}", e)),
+            )?;
+
+        let group_key = public_key_package
+            .verifying_key()
+            .serialize()
+            .as_ref()
+            .to_vec();
+        self.key_package = Some(key_package);
+        self.public_key_package = Some(public_key_package);
+        Ok(group_key)
+    }
+
+    fn generate_commitment(&mut self) -> Result<Vec<u8>, CiphersuiteError> {
+        let local_index = self
+            .local_index
+            .ok_or(CiphersuiteError::MissingState("local index"))?;
+        let key_package = self
+            .key_package
+            .as_ref()
+            .ok_or(CiphersuiteError::MissingState("key package"))?;
+
+        let mut nonces = Vec::with_capacity(NONCE_BATCH_SIZE);
+        let mut commitments = Vec::with_capacity(NONCE_BATCH_SIZE);
+        for _ in 0..NONCE_BATCH_SIZE {
+            let (nonce, commitment) =
+                frost_core::round1::commit(key_package.signing_share(), &mut OsRng);
+            nonces.push(Some(nonce));
+            commitments.push(commitment);
+        }
+        self.local_nonces = nonces;
+        self.local_commitments = commitments.clone();
+        self.next_nonce = 0;
+
+        encode(&(local_index, commitments), "commitment")
+    }
+
+    fn add_commitment(&mut self, data: &[u8]) -> Result<usize, CiphersuiteError> {
+        let (index, commitments): (u32, Vec<SigningCommitments<C>>) =
+            decode(data, "commitment")?;
+        self.others_commitments.insert(index, commitments);
+        Ok(self.others_commitments.len())
+    }
+
+    fn signers(&mut self, _msg: &[u8]) -> Result<Vec<u8>, CiphersuiteError> {
+        let params = self
+            .params
+            .ok_or(CiphersuiteError::MissingState("tss params"))?;
+        let local_index = self
+            .local_index
+            .ok_or(CiphersuiteError::MissingState("local index"))?;
+        if self.local_commitments.is_empty() {
+            return Err(CiphersuiteError::MissingState("local commitments"));
+        }
+        let nonce_index = self.next_nonce;
+        let local_commitment = self
+            .local_commitments
+            .get(nonce_index)
+            .cloned()
+            .ok_or(CiphersuiteError::NoncesExhausted)?;
+
+        //aggregator signs too, the other signers are nodes which still have a commitment,
+        //starting at another node for every message so one offline node does not stall all of them
+        let candidates: Vec<(u32, SigningCommitments<C>)> = self
+            .others_commitments
+            .iter()
+            .filter_map(|(index, commitments)| {
+                commitments
+                    .get(nonce_index)
+                    .map(|commitment| (*index, *commitment))
+            })
+            .collect();
+        let mut signers = vec![(local_index, local_commitment)];
+        signers.extend(rotate(
+            candidates,
+            nonce_index,
+            (params.t as usize).saturating_sub(1),
+        ));
+        if signers.len() < params.t as usize {
+            return Err(CiphersuiteError::MissingState("signer commitments"));
+        }
+        self.next_nonce += 1;
+
+        //this signers list will be used by other nodes to verify themselves.
+        encode(&(nonce_index, signers), "signers")
+    }
+
+    fn refresh_commitments(&mut self) -> Result<(), CiphersuiteError> {
+        self.others_commitments.clear();
+        self.generate_commitment().map(|_| ())
+    }
+
+    fn partial_sign(&mut self, msg: &[u8], signers: &[u8]) -> Result<Vec<u8>, CiphersuiteError> {
+        let local_index = self
+            .local_index
+            .ok_or(CiphersuiteError::MissingState("local index"))?;
+        let (nonce_index, signers): (usize, Vec<(u32, SigningCommitments<C>)>) =
+            decode(signers, "signers")?;
+        if !signers.iter().any(|(index, _)| *index == local_index) {
+            return Err(CiphersuiteError::NotSigner);
+        }
+
+        let signing_package = self.signing_package(msg, &signers)?;
+        let key_package = self
+            .key_package
+            .as_ref()
+            .ok_or(CiphersuiteError::MissingState("key package"))?;
+
+        //nonce is dropped here so it can never be used for another message
+        let nonce = self
+            .local_nonces
+            .get_mut(nonce_index)
+            .and_then(|nonce| nonce.take())
+            .ok_or(CiphersuiteError::Protocol(format!(
+                "signing nonce {} not available",
+                nonce_index
+            )))?;
+
+        let signature_share = frost_core::round2::sign(&signing_package, &nonce, key_package)
+            .map_err(|e| CiphersuiteError::Protocol(format!("signing failed: {:?}", e)))?;
+
+        encode(&(local_index, signature_share), "partial signature")
+    }
+
+    fn aggregate(
+        &self,
+        msg: &[u8],
+        signers: &[u8],
+        partial_signatures: &[Vec<u8>],
+    ) -> Result<Vec<u8>, CiphersuiteError> {
+        let public_key_package = self
+            .public_key_package
+            .as_ref()
+            .ok_or(CiphersuiteError::MissingState("public key package"))?;
+        let (_, signers): (usize, Vec<(u32, SigningCommitments<C>)>) =
+            decode(signers, "signers")?;
+        let signing_package = self.signing_package(msg, &signers)?;
+
+        let mut signature_shares = BTreeMap::new();
+        for item in partial_signatures {
+            let (index, share): (u32, SignatureShare<C>) = decode(item, "partial signature")?;
+            signature_shares.insert(identifier::<C>(index)?, share);
+        }
+
+        let signature =
+            frost_core::aggregate(&signing_package, &signature_shares, public_key_package)
+                .map_err(|e| {
+                    CiphersuiteError::Protocol(format!("aggregating signature failed: {:?}", e))
+                })?;
+
+        encode(&signature, "threshold signature")
+    }
+
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> Result<(), CiphersuiteError> {
+        let public_key_package = self
+            .public_key_package
+            .as_ref()
+            .ok_or(CiphersuiteError::MissingState("public key package"))?;
+        let signature: Signature<C> = decode(signature, "threshold signature")?;
+
+        public_key_package
+            .verifying_key()
+            .verify(&self.signing_msg(msg), &signature)
+            .map_err(|_| CiphersuiteError::Protocol("threshold signature invalid".into()))
+    }
+
+    fn group_key(&self) -> Option<Vec<u8>> {
+        self.public_key_package
+            .as_ref()
+            .map(|package| package.verifying_key().serialize().as_ref().to_vec())
+    }
+
+    fn reset(&mut self) {
+        *self = Rfc9591::new(self.id, &self.context);
+    }
+}
@@ -0,0 +1,435 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use curve25519_dalek::ristretto::RistrettoPoint;
+use frost_dalek::{
+    compute_message_hash, generate_commitment_share_lists,
+    keygen::{Coefficients, RoundOne, SecretShare},
+    precomputation::{PublicCommitmentShareList, SecretCommitmentShareList},
+    signature::{PartialThresholdSignature, SecretKey, Signer, ThresholdSignature},
+    DistributedKeyGeneration, GroupKey, IndividualPublicKey, Parameters, Participant,
+    SignatureAggregator,
+};
+use rand::rngs::OsRng;
+use std::collections::HashMap;
+
+use crate::ciphersuite::{rotate, Ciphersuite, CiphersuiteError, CiphersuiteId};
+use crate::utils::{make_participant, round_one_state};
+
+/// number of signing nonces each node precomputes after keygen.
+/// every message consumes one nonce so a nonce is never used twice.
+pub const NONCE_BATCH_SIZE: usize = 256;
+
+#[derive(Debug, BorshSerialize, BorshDeserialize, Clone, PartialEq)]
+pub struct OthersCommitmentShares {
+    pub public_key: IndividualPublicKey,
+    pub public_commitment_share_list: PublicCommitmentShareList,
+}
+
+/// signers picked by the aggregator and the index of the commitments they sign a message with
+#[derive(BorshSerialize, BorshDeserialize)]
+struct SignersList {
+    nonce_index: u32,
+    signers: Vec<Signer>,
+}
+
+/// frost-dalek keygen and signing over ristretto255
+pub struct Ristretto255 {
+    context: Vec<u8>,
+    params: Option<Parameters>,
+    local_index: Option<u32>,
+    local_participant: Option<(Participant, Coefficients)>,
+    others_participants: Vec<Participant>,
+    local_dkg_r1_state: Option<DistributedKeyGeneration<RoundOne>>,
+    others_my_secret_share: Vec<SecretShare>,
+    local_finished_state: Option<(GroupKey, SecretKey)>,
+    local_public_key: Option<IndividualPublicKey>,
+    /// published commitments of this node, one per nonce
+    local_commitments: Option<PublicCommitmentShareList>,
+    /// secret nonces of the commitments, each dropped once it signed
+    local_nonces: Vec<Option<SecretCommitmentShareList>>,
+    others_commitment_share: Vec<OthersCommitmentShares>,
+    next_nonce: usize,
+}
+
+impl Ristretto255 {
+    pub fn new(context: &[u8]) -> Self {
+        Ristretto255 {
+            context: context.to_vec(),
+            params: None,
+            local_index: None,
+            local_participant: None,
+            others_participants: vec![],
+            local_dkg_r1_state: None,
+            others_my_secret_share: vec![],
+            local_finished_state: None,
+            local_public_key: None,
+            local_commitments: None,
+            local_nonces: vec![],
+            others_commitment_share: vec![],
+            next_nonce: 0,
+        }
+    }
+
+    fn finished_state(&self) -> Result<(GroupKey, SecretKey), CiphersuiteError> {
+        self.local_finished_state
+            .clone()
+            .ok_or(CiphersuiteError::MissingState("local finished state"))
+    }
+
+    /// public key of a signer, either the local node or a node which sent its commitment
+    fn signer_public_key(&self, index: u32) -> Result<IndividualPublicKey, CiphersuiteError> {
+        if Some(index) == self.local_index {
+            return self
+                .local_public_key
+                .clone()
+                .ok_or(CiphersuiteError::MissingState("local public key"));
+        }
+        self.others_commitment_share
+            .iter()
+            .find(|com| com.public_commitment_share_list.participant_index == index)
+            .map(|com| com.public_key.clone())
+            .ok_or(CiphersuiteError::MissingState("signer public key"))
+    }
+
+    /// verifies a threshold signature against a group key as returned by `group_key`
+    pub fn verify_with_group_key(
+        context: &[u8],
+        group_key: &[u8],
+        msg: &[u8],
+        signature: &[u8],
+    ) -> Result<(), CiphersuiteError> {
+        let group_key = <[u8; 32]>::try_from(group_key)
+            .ok()
+            .and_then(|bytes| GroupKey::from_bytes(bytes).ok())
+            .ok_or(CiphersuiteError::Decode("group key"))?;
+        let threshold_signature = ThresholdSignature::try_from_slice(signature)
+            .map_err(|_| CiphersuiteError::Decode("threshold signature"))?;
+
+        let msg_hash = compute_message_hash(context, msg);
+        threshold_signature
+            .verify(&group_key, &msg_hash)
+            .map_err(|_| CiphersuiteError::Protocol("threshold signature invalid".into()))
+    }
+}
+
+impl Ciphersuite for Ristretto255 {
+    fn id(&self) -> CiphersuiteId {
+        CiphersuiteId::Ristretto255
+    }
+
+    fn new_participant(
+        &mut self,
+        params: Parameters,
+        index: u32,
+    ) -> Result<Vec<u8>, CiphersuiteError> {
+        let participant = make_participant(params, index);
+        self.params = Some(params);
+        self.local_index = Some(index);
+        self.local_participant = Some(participant.clone());
+
+        participant
+            .0
+            .try_to_vec()
+            .map_err(|_| CiphersuiteError::Encode("participant"))
+    }
+
+    fn add_participant(&mut self, data: &[u8]) -> Result<usize, CiphersuiteError> {
+        let participant = Participant::try_from_slice(data)
+            .map_err(|_| CiphersuiteError::Decode("participant"))?;
+        if !self.others_participants.contains(&participant) {
+            self.others_participants.push(participant);
+        }
+        Ok(self.others_participants.len())
+    }
+
+    fn round_one(&mut self) -> Result<HashMap<u32, Vec<u8>>, CiphersuiteError> {
+        let params = self
+            .params
+            .ok_or(CiphersuiteError::MissingState("tss params"))?;
+        let local_index = self
+            .local_index
+            .ok_or(CiphersuiteError::MissingState("local index"))?;
+        let participant = self
+            .local_participant
+            .clone()
+            .ok_or(CiphersuiteError::MissingState("local participant"))?;
+
+        let round_one_state = round_one_state(
+            &params,
+            &local_index,
+            &participant.1,
+            &mut self.others_participants,
+        )
+        .map_err(|e| CiphersuiteError::Protocol(format!("round one failed for {:?}", e)))?;
+
+        let secret_shares = round_one_state
+            .their_secret_shares()
+            .map_err(|_| CiphersuiteError::MissingState("secret shares"))?;
+
+        let mut distributed_hashmap = HashMap::new();
+        for share in secret_shares.iter() {
+            let encoded = share
+                .try_to_vec()
+                .map_err(|_| CiphersuiteError::Encode("secret share"))?;
+            distributed_hashmap.insert(share.index, encoded);
+        }
+
+        self.local_dkg_r1_state = Some(round_one_state);
+        Ok(distributed_hashmap)
+    }
+
+    fn add_secret_share(
+        &mut self,
+        shares: &HashMap<u32, Vec<u8>>,
+    ) -> Result<usize, CiphersuiteError> {
+        let local_index = self
+            .local_index
+            .ok_or(CiphersuiteError::MissingState("local index"))?;
+        let data = shares
+            .get(&local_index)
+            .ok_or(CiphersuiteError::MissingState("secret share for local index"))?;
+        let secret_share =
+            SecretShare::try_from_slice(data).map_err(|_| CiphersuiteError::Decode("secret share"))?;
+
+        if !self.others_my_secret_share.contains(&secret_share) {
+            self.others_my_secret_share.push(secret_share);
+        }
+        Ok(self.others_my_secret_share.len())
+    }
+
+    fn finish_keygen(&mut self) -> Result<Vec<u8>, CiphersuiteError> {
+        let round_one = self
+            .local_dkg_r1_state
+            .clone()
+            .ok_or(CiphersuiteError::MissingState("round one state"))?;
+        let participant = self
+            .local_participant
+            .clone()
+            .ok_or(CiphersuiteError::MissingState("local participant"))?;
+        let my_commitment = participant
+            .0
+            .public_key()
+            .ok_or(CiphersuiteError::MissingState("local participant commitment"))?;
+
+        let round_two_state = round_one
+            .to_round_two(self.others_my_secret_share.clone())
+            .map_err(|e| CiphersuiteError::Protocol(format!("round two failed: {:?}", e)))?;
+
+        let (group_key, secret_key) = round_two_state
+            .finish(my_commitment)
+            .map_err(|e| CiphersuiteError::Protocol(format!("finishing state failed: {:?}", e)))?;
+
+        let group_key_bytes = group_key.to_bytes().to_vec();
+        self.local_public_key = Some(secret_key.to_public());
+        self.local_finished_state = Some((group_key, secret_key));
+        Ok(group_key_bytes)
+    }
+
+    fn generate_commitment(&mut self) -> Result<Vec<u8>, CiphersuiteError> {
+        let index = self
+            .local_index
+            .ok_or(CiphersuiteError::MissingState("local index"))?;
+        let public_key = self
+            .local_public_key
+            .clone()
+            .ok_or(CiphersuiteError::MissingState("local public key"))?;
+
+        //one list per nonce, so each secret nonce can be dropped on its own after signing
+        let (mut commitments, nonce) = generate_commitment_share_lists(&mut OsRng, index, 1);
+        let mut nonces = Vec::with_capacity(NONCE_BATCH_SIZE);
+        nonces.push(Some(nonce));
+        for _ in 1..NONCE_BATCH_SIZE {
+            let (commitment, nonce) = generate_commitment_share_lists(&mut OsRng, index, 1);
+            commitments.commitments.extend(commitment.commitments);
+            nonces.push(Some(nonce));
+        }
+        let share_commitment = OthersCommitmentShares {
+            public_key,
+            public_commitment_share_list: commitments.clone(),
+        };
+        self.local_commitments = Some(commitments);
+        self.local_nonces = nonces;
+        self.next_nonce = 0;
+
+        share_commitment
+            .try_to_vec()
+            .map_err(|_| CiphersuiteError::Encode("commitment"))
+    }
+
+    fn add_commitment(&mut self, data: &[u8]) -> Result<usize, CiphersuiteError> {
+        let commitment = OthersCommitmentShares::try_from_slice(data)
+            .map_err(|_| CiphersuiteError::Decode("commitment"))?;
+        //new commitments of a node replace its previous ones
+        let index = commitment.public_commitment_share_list.participant_index;
+        self.others_commitment_share
+            .retain(|com| com.public_commitment_share_list.participant_index != index);
+        self.others_commitment_share.push(commitment);
+        Ok(self.others_commitment_share.len())
+    }
+
+    fn signers(&mut self, msg: &[u8]) -> Result<Vec<u8>, CiphersuiteError> {
+        let params = self
+            .params
+            .ok_or(CiphersuiteError::MissingState("tss params"))?;
+        let finished_state = self.finished_state()?;
+        let local_index = self
+            .local_index
+            .ok_or(CiphersuiteError::MissingState("local index"))?;
+        let nonce_index = self.next_nonce;
+        let local_commitment = *self
+            .local_commitments
+            .as_ref()
+            .ok_or(CiphersuiteError::MissingState("local commitments"))?
+            .commitments
+            .get(nonce_index)
+            .ok_or(CiphersuiteError::NoncesExhausted)?;
+
+        //the other signers are nodes which still have a commitment, starting at another
+        //node for every message so one offline node does not stall all of them
+        let candidates: Vec<(u32, (RistrettoPoint, RistrettoPoint), IndividualPublicKey)> = self
+            .others_commitment_share
+            .iter()
+            .filter_map(|com| {
+                let list = &com.public_commitment_share_list;
+                list.commitments
+                    .get(nonce_index)
+                    .map(|commitment| (list.participant_index, *commitment, com.public_key.clone()))
+            })
+            .collect();
+        let others = (params.t as usize).saturating_sub(1);
+        if candidates.len() < others {
+            return Err(CiphersuiteError::MissingState("signer commitments"));
+        }
+
+        //all nodes should share the same message hash
+        //to verify the threshold signature
+        let mut aggregator =
+            SignatureAggregator::new(params, finished_state.0, &self.context[..], msg);
+
+        //aggregator signs too
+        aggregator.include_signer(
+            local_index,
+            local_commitment,
+            self.signer_public_key(local_index)?,
+        );
+        for (index, commitment, public_key) in rotate(candidates, nonce_index, others) {
+            aggregator.include_signer(index, commitment, public_key);
+        }
+
+        //this signers list will be used by other nodes to verify themselves.
+        let signers = SignersList {
+            nonce_index: nonce_index as u32,
+            signers: aggregator.get_signers().clone(),
+        }
+        .try_to_vec()
+        .map_err(|_| CiphersuiteError::Encode("signers"))?;
+        self.next_nonce += 1;
+        Ok(signers)
+    }
+
+    fn refresh_commitments(&mut self) -> Result<(), CiphersuiteError> {
+        self.others_commitment_share.clear();
+        self.generate_commitment().map(|_| ())
+    }
+
+    fn partial_sign(&mut self, msg: &[u8], signers: &[u8]) -> Result<Vec<u8>, CiphersuiteError> {
+        let final_state = self.finished_state()?;
+        let SignersList {
+            nonce_index,
+            signers,
+        } = SignersList::try_from_slice(signers).map_err(|_| CiphersuiteError::Decode("signers"))?;
+        if !signers
+            .iter()
+            .any(|signer| Some(signer.participant_index) == self.local_index)
+        {
+            return Err(CiphersuiteError::NotSigner);
+        }
+
+        //nonce is dropped here so it can never be used for another message
+        let mut nonce = self
+            .local_nonces
+            .get_mut(nonce_index as usize)
+            .and_then(|nonce| nonce.take())
+            .ok_or(CiphersuiteError::Protocol(format!(
+                "signing nonce {} not available",
+                nonce_index
+            )))?;
+
+        let msg_hash = compute_message_hash(&self.context[..], msg);
+        let partial_signature = final_state
+            .1
+            .sign(&msg_hash, &final_state.0, &mut nonce, 0, &signers)
+            .map_err(|e| CiphersuiteError::Protocol(format!("signing failed: {:?}", e)))?;
+
+        partial_signature
+            .try_to_vec()
+            .map_err(|_| CiphersuiteError::Encode("partial signature"))
+    }
+
+    fn aggregate(
+        &self,
+        msg: &[u8],
+        signers: &[u8],
+        partial_signatures: &[Vec<u8>],
+    ) -> Result<Vec<u8>, CiphersuiteError> {
+        let params = self
+            .params
+            .ok_or(CiphersuiteError::MissingState("tss params"))?;
+        let finished_state = self.finished_state()?;
+        let signers = SignersList::try_from_slice(signers)
+            .map_err(|_| CiphersuiteError::Decode("signers"))?
+            .signers;
+        let mut aggregator =
+            SignatureAggregator::new(params, finished_state.0, &self.context[..], msg);
+
+        //only the signers picked for this message are part of the signature
+        for signer in signers {
+            aggregator.include_signer(
+                signer.participant_index,
+                signer.published_commitment_share,
+                self.signer_public_key(signer.participant_index)?,
+            );
+        }
+
+        //include partial signature
+        for item in partial_signatures {
+            let partial_signature = PartialThresholdSignature::try_from_slice(item)
+                .map_err(|_| CiphersuiteError::Decode("partial signature"))?;
+            aggregator.include_partial_signature(partial_signature);
+        }
+
+        //finalize aggregator
+        let aggregator_finalized = aggregator.finalize().map_err(|e| {
+            CiphersuiteError::Protocol(format!("finalizing aggregator failed: {:?}", e))
+        })?;
+
+        //aggregate aggregator
+        let threshold_signature = aggregator_finalized.aggregate().map_err(|e| {
+            CiphersuiteError::Protocol(format!("aggregating signature failed: {:?}", e))
+        })?;
+
+        threshold_signature
+            .try_to_vec()
+            .map_err(|_| CiphersuiteError::Encode("threshold signature"))
+    }
+
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> Result<(), CiphersuiteError> {
+        let finished_state = self.finished_state()?;
+        let threshold_signature = ThresholdSignature::try_from_slice(signature)
+            .map_err(|_| CiphersuiteError::Decode("threshold signature"))?;
+
+        let msg_hash = compute_message_hash(&self.context[..], msg);
+        threshold_signature
+            .verify(&finished_state.0, &msg_hash)
+            .map_err(|_| CiphersuiteError::Protocol("threshold signature invalid".into()))
+    }
+
+    fn group_key(&self) -> Option<Vec<u8>> {
+        self.local_finished_state
+            .as_ref()
+            .map(|state| state.0.to_bytes().to_vec())
+    }
+
+    fn reset(&mut self) {
+        *self = Ristretto255::new(&self.context);
+    }
+}
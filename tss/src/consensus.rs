@@ -0,0 +1,282 @@
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+
+/// Proposed values further than this from the own observation are not signed, in basis points.
+pub const DEFAULT_OBSERVATION_TOLERANCE_BPS: u64 = 50;
+
+/// Rounds kept in memory, the oldest round is dropped first.
+pub const MAX_OBSERVATION_ROUNDS: usize = 1024;
+
+/// Event whose value is observed by every node independently, so the strings sent by
+/// the connectors of two nodes differ and have to be agreed on before signing.
+/// Swap prices are observed per pair and `swap_index` round.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObservedEvent {
+    pub round_id: String,
+    pub value: u128,
+    pub event: String,
+}
+
+impl ObservedEvent {
+    /// returns observation if data received from connector is one, an error if it is a
+    /// swap event without a valid round or price
+    pub fn parse(data: &str) -> Option<Result<Self, String>> {
+        let json: Value = serde_json::from_str(data).ok()?;
+        if json["event_type"] != "swap" {
+            return None;
+        }
+        Some(Self::parse_swap(&json, data))
+    }
+
+    fn parse_swap(json: &Value, data: &str) -> Result<Self, String> {
+        let field = |name: &str| {
+            json[name]
+                .as_str()
+                .ok_or_else(|| format!("swap event without {}", name))
+        };
+        let swap_index = json["swap_index"]
+            .as_i64()
+            .ok_or("swap event without swap_index")?;
+        let round_id = format!(
+            "swap:{}/{}/{}",
+            field("swap_from")?.to_lowercase(),
+            field("swap_to")?.to_lowercase(),
+            swap_index
+        );
+        let price = field("swap_price")?;
+        let value = price
+            .parse()
+            .map_err(|e| format!("swap price {} of round {}: {}", price, round_id, e))?;
+        Ok(ObservedEvent {
+            round_id,
+            value,
+            event: data.to_string(),
+        })
+    }
+
+    /// same event carrying `value`, the string all nodes sign
+    fn with_value(&self, value: u128, observations: usize) -> Option<String> {
+        let mut json: Value = serde_json::from_str(&self.event).ok()?;
+        json["swap_price"] = Value::String(value.to_string());
+        json["observations"] = Value::from(observations);
+        Some(json.to_string())
+    }
+}
+
+pub fn within_tolerance(observed: u128, proposed: u128, tolerance_bps: u64) -> bool {
+    let deviation = observed.abs_diff(proposed);
+    deviation.saturating_mul(10_000) <= observed.saturating_mul(tolerance_bps as u128)
+}
+
+/// lower middle value for an even count
+fn median(values: &mut [u128]) -> Option<u128> {
+    values.sort_unstable();
+    values.get(values.len().checked_sub(1)? / 2).copied()
+}
+
+#[derive(Debug, Default)]
+struct Round {
+    own: Option<ObservedEvent>,
+    peers: HashMap<String, u128>,
+    proposal: Option<String>,
+    proposed: bool,
+}
+
+/// Observations of this node and its peers by round id.
+/// The aggregator proposes the median once `t` nodes observed a round,
+/// the other nodes only sign a proposal close to their own observation.
+#[derive(Debug, Default)]
+pub struct ObservationRounds {
+    rounds: HashMap<String, Round>,
+    order: VecDeque<String>,
+}
+
+impl ObservationRounds {
+    fn round(&mut self, round_id: &str) -> &mut Round {
+        if !self.rounds.contains_key(round_id) {
+            if self.order.len() >= MAX_OBSERVATION_ROUNDS {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.rounds.remove(&oldest);
+                }
+            }
+            self.order.push_back(round_id.to_string());
+        }
+        self.rounds.entry(round_id.to_string()).or_default()
+    }
+
+    pub fn observe_own(&mut self, observed: ObservedEvent) {
+        let round_id = observed.round_id.clone();
+        self.round(&round_id).own = Some(observed);
+    }
+
+    pub fn observe_peer(&mut self, round_id: &str, peer_id: String, value: u128) {
+        self.round(round_id).peers.insert(peer_id, value);
+    }
+
+    pub fn receive_proposal(&mut self, round_id: &str, event: String) {
+        self.round(round_id).proposal = Some(event);
+    }
+
+    /// Canonical event with the median of all observations, once `min_observations`
+    /// nodes including this one observed the round. Every round is proposed once.
+    pub fn propose(&mut self, round_id: &str, min_observations: usize) -> Option<String> {
+        let round = self.rounds.get_mut(round_id)?;
+        let own = round.own.as_ref()?;
+        if round.proposed || round.peers.len() + 1 < min_observations {
+            return None;
+        }
+
+        let mut values: Vec<u128> = round.peers.values().copied().collect();
+        values.push(own.value);
+        let event = own.with_value(median(&mut values)?, values.len())?;
+        round.proposed = true;
+        Some(event)
+    }
+
+    /// Checks the proposal of a round against the own observation. `None` while one of
+    /// them is missing, the round is dropped once checked.
+    pub fn check_proposal(
+        &mut self,
+        round_id: &str,
+        tolerance_bps: u64,
+    ) -> Option<Result<String, String>> {
+        let round = self.rounds.get(round_id)?;
+        let (own, proposal) = match (&round.own, &round.proposal) {
+            (Some(own), Some(proposal)) => (own.clone(), proposal.clone()),
+            _ => return None,
+        };
+        self.rounds.remove(round_id);
+        self.order.retain(|id| id != round_id);
+
+        let proposed = match ObservedEvent::parse(&proposal) {
+            Some(Ok(proposed)) => proposed,
+            Some(Err(e)) => return Some(Err(format!("invalid proposal: {}", e))),
+            None => return Some(Err("proposal is no observed event".into())),
+        };
+        if proposed.round_id != own.round_id {
+            return Some(Err(format!("proposal is for round {}", proposed.round_id)));
+        }
+        if !within_tolerance(own.value, proposed.value, tolerance_bps) {
+            return Some(Err(format!(
+                "proposed {} but observed {}",
+                proposed.value, own.value
+            )));
+        }
+        Some(Ok(proposal))
+    }
+
+    pub fn len(&self) -> usize {
+        self.rounds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rounds.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn observed(index: i64, price: u128) -> ObservedEvent {
+        ObservedEvent::parse(
+            &json!({
+                "event_type": "swap",
+                "swap_from": "0xC02a",
+                "swap_to": "0xdAC1",
+                "swap_index": index,
+                "swap_price": price.to_string(),
+            })
+            .to_string(),
+        )
+        .unwrap()
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_only_observed_events() {
+        let event = observed(7, 1700);
+        assert_eq!(event.round_id, "swap:0xc02a/0xdac1/7");
+        assert_eq!(event.value, 1700);
+        assert!(ObservedEvent::parse(r#"{"event_type":"log"}"#).is_none());
+        assert!(ObservedEvent::parse("not json").is_none());
+    }
+
+    //tests that a swap event whose price does not fit is reported, not taken for another event
+    #[test]
+    fn reports_invalid_swap_price() {
+        let event = json!({
+            "event_type": "swap",
+            "swap_from": "0xC02a",
+            "swap_to": "0xdAC1",
+            "swap_index": 5,
+            "swap_price": format!("{}0", u128::MAX),
+        })
+        .to_string();
+        assert!(matches!(ObservedEvent::parse(&event), Some(Err(_))));
+        assert!(matches!(
+            ObservedEvent::parse(r#"{"event_type":"swap","swap_index":5}"#),
+            Some(Err(_))
+        ));
+    }
+
+    #[test]
+    fn aggregator_proposes_median_once_threshold_observed() {
+        let mut rounds = ObservationRounds::default();
+        let own = observed(1, 1_700_000);
+        let round_id = own.round_id.clone();
+        rounds.observe_own(own);
+        assert!(rounds.propose(&round_id, 3).is_none());
+
+        rounds.observe_peer(&round_id, "peer-a".into(), 1_702_000);
+        rounds.observe_peer(&round_id, "peer-b".into(), 1_690_000);
+        let proposal = rounds.propose(&round_id, 3).unwrap();
+        let proposed = ObservedEvent::parse(&proposal).unwrap().unwrap();
+        assert_eq!(proposed.round_id, round_id);
+        assert_eq!(proposed.value, 1_700_000);
+
+        assert!(rounds.propose(&round_id, 3).is_none());
+    }
+
+    #[test]
+    fn signs_only_proposals_within_tolerance() {
+        let mut rounds = ObservationRounds::default();
+        let proposal = observed(2, 1_700_000).event;
+
+        let own = observed(2, 1_704_000);
+        let round_id = own.round_id.clone();
+        rounds.receive_proposal(&round_id, proposal.clone());
+        assert!(rounds.check_proposal(&round_id, 50).is_none());
+        rounds.observe_own(own);
+        assert_eq!(
+            rounds.check_proposal(&round_id, 50),
+            Some(Ok(proposal.clone()))
+        );
+        assert!(rounds.is_empty());
+
+        rounds.observe_own(observed(2, 1_800_000));
+        rounds.receive_proposal(&round_id, proposal);
+        assert!(matches!(rounds.check_proposal(&round_id, 50), Some(Err(_))));
+    }
+
+    #[test]
+    fn rejects_proposal_of_other_round() {
+        let mut rounds = ObservationRounds::default();
+        let own = observed(3, 1_700_000);
+        let round_id = own.round_id.clone();
+        rounds.observe_own(own);
+        rounds.receive_proposal(&round_id, observed(4, 1_700_000).event);
+        assert!(matches!(rounds.check_proposal(&round_id, 50), Some(Err(_))));
+    }
+
+    #[test]
+    fn drops_oldest_round() {
+        let mut rounds = ObservationRounds::default();
+        for index in 0..=MAX_OBSERVATION_ROUNDS as i64 {
+            rounds.observe_own(observed(index, 1));
+        }
+        assert_eq!(rounds.len(), MAX_OBSERVATION_ROUNDS);
+        assert!(!rounds.rounds.contains_key(&observed(0, 1).round_id));
+    }
+}
@@ -1,4 +1,8 @@
+pub mod attestation;
+pub mod ciphersuite;
+pub mod consensus;
 pub mod local_state_struct;
+pub mod msg_pool;
 pub mod signverify;
 pub mod tss_event_handler;
 pub mod tss_event_handler_helper;
@@ -9,3 +13,5 @@ pub mod utils;
 // pub const MIN_TSS_NODES: usize = 3;
 pub const DEFUALT_TSS_TOTAL_NODES: u32 = 3;
 pub const DEFUALT_TSS_THRESHOLD: u32 = 2;
+/// bound into every msg hash and threshold signature
+pub const SIGNING_CONTEXT: [u8; 25] = *b"TANGOS-EVENT-DATA-SIGNING";
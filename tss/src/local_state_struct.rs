@@ -1,45 +1,44 @@
+use accounts::Registration;
 use borsh::{BorshDeserialize, BorshSerialize};
-use frost_dalek::{
-    keygen::{Coefficients, RoundOne, RoundTwo, SecretShare},
-    precomputation::{PublicCommitmentShareList, SecretCommitmentShareList},
-    signature::{PartialThresholdSignature, SecretKey, Signer, ThresholdSignature},
-    DistributedKeyGeneration, GroupKey, IndividualPublicKey, Parameters, Participant,
-};
+use frost_dalek::Parameters;
 use keystore::commands::KeyTypeId;
 use sp_keystore::SyncCryptoStore;
 use std::sync::Arc;
-use std::{collections::HashMap, fmt};
+use std::time::Instant;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
-use crate::{DEFUALT_TSS_THRESHOLD, DEFUALT_TSS_TOTAL_NODES};
-
-#[derive(Debug, BorshSerialize, BorshDeserialize, Clone, PartialEq)]
-pub struct OthersCommitmentShares {
-    pub public_key: IndividualPublicKey,
-    pub public_commitment_share_list: PublicCommitmentShareList,
-}
+use crate::ciphersuite::{new_ciphersuite, Ciphersuite, CiphersuiteId};
+use crate::consensus::{ObservationRounds, DEFAULT_OBSERVATION_TOLERANCE_BPS};
+use crate::msg_pool::{EvictionReason, MsgPool, MsgPoolConfig};
+use crate::{DEFUALT_TSS_THRESHOLD, DEFUALT_TSS_TOTAL_NODES, SIGNING_CONTEXT};
 
 #[derive(Debug, BorshSerialize, BorshDeserialize, Clone)]
 pub struct PartialMessageSign {
     pub msg_hash: [u8; 64],
-    pub signers: Vec<Signer>,
+    pub signers: Vec<u8>,
 }
 
 #[derive(Debug, BorshSerialize, BorshDeserialize, Clone)]
 pub struct ReceivePartialSignatureReq {
     pub msg_hash: [u8; 64],
-    pub partial_sign: PartialThresholdSignature,
+    pub partial_sign: Vec<u8>,
 }
 
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
 pub struct VerifyThresholdSignatureReq {
     pub msg_hash: [u8; 64],
-    pub threshold_sign: ThresholdSignature,
+    pub threshold_sign: Vec<u8>,
 }
 
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
 pub struct FilterAndPublishParticipant {
     pub total_peer_list: Vec<String>,
-    pub col_participant: Participant,
+    pub col_participant: Vec<u8>,
+    /// json registrations of the peers in `total_peer_list`
+    pub registrations: Vec<String>,
 }
 
 
@@ -68,22 +67,27 @@ pub struct TSSLocalStateData {
     pub tss_process_state: TSSLocalStateType,
     pub tss_params: Parameters,
     pub local_peer_id: Option<String>,
+    /// peer of the collector, which aggregates threshold signatures
+    pub aggregator_peer_id: Option<String>,
     pub others_peer_id: Vec<String>,
+    /// peers allowed to take part in keygen, every peer if there is no committee
+    pub committee: Option<HashSet<String>>,
+    /// verified registrations by peer id, kept across resets
+    pub registrations: HashMap<String, Registration>,
     pub local_index: Option<u32>,
     pub key_type: Option<KeyTypeId>,
     pub keystore: Option<Arc<dyn SyncCryptoStore>>,
-    pub local_participant: Option<(Participant, Coefficients)>,
-    pub others_participants: Vec<Participant>,
-    pub local_dkg_r1_state: Option<DistributedKeyGeneration<RoundOne>>,
-    pub others_my_secret_share: Vec<SecretShare>,
-    pub local_dkg_r2_state: Option<DistributedKeyGeneration<RoundTwo>>,
-    pub local_finished_state: Option<(GroupKey, SecretKey)>,
-    pub local_public_key: Option<IndividualPublicKey>,
-    pub local_commitment_share: Option<(PublicCommitmentShareList, SecretCommitmentShareList)>,
-    pub others_commitment_share: Vec<OthersCommitmentShares>,
-    pub others_partial_signature: HashMap<[u8; 64], Vec<PartialThresholdSignature>>,
-    pub msg_pool: HashMap<[u8; 64], Vec<u8>>,
-    pub msgs_signature_pending: HashMap<[u8; 64], Vec<Signer>>,
+    pub ciphersuite: Box<dyn Ciphersuite>,
+    pub others_partial_signature: HashMap<[u8; 64], Vec<Vec<u8>>>,
+    pub msg_pool: MsgPool<Vec<u8>>,
+    pub msgs_signers: HashMap<[u8; 64], Vec<u8>>,
+    pub msgs_signature_pending: MsgPool<Vec<u8>>,
+    pub observations: ObservationRounds,
+    pub observation_tolerance_bps: u64,
+    /// when the collector started the running keygen, restarted if it does not finish in time
+    pub keygen_started: Option<Instant>,
+    /// events persisted at the last shutdown are in msg pool
+    pub pending_events_resumed: bool,
 }
 
 impl fmt::Debug for TSSLocalStateData {
@@ -95,28 +99,31 @@ impl fmt::Debug for TSSLocalStateData {
             .field("tss_params", &self.tss_params)
             .field("key_type", &self.key_type)
             .field("local_peer_id", &self.local_peer_id)
+            .field("aggregator_peer_id", &self.aggregator_peer_id)
             .field("others_peer_id", &self.others_peer_id)
+            .field("committee", &self.committee)
+            .field("registrations", &self.registrations.len())
             .field("local_index", &self.local_index)
-            .field("local_participant", &self.local_participant.is_some())
-            .field("others_participants", &self.others_participants.len())
-            .field("local_dkg_r1_state", &self.local_dkg_r1_state.is_some())
-            .field("others_my_secret_share", &self.others_my_secret_share.len())
-            .field("local_dkg_r2_state", &self.local_dkg_r2_state)
-            .field("local_finished_state", &self.local_finished_state)
-            .field("local_public_key", &self.local_public_key)
-            .field("local_commitment_share", &self.local_commitment_share)
-            .field("others_commitment_share", &self.others_commitment_share)
+            .field("ciphersuite", &self.ciphersuite.id())
+            .field("group_key", &self.ciphersuite.group_key())
             .field("others_partial_signature", &self.others_partial_signature)
+            .field("msg_pool", &self.msg_pool.len())
+            .field("msgs_signature_pending", &self.msgs_signature_pending.len())
+            .field("observation_rounds", &self.observations.len())
+            .field("observation_tolerance_bps", &self.observation_tolerance_bps)
+            .field("keygen_started", &self.keygen_started)
+            .field("pending_events_resumed", &self.pending_events_resumed)
             .finish()
     }
 }
 
 impl TSSLocalStateData {
-    pub fn new() -> TSSLocalStateData {
+    pub fn new(ciphersuite: CiphersuiteId, msg_pool_config: MsgPoolConfig) -> TSSLocalStateData {
+        let context = SIGNING_CONTEXT;
         TSSLocalStateData {
             is_node_collector: false,
             is_node_aggregator: false,
-            context: *b"TANGOS-EVENT-DATA-SIGNING",
+            context,
             tss_process_state: TSSLocalStateType::Empty,
             tss_params: Parameters {
                 n: DEFUALT_TSS_TOTAL_NODES,
@@ -125,44 +132,156 @@ impl TSSLocalStateData {
             key_type: None,
             keystore: None,
             local_peer_id: None,
+            aggregator_peer_id: None,
             others_peer_id: vec![],
+            committee: None,
+            registrations: HashMap::new(),
             local_index: None,
-            local_participant: None,
-            others_participants: vec![],
-            local_dkg_r1_state: None,
-            others_my_secret_share: vec![],
-            local_dkg_r2_state: None,
-            local_finished_state: None,
-            local_public_key: None,
-            local_commitment_share: None,
-            others_commitment_share: vec![],
+            ciphersuite: new_ciphersuite(ciphersuite, &context),
             others_partial_signature: HashMap::new(),
-            msg_pool: HashMap::new(),
-            msgs_signature_pending: HashMap::new(),
+            msg_pool: MsgPool::new(msg_pool_config),
+            msgs_signers: HashMap::new(),
+            msgs_signature_pending: MsgPool::new(msg_pool_config),
+            observations: ObservationRounds::default(),
+            observation_tolerance_bps: DEFAULT_OBSERVATION_TOLERANCE_BPS,
+            keygen_started: None,
+            pending_events_resumed: false,
         }
     }
 
     pub fn reset(self: &mut Self) {
+        self.reset_keygen();
         self.is_node_collector = false;
-        self.is_node_aggregator = false;
-        self.tss_process_state = TSSLocalStateType::Empty;
         self.tss_params = Parameters {
             n: DEFUALT_TSS_TOTAL_NODES,
             t: DEFUALT_TSS_THRESHOLD,
         };
+        self.msg_pool.clear();
+        self.msgs_signature_pending.clear();
+        self.observations = ObservationRounds::default();
+        //persisted events are still stored, submitted again after the next keygen
+        self.pending_events_resumed = false;
+    }
+
+    /// Drops the key group so keygen can start over, the role, params and msgs of the node stay.
+    pub fn reset_keygen(self: &mut Self) {
+        self.is_node_aggregator = false;
+        self.tss_process_state = TSSLocalStateType::Empty;
+        self.aggregator_peer_id = None;
         self.others_peer_id = vec![];
         self.local_index = None;
-        self.local_participant = None;
-        self.others_participants = vec![];
-        self.local_dkg_r1_state = None;
-        self.others_my_secret_share = vec![];
-        self.local_dkg_r2_state = None;
-        self.local_finished_state = None;
-        self.local_public_key = None;
-        self.local_commitment_share = None;
-        self.others_commitment_share = vec![];
+        self.ciphersuite.reset();
         self.others_partial_signature = HashMap::new();
-        self.msg_pool = HashMap::new();
-        self.msgs_signature_pending = HashMap::new();
+        self.msgs_signers = HashMap::new();
+        self.keygen_started = None;
+    }
+
+    pub fn is_committee_member(self: &Self, peer_id: &str) -> bool {
+        match &self.committee {
+            Some(committee) => committee.contains(peer_id),
+            None => true,
+        }
+    }
+
+    /// whether `peer_id` is one of the other peers of the key group
+    pub fn is_key_group_member(self: &Self, peer_id: &str) -> bool {
+        self.others_peer_id.iter().any(|other| other == peer_id)
+    }
+
+    /// verifies a json registration and keeps it, returns the registered peer
+    pub fn add_registration(self: &mut Self, registration: &str) -> Result<String, String> {
+        let registration = parse_registration(registration)?;
+        let peer_id = registration.peer_id.clone();
+        self.registrations.insert(peer_id.clone(), registration);
+        Ok(peer_id)
+    }
+
+    /// keeps the registration sent along by the authenticated peer `source`, which has to be
+    /// its own, so a registration replayed by another peer is refused
+    pub fn register_peer(
+        self: &mut Self,
+        source: &str,
+        registration: &str,
+    ) -> Result<(), String> {
+        let registration = parse_registration(registration)?;
+        if registration.peer_id != source {
+            return Err(format!(
+                "{} sent the registration of {}",
+                source, registration.peer_id
+            ));
+        }
+        self.registrations.insert(registration.peer_id.clone(), registration);
+        Ok(())
+    }
+
+    /// registrations of this node and the other peers of the key group
+    pub fn key_group_registrations(self: &Self) -> Vec<Registration> {
+        self.local_peer_id
+            .iter()
+            .chain(self.others_peer_id.iter())
+            .filter_map(|peer_id| self.registrations.get(peer_id).cloned())
+            .collect()
+    }
+
+    /// drops signing state of a msg evicted from msg pool or pending requests
+    pub fn evict_msg(self: &mut Self, msg_hash: [u8; 64], reason: EvictionReason) {
+        self.msg_pool.remove(&msg_hash);
+        self.msgs_signature_pending.remove(&msg_hash);
+        self.msgs_signers.remove(&msg_hash);
+        self.others_partial_signature.remove(&msg_hash);
+        log::warn!("TSS::Evicted msg {} ({})", hex::encode(msg_hash), reason);
+    }
+
+    /// evicts msgs which were not signed within the ttl
+    pub fn expire_msgs(self: &mut Self) {
+        let now = Instant::now();
+        let mut evicted = self.msg_pool.expire(now);
+        evicted.extend(self.msgs_signature_pending.expire(now));
+        for (msg_hash, reason) in evicted {
+            self.evict_msg(msg_hash, reason);
+        }
+    }
+
+    pub fn pool_metrics(&self) -> PoolMetrics {
+        PoolMetrics {
+            msg_pool: self.msg_pool.len(),
+            msgs_signature_pending: self.msgs_signature_pending.len(),
+            observation_rounds: self.observations.len(),
+            expired: self.msg_pool.evicted(EvictionReason::Expired)
+                + self.msgs_signature_pending.evicted(EvictionReason::Expired),
+            evicted_pool_full: self.msg_pool.evicted(EvictionReason::PoolFull)
+                + self.msgs_signature_pending.evicted(EvictionReason::PoolFull),
+        }
+    }
+}
+
+fn parse_registration(registration: &str) -> Result<Registration, String> {
+    let registration: Registration = serde_json::from_str(registration)
+        .map_err(|e| format!("invalid registration: {}", e))?;
+    registration.verify()?;
+    Ok(registration)
+}
+
+/// Depth of the signing queues of a node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolMetrics {
+    pub msg_pool: usize,
+    pub msgs_signature_pending: usize,
+    pub observation_rounds: usize,
+    pub expired: u64,
+    pub evicted_pool_full: u64,
+}
+
+impl fmt::Display for PoolMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "msg_pool={} pending={} rounds={} expired={} evicted_pool_full={}",
+            self.msg_pool,
+            self.msgs_signature_pending,
+            self.observation_rounds,
+            self.expired,
+            self.evicted_pool_full
+        )
     }
 }
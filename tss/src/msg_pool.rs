@@ -0,0 +1,261 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Messages kept per pool before the oldest one is evicted.
+pub const DEFAULT_MSG_POOL_SIZE: usize = 10_000;
+
+/// Messages not signed within this time are evicted.
+pub const DEFAULT_MSG_TTL: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsgPoolConfig {
+    pub max_len: usize,
+    pub ttl: Duration,
+}
+
+impl Default for MsgPoolConfig {
+    fn default() -> Self {
+        MsgPoolConfig {
+            max_len: DEFAULT_MSG_POOL_SIZE,
+            ttl: DEFAULT_MSG_TTL,
+        }
+    }
+}
+
+/// Why a message left the pool without being signed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EvictionReason {
+    /// not signed within the ttl of the pool
+    Expired,
+    /// pool was full and the message was the oldest one
+    PoolFull,
+}
+
+impl fmt::Display for EvictionReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvictionReason::Expired => write!(f, "expired"),
+            EvictionReason::PoolFull => write!(f, "pool full"),
+        }
+    }
+}
+
+/// Messages by msg hash, bounded in size and age so messages that never reach
+/// threshold cannot pile up.
+#[derive(Debug)]
+pub struct MsgPool<V> {
+    config: MsgPoolConfig,
+    msgs: HashMap<[u8; 64], (V, Instant)>,
+    /// msg hashes oldest first, eviction and expiry take them from the front
+    by_age: BTreeSet<(Instant, [u8; 64])>,
+    expired: u64,
+    evicted_full: u64,
+}
+
+impl<V> MsgPool<V> {
+    pub fn new(config: MsgPoolConfig) -> Self {
+        MsgPool {
+            config,
+            msgs: HashMap::new(),
+            by_age: BTreeSet::new(),
+            expired: 0,
+            evicted_full: 0,
+        }
+    }
+
+    /// Adds a message, evicting the oldest one if the pool is full.
+    pub fn insert(&mut self, msg_hash: [u8; 64], value: V) -> Option<([u8; 64], EvictionReason)> {
+        self.insert_at(msg_hash, value, Instant::now())
+    }
+
+    fn insert_at(
+        &mut self,
+        msg_hash: [u8; 64],
+        value: V,
+        now: Instant,
+    ) -> Option<([u8; 64], EvictionReason)> {
+        let mut evicted = None;
+        if !self.msgs.contains_key(&msg_hash) && self.is_full() {
+            if let Some((_, oldest)) = self.by_age.iter().next().copied() {
+                self.remove(&oldest);
+                self.evicted_full += 1;
+                evicted = Some((oldest, EvictionReason::PoolFull));
+            }
+        }
+        if let Some((_, added)) = self.msgs.insert(msg_hash, (value, now)) {
+            self.by_age.remove(&(added, msg_hash));
+        }
+        self.by_age.insert((now, msg_hash));
+        evicted
+    }
+
+    pub fn get(&self, msg_hash: &[u8; 64]) -> Option<&V> {
+        self.msgs.get(msg_hash).map(|(value, _)| value)
+    }
+
+    pub fn remove(&mut self, msg_hash: &[u8; 64]) -> Option<V> {
+        let (value, added) = self.msgs.remove(msg_hash)?;
+        self.by_age.remove(&(added, *msg_hash));
+        Some(value)
+    }
+
+    pub fn contains_key(&self, msg_hash: &[u8; 64]) -> bool {
+        self.msgs.contains_key(msg_hash)
+    }
+
+    pub fn keys(&self) -> Vec<[u8; 64]> {
+        self.msgs.keys().copied().collect()
+    }
+
+    /// Removes messages older than the ttl.
+    pub fn expire(&mut self, now: Instant) -> Vec<([u8; 64], EvictionReason)> {
+        let ttl = self.config.ttl;
+        let expired: Vec<[u8; 64]> = self
+            .by_age
+            .iter()
+            .take_while(|(added, _)| now.saturating_duration_since(*added) > ttl)
+            .map(|(_, msg_hash)| *msg_hash)
+            .collect();
+        for msg_hash in expired.iter() {
+            self.remove(msg_hash);
+        }
+        self.expired += expired.len() as u64;
+        expired
+            .into_iter()
+            .map(|msg_hash| (msg_hash, EvictionReason::Expired))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.msgs.clear();
+        self.by_age.clear();
+    }
+
+    /// Removes all messages, oldest first.
+    pub fn drain(&mut self) -> Vec<([u8; 64], V)> {
+        let by_age = std::mem::take(&mut self.by_age);
+        by_age
+            .into_iter()
+            .filter_map(|(_, msg_hash)| {
+                self.msgs
+                    .remove(&msg_hash)
+                    .map(|(value, _)| (msg_hash, value))
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.msgs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.msgs.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.msgs.len() >= self.config.max_len
+    }
+
+    /// messages evicted since the pool was created, by reason
+    pub fn evicted(&self, reason: EvictionReason) -> u64 {
+        match reason {
+            EvictionReason::Expired => self.expired,
+            EvictionReason::PoolFull => self.evicted_full,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(n: u8) -> [u8; 64] {
+        [n; 64]
+    }
+
+    #[test]
+    fn evicts_oldest_msg_when_full() {
+        let mut pool = MsgPool::new(MsgPoolConfig {
+            max_len: 2,
+            ttl: DEFAULT_MSG_TTL,
+        });
+        let start = Instant::now();
+        assert_eq!(pool.insert_at(hash(1), 1, start), None);
+        assert_eq!(
+            pool.insert_at(hash(2), 2, start + Duration::from_secs(1)),
+            None
+        );
+        assert!(pool.is_full());
+
+        assert_eq!(
+            pool.insert_at(hash(3), 3, start + Duration::from_secs(2)),
+            Some((hash(1), EvictionReason::PoolFull))
+        );
+        assert_eq!(pool.len(), 2);
+        assert!(!pool.contains_key(&hash(1)));
+        assert_eq!(pool.evicted(EvictionReason::PoolFull), 1);
+
+        // replacing a msg does not evict another one
+        assert_eq!(
+            pool.insert_at(hash(3), 4, start + Duration::from_secs(3)),
+            None
+        );
+        assert_eq!(pool.get(&hash(3)), Some(&4));
+    }
+
+    #[test]
+    fn expires_msgs_after_ttl() {
+        let mut pool = MsgPool::new(MsgPoolConfig {
+            max_len: 10,
+            ttl: Duration::from_secs(60),
+        });
+        let start = Instant::now();
+        pool.insert_at(hash(1), (), start);
+        pool.insert_at(hash(2), (), start + Duration::from_secs(30));
+
+        assert!(pool.expire(start + Duration::from_secs(60)).is_empty());
+        assert_eq!(
+            pool.expire(start + Duration::from_secs(61)),
+            vec![(hash(1), EvictionReason::Expired)]
+        );
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.evicted(EvictionReason::Expired), 1);
+    }
+
+    #[test]
+    fn drains_oldest_msg_first() {
+        let mut pool = MsgPool::new(MsgPoolConfig::default());
+        let start = Instant::now();
+        pool.insert_at(hash(2), 2, start + Duration::from_secs(1));
+        pool.insert_at(hash(1), 1, start);
+
+        assert_eq!(pool.drain(), vec![(hash(1), 1), (hash(2), 2)]);
+        assert!(pool.is_empty());
+    }
+
+    //a replaced msg counts as new, the msg added before it is evicted and expires first
+    #[test]
+    fn replaced_msg_moves_to_the_back() {
+        let mut pool = MsgPool::new(MsgPoolConfig {
+            max_len: 2,
+            ttl: Duration::from_secs(60),
+        });
+        let start = Instant::now();
+        pool.insert_at(hash(1), 1, start);
+        pool.insert_at(hash(2), 2, start + Duration::from_secs(1));
+        pool.insert_at(hash(1), 3, start + Duration::from_secs(2));
+
+        assert_eq!(
+            pool.expire(start + Duration::from_secs(62)),
+            vec![(hash(2), EvictionReason::Expired)]
+        );
+        pool.insert_at(hash(4), 4, start + Duration::from_secs(3));
+        assert_eq!(
+            pool.insert_at(hash(5), 5, start + Duration::from_secs(4)),
+            Some((hash(1), EvictionReason::PoolFull))
+        );
+        pool.remove(&hash(4));
+        assert_eq!(pool.drain(), vec![(hash(5), 5)]);
+    }
+}
@@ -8,17 +8,26 @@ impl TssService {
         match tss_gossiped_data.tss_event_type {
             //nodes will be receiving this event to make participant using params
             TSSEventType::ReceiveParams => {
-                self.handler_receive_params(&tss_gossiped_data.tss_data)
-                    .await;
+                self.handler_receive_params(
+                    &tss_gossiped_data.peer_id,
+                    &tss_gossiped_data.tss_data,
+                )
+                .await;
             }
             // nodes will receive peer id of other nodes and will add it to their list
             TSSEventType::ReceivePeerIDForIndex => {
-                self.handler_receive_peer_id_for_index(&tss_gossiped_data.tss_data)
-                    .await;
+                self.handler_receive_peer_id_for_index(
+                    &tss_gossiped_data.peer_id,
+                    &tss_gossiped_data.tss_data,
+                )
+                .await;
             }
             TSSEventType::ReceivePeersWithColParticipant => {
-                self.handler_receiver_peers_with_col_participant(&tss_gossiped_data.tss_data)
-                    .await;
+                self.handler_receiver_peers_with_col_participant(
+                    &tss_gossiped_data.peer_id,
+                    &tss_gossiped_data.tss_data,
+                )
+                .await;
 
             },
             //nodes will receive participant and will add will go to round one state
@@ -56,6 +65,33 @@ impl TssService {
                     .await;
             }
 
+            //aggregator collects observations of a round to propose a value
+            TSSEventType::ReceiveObservation => {
+                self.handler_receive_observation(
+                    &tss_gossiped_data.peer_id,
+                    &tss_gossiped_data.tss_data,
+                )
+                .await;
+            }
+
+            //value proposed by aggregator, checked against own observation before signing
+            TSSEventType::ReceiveProposal => {
+                self.handler_receive_proposal(
+                    &tss_gossiped_data.peer_id,
+                    &tss_gossiped_data.tss_data,
+                )
+                .await;
+            }
+
+            //aggregator ran out of signing nonces and asks for new commitments
+            TSSEventType::RefreshCommitments => {
+                self.handler_refresh_commitments(
+                    &tss_gossiped_data.peer_id,
+                    &tss_gossiped_data.tss_data,
+                )
+                .await;
+            }
+
             //received resetting tss state request
             TSSEventType::ResetTSSState => {
                 self.handler_reset_tss_state(&tss_gossiped_data.tss_data)
@@ -1,40 +1,77 @@
 use crate::{
+    attestation::{store_attestation, IndividualAttestation, ThresholdAttestation},
+    ciphersuite::{new_ciphersuite, CiphersuiteError},
     local_state_struct::{
-        FilterAndPublishParticipant, OthersCommitmentShares, PartialMessageSign,
-        ReceivePartialSignatureReq, TSSLocalStateType, VerifyThresholdSignatureReq,
+        FilterAndPublishParticipant, PartialMessageSign, ReceivePartialSignatureReq,
+        TSSLocalStateType, VerifyThresholdSignatureReq,
     },
     signverify::sign_data,
-    tss_event_model::{PublishPeerIDCall, ReceiveParamsWithPeerCall, TSSEventType, ResetTSSCall},
+    tss_event_model::{
+        ObservationCall, ProposalCall, PublishPeerIDCall, ReceiveParamsWithPeerCall,
+        RefreshCommitmentsCall, ResetTSSCall, TSSEventType,
+    },
     tss_service::TssService,
     utils::{
-        get_participant_index, get_publish_peer_id_msg, make_gossip_tss_data,
-        make_hashmap_for_secret_share, make_participant, round_one_state,
+        get_participant_index, get_participant_peers, get_publish_peer_id_msg,
+        make_gossip_tss_data,
     },
 };
+use events::events::OutboundMessage;
 use borsh::{BorshDeserialize, BorshSerialize};
-use frost_dalek::{
-    generate_commitment_share_lists, keygen::SecretShare, Parameters, Participant,
-    SignatureAggregator,
-};
-use rand::rngs::OsRng;
+use frost_dalek::Parameters;
 use std::collections::HashMap;
+use tango_database::Repo;
 
 impl TssService {
-    // will be run by non collector nodes
-    pub async fn handler_receive_params(self: &mut Self, data: &Vec<u8>) {
+    // will be run by non collector nodes, `source` is the peer the params came from
+    pub async fn handler_receive_params(self: &mut Self, source: &str, data: &Vec<u8>) {
         let local_peer_id = self.tss_local_state.local_peer_id.clone().unwrap();
 
         if self.tss_local_state.tss_process_state == TSSLocalStateType::Empty {
             if let Ok(peer_id_call) = ReceiveParamsWithPeerCall::try_from_slice(&data) {
+                //params are sent by the collector, which aggregates signatures later
+                if !self.tss_local_state.is_committee_member(source) {
+                    log::warn!("TSS::Ignoring params of collector {} outside committee", source);
+                    return;
+                }
+                if peer_id_call.peer_id != source {
+                    log::warn!(
+                        "TSS::Ignoring params of {} sent by {}",
+                        peer_id_call.peer_id,
+                        source
+                    );
+                    return;
+                }
+                let peer_id = peer_id_call.peer_id;
+                if let Err(e) = self
+                    .tss_local_state
+                    .register_peer(source, &peer_id_call.registration)
+                {
+                    log::warn!("TSS::Ignoring params of unregistered collector: {}", e);
+                    return;
+                }
                 self.tss_local_state.tss_params = peer_id_call.params;
                 self.tss_local_state.tss_process_state = TSSLocalStateType::ReceivedParams;
 
-                let peer_id = peer_id_call.peer_id;
+                //key group uses the ciphersuite chosen by collector
+                if self.tss_local_state.ciphersuite.id() != peer_id_call.ciphersuite {
+                    log::info!(
+                        "TSS::switching ciphersuite to {}",
+                        peer_id_call.ciphersuite
+                    );
+                    self.tss_local_state.ciphersuite =
+                        new_ciphersuite(peer_id_call.ciphersuite, &self.tss_local_state.context);
+                }
+
+                self.tss_local_state.aggregator_peer_id = Some(peer_id.clone());
                 if !self.tss_local_state.others_peer_id.contains(&peer_id) {
                     self.tss_local_state.others_peer_id.push(peer_id);
                 }
 
-                if let Ok(peer_id_data) = get_publish_peer_id_msg(local_peer_id.clone()) {
+                let peer_id_msg = self.registration_json().and_then(|registration| {
+                    get_publish_peer_id_msg(local_peer_id.clone(), registration)
+                });
+                if let Ok(peer_id_data) = peer_id_msg {
                     //nodes replies to this event with their peer id
                     if let Ok(data) = make_gossip_tss_data(
                         local_peer_id,
@@ -42,7 +79,11 @@ impl TssService {
                         TSSEventType::ReceivePeerIDForIndex,
                     ) {
                         //log error of gossip sender if failed to send
-                        if let Err(e) = self.tss_to_gossip_sender.send(data.into()).await {
+                        if let Err(e) = self
+                            .tss_to_gossip_sender
+                            .send(OutboundMessage::Broadcast(data))
+                            .await
+                        {
                             log::error!("TSS::error sending peer id for tss init: {}", e);
                         }
                     } else {
@@ -62,8 +103,8 @@ impl TssService {
         }
     }
 
-    //used by node collector to set peers for tss process
-    pub async fn handler_receive_peer_id_for_index(self: &mut Self, data: &Vec<u8>) {
+    //used by node collector to set peers for tss process, `source` is the peer that sent its id
+    pub async fn handler_receive_peer_id_for_index(self: &mut Self, source: &str, data: &Vec<u8>) {
         let local_peer_id = self.tss_local_state.local_peer_id.clone().unwrap();
 
         //receive index and update state of node
@@ -71,7 +112,22 @@ impl TssService {
             && self.tss_local_state.tss_process_state == TSSLocalStateType::Empty
         {
             if let Ok(peer_id_call) = PublishPeerIDCall::try_from_slice(data) {
+                if !self.tss_local_state.is_committee_member(source) {
+                    log::warn!("TSS::Ignoring peer {} outside committee", source);
+                    return;
+                }
                 let peer_id = peer_id_call.peer_id;
+                if peer_id != source {
+                    log::warn!("TSS::Ignoring peer id {} sent by {}", peer_id, source);
+                    return;
+                }
+                if let Err(e) = self
+                    .tss_local_state
+                    .register_peer(source, &peer_id_call.registration)
+                {
+                    log::warn!("TSS::Ignoring unregistered peer: {}", e);
+                    return;
+                }
 
                 if !self.tss_local_state.others_peer_id.contains(&peer_id) {
                     self.tss_local_state.others_peer_id.push(peer_id);
@@ -88,16 +144,29 @@ impl TssService {
                         self.tss_local_state.local_index = Some(index);
 
                         //collector node making participant and publishing
-                        let participant = make_participant(params, index);
-                        self.tss_local_state.local_participant = Some(participant.clone());
+                        let participant =
+                            match self.tss_local_state.ciphersuite.new_participant(params, index) {
+                                Ok(participant) => participant,
+                                Err(e) => {
+                                    log::error!("TSS::Unable to make participant: {}", e);
+                                    return;
+                                }
+                            };
 
                         log::info!("TSS::this nodes participant index {}", index);
 
                         //preparing publish data
                         other_peer_list.push(self.tss_local_state.local_peer_id.clone().unwrap());
+                        let registrations = self
+                            .tss_local_state
+                            .key_group_registrations()
+                            .iter()
+                            .filter_map(|registration| serde_json::to_string(registration).ok())
+                            .collect();
                         let data = FilterAndPublishParticipant {
                             total_peer_list: other_peer_list,
-                            col_participant: participant.0,
+                            col_participant: participant,
+                            registrations,
                         };
 
                         //publish to network
@@ -117,12 +186,42 @@ impl TssService {
         }
     }
 
-    //filter participants and publish participants to network
-    pub async fn handler_receiver_peers_with_col_participant(self: &mut Self, data: &Vec<u8>) {
+    //filter participants and publish participants to network, `source` is the peer that sent them
+    pub async fn handler_receiver_peers_with_col_participant(
+        self: &mut Self,
+        source: &str,
+        data: &Vec<u8>,
+    ) {
         let local_peer_id = self.tss_local_state.local_peer_id.clone().unwrap();
         if self.tss_local_state.tss_process_state == TSSLocalStateType::ReceivedParams {
             if let Ok(data) = FilterAndPublishParticipant::try_from_slice(data) {
+                //the peer list and the registrations are relayed by the collector of the params
+                if self.tss_local_state.aggregator_peer_id.as_deref() != Some(source) {
+                    log::warn!("TSS::Ignoring peers sent by {}, not the collector", source);
+                    return;
+                }
                 let mut other_peer_list = data.total_peer_list;
+                if let Some(peer_id) = other_peer_list
+                    .iter()
+                    .find(|peer_id| !self.tss_local_state.is_committee_member(peer_id))
+                {
+                    log::warn!("TSS::Refusing keygen with peer {} outside committee", peer_id);
+                    return;
+                }
+
+                //every peer of the key group has to be traceable to its account
+                for registration in &data.registrations {
+                    if let Err(e) = self.tss_local_state.add_registration(registration) {
+                        log::warn!("TSS::Ignoring registration: {}", e);
+                    }
+                }
+                if let Some(peer_id) = other_peer_list
+                    .iter()
+                    .find(|peer_id| !self.tss_local_state.registrations.contains_key(*peer_id))
+                {
+                    log::warn!("TSS::Refusing keygen with unregistered peer {}", peer_id);
+                    return;
+                }
 
                 if let Some(index) = other_peer_list.iter().position(|x| x.eq(&local_peer_id)) {
                     other_peer_list.remove(index);
@@ -132,14 +231,13 @@ impl TssService {
                     return;
                 }
 
-                if !self
+                if let Err(e) = self
                     .tss_local_state
-                    .others_participants
-                    .contains(&data.col_participant)
+                    .ciphersuite
+                    .add_participant(&data.col_participant)
                 {
-                    self.tss_local_state
-                        .others_participants
-                        .push(data.col_participant);
+                    log::error!("TSS::Unable to add collector participant: {}", e);
+                    return;
                 }
 
                 self.tss_local_state.others_peer_id = other_peer_list.clone();
@@ -147,12 +245,19 @@ impl TssService {
                 self.tss_local_state.local_index = Some(index);
 
                 //make participant and publish
-                let participant = make_participant(self.tss_local_state.tss_params, index);
-                self.tss_local_state.local_participant = Some(participant.clone());
+                let params = self.tss_local_state.tss_params;
+                let participant = match self.tss_local_state.ciphersuite.new_participant(params, index)
+                {
+                    Ok(participant) => participant,
+                    Err(e) => {
+                        log::error!("TSS::Unable to make participant: {}", e);
+                        return;
+                    }
+                };
 
-                self.publish_to_network::<Participant>(
+                self.publish_to_network::<Vec<u8>>(
                     local_peer_id,
-                    participant.0,
+                    participant,
                     TSSEventType::ReceiveParticipant,
                 )
                 .await;
@@ -166,68 +271,63 @@ impl TssService {
         let local_peer_id = self.tss_local_state.local_peer_id.clone().unwrap();
         //receive participants and update state of node
         if self.tss_local_state.tss_process_state == TSSLocalStateType::ReceivedPeers {
-            if let Ok(participant) = Participant::try_from_slice(data) {
-                if !self
-                    .tss_local_state
-                    .others_participants
-                    .contains(&participant)
-                {
-                    self.tss_local_state.others_participants.push(participant);
-                }
+            if let Ok(participant) = Vec::<u8>::try_from_slice(data) {
+                let others_participants =
+                    match self.tss_local_state.ciphersuite.add_participant(&participant) {
+                        Ok(count) => count,
+                        Err(e) => {
+                            log::error!("TSS::Error adding participant: {}", e);
+                            return;
+                        }
+                    };
 
                 let params = self.tss_local_state.tss_params;
                 let total_nodes = params.n;
-                let other_nodes = (self.tss_local_state.others_participants.len() + 1) as u32;
+                let other_nodes = (others_participants + 1) as u32;
                 if total_nodes == other_nodes {
                     // received total participants proceed to next process
                     // creating secret share etc
-                    let local_index = match self.tss_local_state.local_index {
-                        Some(index) => index,
-                        None => {
-                            log::error!("TSS::local index not found");
-                            return;
+                    match self.tss_local_state.ciphersuite.round_one() {
+                        Ok(distributed_hashmap) => {
+                            self.tss_local_state.tss_process_state =
+                                TSSLocalStateType::DkgGeneratedR1;
+                            log::info!("TSS::Keygen phase 1 done");
+
+                            //send every node its own secret share
+                            let participant_peers = get_participant_peers(
+                                &local_peer_id,
+                                &self.tss_local_state.others_peer_id,
+                            );
+                            let mut unaddressed = vec![];
+                            for (index, share) in distributed_hashmap {
+                                match participant_peers.get(&index) {
+                                    Some(peer_id) => {
+                                        let share: HashMap<u32, Vec<u8>> =
+                                            HashMap::from([(index, share)]);
+                                        self.send_to_peer(
+                                            local_peer_id.clone(),
+                                            peer_id.clone(),
+                                            share,
+                                            TSSEventType::ReceiveSecretShare,
+                                        )
+                                        .await;
+                                    }
+                                    None => {
+                                        unaddressed.push(index);
+                                    }
+                                }
+                            }
+                            //shares are secret, they are never gossiped, keygen restarts on timeout
+                            if !unaddressed.is_empty() {
+                                log::error!(
+                                    "TSS::no peer for secret shares of {:?}, keygen cannot finish",
+                                    unaddressed
+                                );
+                            }
                         }
-                    };
-                    let participant = match &self.tss_local_state.local_participant.clone() {
-                        Some(participant) => participant.clone(),
-                        None => {
-                            log::error!("TSS::local participant not found");
-                            return;
+                        Err(e) => {
+                            log::error!("TSS::error in generating round one state: {}", e);
                         }
-                    };
-                    if let Ok(round_one_state) = round_one_state(
-                        &params,
-                        &local_index,
-                        &participant.1,
-                        &mut self.tss_local_state.others_participants,
-                    ) {
-                        //making hashmap of our their_secret_share
-                        let secret_shares = match round_one_state.their_secret_shares() {
-                            Ok(secret_shares) => secret_shares,
-                            Err(e) => {
-                                log::error!("TSS::error getting secret shares: {:#?}", e);
-                                return;
-                            }
-                        };
-
-                        //making hash table for secret share with index
-                        let distributed_hashmap =
-                            make_hashmap_for_secret_share(secret_shares).await;
-
-                        self.tss_local_state.local_dkg_r1_state = Some(round_one_state.clone());
-
-                        self.tss_local_state.tss_process_state = TSSLocalStateType::DkgGeneratedR1;
-                        log::info!("TSS::Keygen phase 1 done");
-
-                        //publish everyone's secret share to network
-                        self.publish_to_network::<HashMap<u32, SecretShare>>(
-                            local_peer_id,
-                            distributed_hashmap.clone(),
-                            TSSEventType::ReceiveSecretShare,
-                        )
-                        .await;
-                    } else {
-                        log::error!("TSS::error in generating round one state");
                     }
                 }
             } else {
@@ -246,144 +346,63 @@ impl TssService {
         let local_peer_id = self.tss_local_state.local_peer_id.clone().unwrap();
         //receive secret shares and update state of node
         if self.tss_local_state.tss_process_state == TSSLocalStateType::DkgGeneratedR1 {
-            if let Ok(distributed_hashmap) = HashMap::<u32, SecretShare>::try_from_slice(data) {
-                let local_index = match self.tss_local_state.local_index {
-                    Some(index) => index,
-                    None => {
-                        log::error!("TSS::unable to get local index");
+            if let Ok(distributed_hashmap) = HashMap::<u32, Vec<u8>>::try_from_slice(data) {
+                let other_nodes = match self
+                    .tss_local_state
+                    .ciphersuite
+                    .add_secret_share(&distributed_hashmap)
+                {
+                    Ok(count) => count as u32,
+                    Err(e) => {
+                        log::error!("TSS::Could not get secret share: {}", e);
                         return;
                     }
                 };
-                if let Some(secret_share) = distributed_hashmap.get(&local_index) {
-                    if !self
-                        .tss_local_state
-                        .others_my_secret_share
-                        .contains(secret_share)
-                    {
-                        self.tss_local_state
-                            .others_my_secret_share
-                            .push(secret_share.clone());
+
+                let params = self.tss_local_state.tss_params;
+                let total_nodes = params.n;
+                if total_nodes == other_nodes + 1 {
+                    match self.tss_local_state.ciphersuite.finish_keygen() {
+                        Ok(local_group_key) => {
+                            self.tss_local_state.tss_process_state =
+                                TSSLocalStateType::StateFinished;
+
+                            log::info!("TSS::==========================");
+                            log::info!(
+                                "TSS::local {} group key is: {:?}",
+                                self.tss_local_state.ciphersuite.id(),
+                                local_group_key
+                            );
+                            log::info!("TSS::==========================");
+                        }
+                        Err(e) => {
+                            log::error!("TSS::error occured while finishing state: {}", e);
+                            return;
+                        }
                     }
 
-                    let others_my_secret_shares =
-                        self.tss_local_state.others_my_secret_share.clone();
-                    let params = self.tss_local_state.tss_params;
-                    let total_nodes = params.n;
-                    let other_nodes = self.tss_local_state.others_my_secret_share.len() as u32;
-                    if total_nodes == other_nodes + 1 {
-                        let round_one = match self.tss_local_state.local_dkg_r1_state.clone() {
-                            Some(round_one) => round_one,
-                            None => {
-                                log::error!("TSS::Could not get round one state from local state");
-                                return;
-                            }
-                        };
-                        match round_one.to_round_two(others_my_secret_shares) {
-                            Ok(round_two_state) => {
-                                self.tss_local_state.local_dkg_r2_state =
-                                    Some(round_two_state.clone());
-
-                                self.tss_local_state.tss_process_state =
-                                    TSSLocalStateType::DkgGeneratedR2;
-                                log::info!("TSS::Keygen phase 2 done");
-
-                                //finish local state progress
-                                let participant =
-                                    match self.tss_local_state.local_participant.clone() {
-                                        Some(participant) => participant,
-                                        None => {
-                                            log::error!(
-                                        "TSS::Unable to get local participant from local state"
-                                    );
-                                            return;
-                                        }
-                                    };
-                                let my_commitment = match participant.0.public_key() {
-                                    Some(commitment) => commitment,
-                                    None => {
-                                        log::error!(
-                                            "TSS::Unable to get commitment from local participant"
-                                        );
-                                        return;
-                                    }
-                                };
-                                if let Ok((local_group_key, local_secret_key)) =
-                                    round_two_state.finish(my_commitment)
-                                {
-                                    //update local state
-                                    self.tss_local_state.local_finished_state =
-                                        Some((local_group_key, local_secret_key.clone()));
-                                    self.tss_local_state.local_public_key =
-                                        Some(local_secret_key.to_public());
-                                    self.tss_local_state.tss_process_state =
-                                        TSSLocalStateType::StateFinished;
-
-                                    log::info!("TSS::==========================");
-                                    log::info!(
-                                        "TSS::local group key is: {:?}",
-                                        local_group_key.to_bytes()
-                                    );
-                                    log::info!("TSS::==========================");
-                                } else {
-                                    log::error!("TSS::error occured while finishing state");
-                                }
+                    //generating and publishing commitment to include node in tss process
+                    //aggregator patch
+                    self.tss_local_state.is_node_aggregator = self.tss_local_state.is_node_collector;
 
-                                //generating and publishing commitment to include node in tss process
-                                let index = match self.tss_local_state.local_index {
-                                    Some(index) => index,
-                                    None => {
-                                        log::error!("TSS::unable to get local index");
-                                        return;
-                                    }
-                                };
-                                if self.tss_local_state.tss_process_state
-                                    == TSSLocalStateType::StateFinished
-                                {
-                                    //aggregator patch
-                                    self.tss_local_state.is_node_aggregator =
-                                        self.tss_local_state.is_node_collector;
-
-                                    let local_commitment =
-                                        generate_commitment_share_lists(&mut OsRng, index, 1);
-
-                                    self.tss_local_state.local_commitment_share =
-                                        Some(local_commitment.clone());
-
-                                    let pubkey = match self.tss_local_state.local_public_key.clone()
-                                    {
-                                        Some(pubkey) => pubkey,
-                                        None => {
-                                            log::error!(
-                                            "TSS::Unable to get local public key from local state"
-                                        );
-                                            return;
-                                        }
-                                    };
-
-                                    let share_commitment = OthersCommitmentShares {
-                                        public_key: pubkey,
-                                        public_commitment_share_list: local_commitment.0.clone(),
-                                    };
-
-                                    //publish publicCommitmentSharelist to network
-                                    self.publish_to_network(
-                                        local_peer_id,
-                                        share_commitment,
-                                        TSSEventType::ReceiveCommitment,
-                                    )
-                                    .await;
-                                }
-                            }
-                            Err(e) => {
-                                log::error!("TSS::Error in round two state: {:#?}", e);
-                                return;
-                            }
+                    let share_commitment = match self.tss_local_state.ciphersuite.generate_commitment()
+                    {
+                        Ok(commitment) => commitment,
+                        Err(e) => {
+                            log::error!("TSS::Unable to generate commitment: {}", e);
+                            return;
                         }
-                    } else {
-                        log::info!("TSS::Waiting for other nodes secret share");
-                    }
+                    };
+
+                    //publicCommitmentSharelist is only used by the aggregator
+                    self.send_to_aggregator::<Vec<u8>>(
+                        local_peer_id,
+                        share_commitment,
+                        TSSEventType::ReceiveCommitment,
+                    )
+                    .await;
                 } else {
-                    log::error!("TSS::Could not get secret share hash table");
+                    log::info!("TSS::Waiting for other nodes secret share");
                 }
             } else {
                 log::error!("TSS::Unable to deserialize secret share TSS Params");
@@ -402,27 +421,27 @@ impl TssService {
             if self.tss_local_state.tss_process_state >= TSSLocalStateType::DkgGeneratedR1
                 && self.tss_local_state.tss_process_state <= TSSLocalStateType::StateFinished
             {
-                if let Ok(commitment) = OthersCommitmentShares::try_from_slice(data) {
-                    if !self
-                        .tss_local_state
-                        .others_commitment_share
-                        .contains(&commitment)
-                    {
-                        self.tss_local_state
-                            .others_commitment_share
-                            .push(commitment);
-                    }
+                if let Ok(commitment) = Vec::<u8>::try_from_slice(data) {
+                    let others_commitments =
+                        match self.tss_local_state.ciphersuite.add_commitment(&commitment) {
+                            Ok(count) => count,
+                            Err(e) => {
+                                log::error!("TSS::Unable to add commitment: {}", e);
+                                return;
+                            }
+                        };
 
                     let params = self.tss_local_state.tss_params;
-                    if self.tss_local_state.others_commitment_share.len() == (params.n - 1) as usize
+                    if others_commitments == (params.n - 1) as usize
                     {
                         log::info!("TSS::Received all commitments");
                         self.tss_local_state.tss_process_state =
                             TSSLocalStateType::CommitmentsReceived;
+                        self.request_pending_signatures().await;
                     } else {
                         log::info!(
                             "TSS::Not enough commitments, Got {}, Needed {}",
-                            self.tss_local_state.others_commitment_share.len(),
+                            others_commitments,
                             params.n - 1
                         );
                     }
@@ -442,34 +461,20 @@ impl TssService {
 
         if self.tss_local_state.tss_process_state >= TSSLocalStateType::StateFinished {
             if let Ok(msg_req) = PartialMessageSign::try_from_slice(data) {
-                let final_state = match self.tss_local_state.local_finished_state.clone() {
-                    Some(final_state) => final_state,
-                    None => {
-                        log::error!("TSS::Unable to get local finished state from local state");
-                        return;
-                    }
-                };
-
-                let mut my_commitment = match self.tss_local_state.local_commitment_share.clone() {
-                    Some(commitment) => commitment,
-                    None => {
-                        log::error!("TSS::Unable to get local commitment share from local state");
-                        return;
-                    }
-                };
-
-                if let Some(_) = self.tss_local_state.msg_pool.get(&msg_req.msg_hash) {
+                if let Some(msg) = self.tss_local_state.msg_pool.get(&msg_req.msg_hash).cloned() {
                     //making partial signature here
-                    let partial_signature = match final_state.1.sign(
-                        &msg_req.msg_hash,
-                        &final_state.0,
-                        &mut my_commitment.1,
-                        0,
-                        &msg_req.signers,
-                    ) {
+                    let partial_signature = match self
+                        .tss_local_state
+                        .ciphersuite
+                        .partial_sign(&msg, &msg_req.signers)
+                    {
                         Ok(partial_signature) => partial_signature,
+                        Err(CiphersuiteError::NotSigner) => {
+                            log::debug!("TSS::node not picked as signer of msg");
+                            return;
+                        }
                         Err(e) => {
-                            log::error!("TSS::error occured while signing: {:?}", e);
+                            log::error!("TSS::error occured while signing: {}", e);
                             return;
                         }
                     };
@@ -479,8 +484,8 @@ impl TssService {
                         partial_sign: partial_signature,
                     };
 
-                    //publish partial signature to network
-                    self.publish_to_network(
+                    //partial signature is only needed by the aggregator
+                    self.send_to_aggregator(
                         local_peer_id,
                         gossip_data,
                         TSSEventType::PartialSignatureReceived,
@@ -488,9 +493,13 @@ impl TssService {
                     .await;
                 } else {
                     log::error!("TSS::data received for signing but not in local pool");
-                    self.tss_local_state
+                    if let Some((evicted, reason)) = self
+                        .tss_local_state
                         .msgs_signature_pending
-                        .insert(msg_req.msg_hash, msg_req.signers);
+                        .insert(msg_req.msg_hash, msg_req.signers)
+                    {
+                        self.tss_local_state.evict_msg(evicted, reason);
+                    }
                 }
             } else {
                 log::error!("TSS::Unable to deserialize PartialMessageSign");
@@ -507,89 +516,43 @@ impl TssService {
         if self.tss_local_state.is_node_aggregator {
             if self.tss_local_state.tss_process_state == TSSLocalStateType::CommitmentsReceived {
                 if let Ok(msg_req) = ReceivePartialSignatureReq::try_from_slice(data) {
-                    if let Some(msg) = self.tss_local_state.msg_pool.get(&msg_req.msg_hash) {
+                    if let Some(msg) = self.tss_local_state.msg_pool.get(&msg_req.msg_hash).cloned() {
                         //add in list
-                        if let Some(hashmap) = self
+                        let partial_signatures = self
                             .tss_local_state
                             .others_partial_signature
-                            .get_mut(&msg_req.msg_hash)
-                        {
-                            hashmap.push(msg_req.partial_sign);
-                        } else {
-                            let mut participant_list = Vec::new();
-                            participant_list.push(msg_req.partial_sign);
-                            self.tss_local_state
-                                .others_partial_signature
-                                .insert(msg_req.msg_hash, participant_list);
-                        }
+                            .entry(msg_req.msg_hash)
+                            .or_insert_with(Vec::new);
+                        partial_signatures.push(msg_req.partial_sign);
 
                         let params = self.tss_local_state.tss_params;
-                        //the unwrap wont fail since we are already adding item above
-                        if self
-                            .tss_local_state
-                            .others_partial_signature
-                            .get(&msg_req.msg_hash)
-                            .unwrap()
-                            .len()
-                            >= params.t as usize
-                        {
-                            let context = self.tss_local_state.context.clone();
-                            let finished_state =
-                                match self.tss_local_state.local_finished_state.clone() {
-                                    Some(finished_state) => finished_state,
+                        if partial_signatures.len() >= params.t as usize {
+                            let partial_signatures = partial_signatures.clone();
+                            let signers =
+                                match self.tss_local_state.msgs_signers.get(&msg_req.msg_hash) {
+                                    Some(signers) => signers.clone(),
                                     None => {
-                                        log::error!(
-                                    "TSS::Unable to get local finished state from local state"
-                                );
+                                        log::error!("TSS::Unable to get signers list for msg");
                                         return;
                                     }
                                 };
-                            let mut aggregator = SignatureAggregator::new(
-                                params,
-                                finished_state.0,
-                                &context,
-                                &msg[..],
-                            );
-
-                            for com in self.tss_local_state.others_commitment_share.clone() {
-                                aggregator.include_signer(
-                                    com.public_commitment_share_list.participant_index,
-                                    com.public_commitment_share_list.commitments[0],
-                                    com.public_key,
-                                );
-                            }
 
-                            //include partial signature
-                            for item in self
+                            let threshold_signature = match self
                                 .tss_local_state
-                                .others_partial_signature
-                                .get(&msg_req.msg_hash)
-                                .unwrap()
-                                .clone()
+                                .ciphersuite
+                                .aggregate(&msg, &signers, &partial_signatures)
                             {
-                                aggregator.include_partial_signature(item.clone());
-                            }
-
-                            //finalize aggregator
-                            let aggregator_finalized = match aggregator.finalize() {
-                                Ok(aggregator_finalized) => aggregator_finalized,
-                                Err(e) => {
-                                    log::error!(
-                                        "TSS::error occured while finalizing aggregator: {:?}",
-                                        e
-                                    );
-                                    return;
-                                }
-                            };
-
-                            //aggregate aggregator
-                            let threshold_signature = match aggregator_finalized.aggregate() {
                                 Ok(threshold_signature) => threshold_signature,
                                 Err(e) => {
                                     log::error!(
-                                        "TSS::error occured while aggregating aggregator: {:?}",
+                                        "TSS::error occured while aggregating signature: {}",
                                         e
                                     );
+                                    //msg stays in msg pool and is requested again with new signers
+                                    self.tss_local_state
+                                        .others_partial_signature
+                                        .remove(&msg_req.msg_hash);
+                                    self.tss_local_state.msgs_signers.remove(&msg_req.msg_hash);
                                     return;
                                 }
                             };
@@ -611,6 +574,7 @@ impl TssService {
                             self.tss_local_state
                                 .others_partial_signature
                                 .remove(&msg_req.msg_hash);
+                            self.tss_local_state.msgs_signers.remove(&msg_req.msg_hash);
 
                             //remove event from msg_pool
                             self.tss_local_state.msg_pool.remove(&msg_req.msg_hash);
@@ -636,16 +600,10 @@ impl TssService {
                     .msg_pool
                     .get(&threshold_signature.msg_hash)
                 {
-                    let finished_state = match self.tss_local_state.local_finished_state.clone() {
-                        Some(finished_state) => finished_state,
-                        None => {
-                            log::error!("TSS::Unable to get local finished state from local state");
-                            return;
-                        }
-                    };
-                    if let Ok(_) = threshold_signature
-                        .threshold_sign
-                        .verify(&finished_state.0, &threshold_signature.msg_hash.into())
+                    if let Ok(_) = self
+                        .tss_local_state
+                        .ciphersuite
+                        .verify(msg, &threshold_signature.threshold_sign)
                     {
                         let msg = match String::from_utf8(msg.clone()) {
                             Ok(msg) => msg,
@@ -655,26 +613,72 @@ impl TssService {
                             }
                         };
 
+                        let mode = self.attestation_mode;
+                        let threshold = if mode.with_threshold() {
+                            Some(ThresholdAttestation {
+                                ciphersuite: self.tss_local_state.ciphersuite.id().to_string(),
+                                group_key: hex::encode(
+                                    self.tss_local_state
+                                        .ciphersuite
+                                        .group_key()
+                                        .unwrap_or_default(),
+                                ),
+                                signature: hex::encode(&threshold_signature.threshold_sign),
+                                participants: self.tss_local_state.key_group_registrations(),
+                            })
+                        } else {
+                            None
+                        };
+
                         //sign message with account
-                        let keytype = match self.tss_local_state.key_type.clone() {
-                            Some(keytype) => keytype,
-                            None => return,
+                        let individual = if mode.with_individual() {
+                            let keytype = match self.tss_local_state.key_type.clone() {
+                                Some(keytype) => keytype,
+                                None => return,
+                            };
+
+                            match sign_data(
+                                self.account.clone(),
+                                msg.clone(),
+                                keytype,
+                                self.tss_local_state.keystore.clone().unwrap(),
+                            )
+                            .await
+                            {
+                                Ok(signature) => Some(IndividualAttestation::new(
+                                    &self.account.accounts,
+                                    &signature,
+                                    &msg,
+                                    self.registration.clone(),
+                                )),
+                                Err(e) => {
+                                    log::error!("error in signing message {:?}", e);
+                                    None
+                                }
+                            }
+                        } else {
+                            None
                         };
 
-                        match sign_data(
-                            self.account.clone(),
+                        match store_attestation(
                             self.connection.clone(),
-                            msg,
-                            keytype,
-                            self.tss_local_state.keystore.clone().unwrap(),
+                            &msg,
+                            &threshold_signature.msg_hash,
+                            threshold,
+                            individual,
                         )
                         .await
                         {
                             Ok(_) => {
-                                log::info!("message signed and stored successfully");
+                                log::info!("message attested with {} mode and stored successfully", mode);
+                                //event persisted at a shutdown is signed now
+                                let msg_hash = hex::encode(threshold_signature.msg_hash);
+                                if let Err(e) = self.connection.delete_pending_event(&msg_hash).await {
+                                    log::error!("error in removing signed pending event {}: {}", msg_hash, e);
+                                }
                             }
                             Err(e) => {
-                                log::error!("error in signing message {:?}", e);
+                                log::error!("error in storing attestation {:?}", e);
                             }
                         };
 
@@ -703,6 +707,102 @@ impl TssService {
         }
     }
 
+    //observations count once per peer of the key group, `source` is the peer that sent it
+    pub async fn handler_receive_observation(self: &mut Self, source: &str, data: &Vec<u8>) {
+        //only the aggregator proposes values
+        if !self.tss_local_state.is_node_aggregator {
+            return;
+        }
+        if !self.tss_local_state.is_key_group_member(source) {
+            log::warn!("TSS::Ignoring observation of {} outside key group", source);
+            return;
+        }
+
+        if let Ok(observation) = ObservationCall::try_from_slice(data) {
+            if observation.peer_id != source {
+                log::warn!(
+                    "TSS::Ignoring observation of {} sent by {}",
+                    observation.peer_id,
+                    source
+                );
+                return;
+            }
+            match observation.value.parse::<u128>() {
+                Ok(value) => {
+                    self.tss_local_state.observations.observe_peer(
+                        &observation.round_id,
+                        source.to_string(),
+                        value,
+                    );
+                    self.propose_round(&observation.round_id).await;
+                }
+                Err(e) => log::error!(
+                    "TSS::invalid observation from {}: {}",
+                    observation.peer_id,
+                    e
+                ),
+            }
+        } else {
+            log::error!("TSS::Unable to deserialize ObservationCall");
+        }
+    }
+
+    //only proposals of the aggregator are checked, `source` is the peer that sent it
+    pub async fn handler_receive_proposal(self: &mut Self, source: &str, data: &Vec<u8>) {
+        if self.tss_local_state.is_node_aggregator {
+            return;
+        }
+        if self.tss_local_state.aggregator_peer_id.as_deref() != Some(source) {
+            log::warn!("TSS::Ignoring proposal of {}, not the aggregator", source);
+            return;
+        }
+
+        if let Ok(proposal) = ProposalCall::try_from_slice(data) {
+            self.tss_local_state
+                .observations
+                .receive_proposal(&proposal.round_id, proposal.event);
+            self.check_round(&proposal.round_id).await;
+        } else {
+            log::error!("TSS::Unable to deserialize ProposalCall");
+        }
+    }
+
+    //only the aggregator asks for new commitments, `source` is the peer that sent the request
+    pub async fn handler_refresh_commitments(self: &mut Self, source: &str, data: &Vec<u8>) {
+        if self.tss_local_state.is_node_aggregator {
+            return;
+        }
+        if self.tss_local_state.aggregator_peer_id.as_deref() != Some(source) {
+            log::warn!("TSS::Ignoring commitment refresh of {}, not the aggregator", source);
+            return;
+        }
+        if RefreshCommitmentsCall::try_from_slice(data).is_err() {
+            log::error!("TSS::Unable to deserialize RefreshCommitmentsCall");
+            return;
+        }
+        if self.tss_local_state.tss_process_state < TSSLocalStateType::StateFinished {
+            log::error!("TSS::Node not in correct state to refresh commitments");
+            return;
+        }
+
+        let local_peer_id = self.tss_local_state.local_peer_id.clone().unwrap();
+        let share_commitment = match self.tss_local_state.ciphersuite.generate_commitment() {
+            Ok(commitment) => commitment,
+            Err(e) => {
+                log::error!("TSS::Unable to generate commitment: {}", e);
+                return;
+            }
+        };
+
+        log::info!("TSS::aggregator ran out of nonces, sending new commitments");
+        self.send_to_aggregator::<Vec<u8>>(
+            local_peer_id,
+            share_commitment,
+            TSSEventType::ReceiveCommitment,
+        )
+        .await;
+    }
+
     pub async fn handler_reset_tss_state(self: &mut Self, data: &Vec<u8>) {
         //reset TSS State
         if let Ok(reason) = ResetTSSCall::try_from_slice(data) {
@@ -724,7 +824,11 @@ impl TssService {
         log::info!("TSS::sending tss event: {:?}", tss_type);
         if let Ok(encoded_data) = data.try_to_vec() {
             if let Ok(data) = make_gossip_tss_data(peer_id, encoded_data, tss_type) {
-                if let Err(e) = self.tss_to_gossip_sender.send(data).await {
+                if let Err(e) = self
+                    .tss_to_gossip_sender
+                    .send(OutboundMessage::Broadcast(data))
+                    .await
+                {
                     log::error!("TSS::error sending tss data via gossip {}", e);
                 }
             } else {
@@ -735,4 +839,47 @@ impl TssService {
             log::error!("TSS::tss error");
         }
     }
+
+    /// sends tss data to a single node of the key group over the direct protocol
+    pub async fn send_to_peer<T>(
+        self: &Self,
+        peer_id: String,
+        recipient: String,
+        data: T,
+        tss_type: TSSEventType,
+    ) where
+        T: BorshSerialize,
+    {
+        log::info!("TSS::sending tss event {:?} to {}", tss_type, recipient);
+        if let Ok(encoded_data) = data.try_to_vec() {
+            if let Ok(data) = make_gossip_tss_data(peer_id, encoded_data, tss_type) {
+                let message = OutboundMessage::Direct {
+                    peer_id: recipient,
+                    data,
+                };
+                if let Err(e) = self.tss_to_gossip_sender.send(message).await {
+                    log::error!("TSS::error sending tss data to peer {}", e);
+                }
+            } else {
+                log::error!("TSS::error making gossip data for encoded participant");
+            }
+        } else {
+            log::error!("TSS::tss error");
+        }
+    }
+
+    /// sends tss data only the aggregator uses, the aggregator keeps its own data locally
+    pub async fn send_to_aggregator<T>(self: &Self, peer_id: String, data: T, tss_type: TSSEventType)
+    where
+        T: BorshSerialize,
+    {
+        match self.tss_local_state.aggregator_peer_id.clone() {
+            Some(aggregator) if aggregator != peer_id => {
+                self.send_to_peer(peer_id, aggregator, data, tss_type).await
+            }
+            Some(_) => {}
+            None if self.tss_local_state.is_node_aggregator => {}
+            None => log::warn!("TSS::aggregator unknown, dropping {:?}", tss_type),
+        }
+    }
 }
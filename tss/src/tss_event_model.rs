@@ -1,5 +1,8 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use frost_dalek::Parameters;
+use serde::{Deserialize, Serialize};
+
+use crate::ciphersuite::CiphersuiteId;
 
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub enum TSSEventType {
@@ -14,6 +17,9 @@ pub enum TSSEventType {
     PartialSignatureReceived,
     VerifyThresholdSignature,
     ResetTSSState,
+    ReceiveObservation,
+    ReceiveProposal,
+    RefreshCommitments,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
@@ -27,6 +33,8 @@ pub struct TSSData {
 pub struct PublishPeerIDCall {
     pub peer_id: String,
     pub random: String,
+    /// json registration binding the peer to its account
+    pub registration: String,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
@@ -35,9 +43,50 @@ pub struct ResetTSSCall {
     pub random: String,
 }
 
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct RefreshCommitmentsCall {
+    pub random: String,
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub struct ReceiveParamsWithPeerCall {
     pub peer_id: String,
     pub random: String,
     pub params: Parameters,
-}
\ No newline at end of file
+    pub ciphersuite: CiphersuiteId,
+    /// json registration of the collector
+    pub registration: String,
+}
+/// Value a node observed for a round, collected by the aggregator.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct ObservationCall {
+    pub peer_id: String,
+    pub round_id: String,
+    pub value: String,
+}
+
+/// Event agreed on by the aggregator for a round, signed by nodes whose own observation is close to it.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct ProposalCall {
+    pub round_id: String,
+    pub event: String,
+}
+
+/// Sent by a connector when an event it already passed to TSS was reorged out of the chain.
+/// `event` is the exact string of the cancelled event so its msg hash can be recomputed.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CancelEvent {
+    pub event_type: String,
+    pub event: String,
+}
+
+impl CancelEvent {
+    pub const EVENT_TYPE: &'static str = "cancel";
+
+    /// returns cancellation if data received from connector is one
+    pub fn parse(data: &str) -> Option<Self> {
+        serde_json::from_str::<CancelEvent>(data)
+            .ok()
+            .filter(|cancel| cancel.event_type == Self::EVENT_TYPE)
+    }
+}
@@ -1,52 +1,76 @@
+use crate::attestation::AttestationMode;
+use crate::ciphersuite::{CiphersuiteError, CiphersuiteId};
+use crate::consensus::ObservedEvent;
 use crate::local_state_struct::PartialMessageSign;
-use crate::utils::{get_receive_params_msg, make_gossip_tss_data, get_reset_tss_msg};
+use crate::msg_pool::MsgPoolConfig;
+use crate::utils::{
+    get_receive_params_msg, get_refresh_commitments_msg, make_gossip_tss_data, get_reset_tss_msg,
+};
 use crate::DEFUALT_TSS_TOTAL_NODES;
 use crate::{
     local_state_struct::{TSSLocalStateData, TSSLocalStateType},
     // tss_event_handler::handle_tss_events,
-    tss_event_model::{TSSData, TSSEventType},
+    tss_event_model::{CancelEvent, ObservationCall, ProposalCall, TSSData, TSSEventType},
 };
-use accounts::Account;
+use accounts::{Account, Registration};
 use borsh::BorshSerialize;
-use frost_dalek::signature::Signer;
+use events::events::OutboundMessage;
+use events::tss::{TssCommand, TssStatus};
 
-use frost_dalek::{compute_message_hash, Parameters, SignatureAggregator};
+use frost_dalek::{compute_message_hash, Parameters};
 use keystore::commands::KeyTypeId;
 use sp_keystore::SyncCryptoStore;
+use std::collections::HashSet;
 use std::sync::Arc;
-use tango_database::MongoRepo;
+use tango_database::models::PendingEvent;
+use tango_database::Repo;
 use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::watch;
 use tokio::time;
 
-pub const MIN_THRESHOLD_PERCENTAGE: u32 = 75;
-pub const COLLECTOR_ADDR: Option<String> = None;
+/// keygen started by the collector is restarted if the group has no commitments by then,
+/// e.g. because a secret share could not be delivered
+pub const KEYGEN_TIMEOUT: time::Duration = time::Duration::from_secs(120);
 
 pub struct TssService {
     pub gossip_to_tss_receiver: Receiver<TSSData>,
-    pub tss_to_gossip_sender: Sender<Vec<u8>>,
+    pub tss_to_gossip_sender: Sender<OutboundMessage>,
     pub tss_local_state: TSSLocalStateData,
     pub event_receiver: Receiver<String>,
+    pub commands: Receiver<TssCommand>,
     pub account: Account,
-    pub connection: MongoRepo,
+    /// binds the peer id of this node to `account`
+    pub registration: Registration,
+    pub connection: Arc<dyn Repo>,
+    pub attestation_mode: AttestationMode,
 }
 
 impl TssService {
     pub async fn new(
         gossip_to_tss_receiver: Receiver<TSSData>,
-        tss_to_gossip_sender: Sender<Vec<u8>>,
+        tss_to_gossip_sender: Sender<OutboundMessage>,
         event_receiver: Receiver<String>,
+        commands: Receiver<TssCommand>,
         account: Account,
-        connection: MongoRepo,
+        registration: Registration,
+        connection: Arc<dyn Repo>,
         //collector patch
         is_default_node: bool,
+        //peer id of the collector, the default node collects without one
+        collector: Option<String>,
         peer_id: String,
         tss_nodes_and_threshold_nodes: (u32, u32),
         key_type: Option<KeyTypeId>,
         keystore_option: Option<Arc<dyn SyncCryptoStore>>,
+        ciphersuite: CiphersuiteId,
+        attestation_mode: AttestationMode,
+        observation_tolerance_bps: u64,
+        msg_pool_config: MsgPoolConfig,
+        committee: Option<HashSet<String>>,
     ) -> Self {
         // let arced_tss_state_data = Arc::new(Mutex::new(TSSLocalStateData::new()));
-        let mut unlocked_state = TSSLocalStateData::new();
-        if let Some(c_addr) = COLLECTOR_ADDR {
+        let mut unlocked_state = TSSLocalStateData::new(ciphersuite, msg_pool_config);
+        if let Some(c_addr) = collector {
             if c_addr.eq(&peer_id) {
                 unlocked_state.is_node_collector = true;
             } else {
@@ -59,6 +83,11 @@ impl TssService {
         unlocked_state.local_peer_id = Some(peer_id);
         unlocked_state.key_type = key_type;
         unlocked_state.keystore = keystore_option;
+        unlocked_state.observation_tolerance_bps = observation_tolerance_bps;
+        unlocked_state.committee = committee;
+        unlocked_state
+            .registrations
+            .insert(registration.peer_id.clone(), registration.clone());
 
         if tss_nodes_and_threshold_nodes.0 >= DEFUALT_TSS_TOTAL_NODES as u32 {
             //stop if total nodes for tss provided and threshold number if invalid
@@ -78,14 +107,55 @@ impl TssService {
             tss_to_gossip_sender,
             tss_local_state: unlocked_state,
             event_receiver,
+            commands,
             account,
+            registration,
             connection,
+            attestation_mode,
         }
     }
-    pub async fn run(self: &mut Self) {
+
+    /// registration of this node as sent along with its peer id
+    pub fn registration_json(self: &Self) -> Result<String, String> {
+        serde_json::to_string(&self.registration)
+            .map_err(|e| format!("Unable to encode registration: {}", e))
+    }
+    /// Runs until `shutdown` turns true. TSS then stops taking connector events and keeps
+    /// signing for up to `grace`, events still unsigned after it are persisted and
+    /// resubmitted on the next start.
+    pub async fn run(self: &mut Self, mut shutdown: watch::Receiver<bool>, grace: time::Duration) {
         let mut timer = time::interval(time::Duration::from_secs(5));
+        let mut draining = false;
+        let drain_deadline = time::sleep(grace);
+        tokio::pin!(drain_deadline);
+
         loop {
+            //in-flight signing finished, nothing left to persist
+            if draining && self.tss_local_state.msg_pool.is_empty() {
+                log::info!("TSS::all events signed, stopping");
+                break;
+            }
+
             tokio::select! {
+                //node is shutting down
+                Ok(()) = shutdown.changed(), if !draining => {
+                    if *shutdown.borrow() {
+                        log::info!(
+                            "TSS::shutting down, signing {} in-flight events for up to {:?}",
+                            self.tss_local_state.msg_pool.len(),
+                            grace
+                        );
+                        draining = true;
+                        drain_deadline.as_mut().reset(time::Instant::now() + grace);
+                    }
+                }
+
+                //grace period is over
+                _ = &mut drain_deadline, if draining => {
+                    self.persist_pending_events().await;
+                    break;
+                }
+
                 //received event from network
                 r = self.gossip_to_tss_receiver.recv() => {
                     if let Some(data) = r{
@@ -96,63 +166,43 @@ impl TssService {
                     }
                 }
 
+                //received a query about the tss
+                Some(command) = self.commands.recv() => {
+                    match command {
+                        TssCommand::Status(reply) => {
+                            let _ = reply.send(self.status());
+                        }
+                    }
+                }
+
                 //if event is receiver from connector side then publish for signing
-                event_receiver = self.event_receiver.recv() => {
+                //connector events wait in their channel while msg pool is full
+                //connector events are not taken while shutting down
+                event_receiver = self.event_receiver.recv(), if !self.tss_local_state.msg_pool.is_full() && !draining => {
                     // let tss_local_state = self.tss_local_state;
                     let local_peer_id = self.tss_local_state.local_peer_id.clone().unwrap();
 
                     if let Some(data) = event_receiver{
-                        log::info!("got event to tss {:?}", data);
-                        let context = self.tss_local_state.context;
-                        let msg_hash = compute_message_hash(&context, &data.as_bytes());
-
-                        //add node in msg_pool
-                        if !self.tss_local_state.msg_pool.contains_key(&msg_hash){
-                            self.tss_local_state.msg_pool.insert(msg_hash.clone(), data.clone().into());
-
-                            //process msg if req already received
-                            if let Some(pending_msg_req) = self.tss_local_state.msgs_signature_pending.get(&msg_hash){
-                                self.process_pending_msg_req(msg_hash.clone(), pending_msg_req.to_vec()).await;
-                                self.tss_local_state.msgs_signature_pending.remove(&msg_hash);
-                            }
-
-                        }else{
-                            log::warn!("Msg already in pool");
+                        //event was reorged out of the chain before it got signed
+                        if let Some(cancel) = CancelEvent::parse(&data){
+                            self.cancel_event(&cancel.event);
+                            continue;
                         }
 
-                        //creating signature aggregator for msg
-                        if self.tss_local_state.is_node_aggregator{
-                            //all nodes should share the same message hash
-                            //to verify the threshold signature
-                            let mut aggregator = SignatureAggregator::new(
-                                self.tss_local_state.tss_params,
-                                self.tss_local_state.local_finished_state.clone().unwrap().0,
-                                &context,
-                                &data.as_bytes()[..],
-                            );
-
-                            for com in self.tss_local_state.others_commitment_share.clone(){
-                                aggregator.include_signer(
-                                    com.public_commitment_share_list.participant_index,
-                                    com.public_commitment_share_list.commitments[0],
-                                    com.public_key,
-                                );
+                        //observed values are agreed on before they are signed
+                        match ObservedEvent::parse(&data){
+                            Some(Ok(observed)) => {
+                                self.observe_event(local_peer_id, observed).await;
+                                continue;
                             }
-
-                            //this signers list will be used by other nodes to verify themselves.
-                            let signers = aggregator.get_signers();
-
-                            let sign_msg_req = PartialMessageSign{
-                                msg_hash: msg_hash.clone(),
-                                signers: signers.clone()
-                            };
-
-                            self.publish_to_network(local_peer_id.clone(),
-                                sign_msg_req,
-                                TSSEventType::PartialSignatureGenerateReq,
-                        ).await;
-
+                            Some(Err(e)) => {
+                                log::error!("TSS::dropping swap event: {}", e);
+                                continue;
+                            }
+                            None => {}
                         }
+
+                        self.submit_event(local_peer_id, data).await;
                     }else{
                         log::error!("No data received from event receiver");
                     }
@@ -160,23 +210,54 @@ impl TssService {
 
                 //time loop to start tss process
                 _ = timer.tick() => {
+                    //evict msgs which did not reach threshold in time
+                    self.tss_local_state.expire_msgs();
+                    let metrics = self.tss_local_state.pool_metrics();
+                    if self.tss_local_state.msg_pool.is_full() {
+                        log::warn!("TSS::msg pool full, not taking connector events: {}", metrics);
+                    } else {
+                        log::debug!("TSS::queues {}", metrics);
+                    }
+
+                    //events of the last run are signed by the key group, once it exists
+                    if !self.tss_local_state.pending_events_resumed
+                        && self.tss_local_state.tss_process_state >= TSSLocalStateType::StateFinished
+                    {
+                        self.resume_pending_events().await;
+                    }
+
+                    //msgs which could not be requested for signing yet
+                    self.request_pending_signatures().await;
+
+                    //keygen stalled, collector starts it over
+                    if let Some(started) = self.tss_local_state.keygen_started {
+                        if self.tss_local_state.tss_process_state >= TSSLocalStateType::CommitmentsReceived {
+                            self.tss_local_state.keygen_started = None;
+                        } else if started.elapsed() > KEYGEN_TIMEOUT {
+                            log::warn!("TSS::keygen did not finish in {:?}, restarting", KEYGEN_TIMEOUT);
+                            self.tss_local_state.reset_keygen();
+                        }
+                    }
+
                     //collector node starting TSS process
                     if let Some(local_peer_id) = self.tss_local_state.local_peer_id.clone(){
                         if self.tss_local_state.is_node_collector && self.tss_local_state.tss_process_state <= TSSLocalStateType::ReceivedPeers{
+                            self.tss_local_state.keygen_started.get_or_insert_with(std::time::Instant::now);
 
                             //sending reset state request to all nodes since didn't received good amount of nodes.
                             if let Ok(reset_call) = get_reset_tss_msg("Reinit state".into()){
                                 if let Ok(reset_data) = make_gossip_tss_data(local_peer_id.clone(), reset_call, TSSEventType::ResetTSSState){
-                                    if let Err(e) = self.tss_to_gossip_sender.send(reset_data).await{
+                                    if let Err(e) = self.tss_to_gossip_sender.send(OutboundMessage::Broadcast(reset_data)).await{
                                         log::error!("error sending TSS reset request to gossip: {:?}", e);
                                     }
                                 }
                             }
 
                             //sending gossip to start tss initialization process
-                            if let Ok(peer_id_data) = get_receive_params_msg(local_peer_id.clone(), self.tss_local_state.tss_params){
+                            let params_msg = self.registration_json().and_then(|registration| get_receive_params_msg(local_peer_id.clone(), self.tss_local_state.tss_params, self.tss_local_state.ciphersuite.id(), registration));
+                            if let Ok(peer_id_data) = params_msg{
                                 if let Ok(data) = make_gossip_tss_data(local_peer_id.clone(), peer_id_data, TSSEventType::ReceiveParams){
-                                    let _ = self.tss_to_gossip_sender.send(data).await;
+                                    let _ = self.tss_to_gossip_sender.send(OutboundMessage::Broadcast(data)).await;
                                     log::info!("TSS peer collection req sent");
                                 }
                             }else{
@@ -191,10 +272,318 @@ impl TssService {
         }
     }
 
+    /// Key group and queues of this node, answered on status queries.
+    pub fn status(self: &Self) -> TssStatus {
+        let state = &self.tss_local_state;
+        TssStatus {
+            local_peer_id: state.local_peer_id.clone().unwrap_or_default(),
+            state: format!("{:?}", state.tss_process_state),
+            is_collector: state.is_node_collector,
+            is_aggregator: state.is_node_aggregator,
+            ciphersuite: state.ciphersuite.id().to_string(),
+            participants: state.tss_params.n,
+            threshold: state.tss_params.t,
+            peers: state.others_peer_id.clone(),
+            group_key: state.ciphersuite.group_key().map(hex::encode),
+            msg_pool: state.msg_pool.len(),
+            pending_signatures: state.msgs_signature_pending.len(),
+        }
+    }
+
+    /// Stores the events of msg pool which were not signed before shutdown.
+    pub async fn persist_pending_events(self: &mut Self) {
+        let pending: Vec<PendingEvent> = self
+            .tss_local_state
+            .msg_pool
+            .drain()
+            .into_iter()
+            .map(|(msg_hash, event)| {
+                PendingEvent::new(hex::encode(msg_hash), String::from_utf8_lossy(&event).into())
+            })
+            .collect();
+        if pending.is_empty() {
+            return;
+        }
+
+        let count = pending.len();
+        match self.connection.upsert_pending_events(pending).await {
+            Ok(_) => log::warn!("TSS::persisted {} unsigned events for next start", count),
+            Err(e) => log::error!("TSS::unable to persist {} unsigned events: {}", count, e),
+        }
+    }
+
+    /// Submits the events persisted at the last shutdown again once keygen finished, each
+    /// stays stored until its signature is. Observed events were agreed on before they
+    /// entered msg pool, so they are submitted as they are.
+    pub async fn resume_pending_events(self: &mut Self) {
+        let local_peer_id = self.tss_local_state.local_peer_id.clone().unwrap();
+        let pending = match self.connection.get_pending_events().await {
+            Ok(pending) => pending,
+            Err(e) => {
+                log::error!("TSS::unable to read unsigned events of last run: {}", e);
+                return;
+            }
+        };
+        if !pending.is_empty() {
+            log::info!("TSS::resubmitting {} unsigned events of last run", pending.len());
+        }
+        self.tss_local_state.pending_events_resumed = true;
+        for pending_event in pending {
+            self.submit_event(local_peer_id.clone(), pending_event.event)
+                .await;
+        }
+    }
+
+    /// Adds event to msg pool, the aggregator requests partial signatures for it.
+    pub async fn submit_event(self: &mut Self, local_peer_id: String, data: String) {
+        log::info!("got event to tss {:?}", data);
+        let context = self.tss_local_state.context;
+        let msg_hash = compute_message_hash(&context, &data.as_bytes());
+
+        //add node in msg_pool
+        if !self.tss_local_state.msg_pool.contains_key(&msg_hash) {
+            if let Some((evicted, reason)) = self
+                .tss_local_state
+                .msg_pool
+                .insert(msg_hash.clone(), data.clone().into())
+            {
+                self.tss_local_state.evict_msg(evicted, reason);
+            }
+
+            //process msg if req already received
+            if let Some(pending_msg_req) = self
+                .tss_local_state
+                .msgs_signature_pending
+                .get(&msg_hash)
+                .cloned()
+            {
+                self.process_pending_msg_req(msg_hash.clone(), pending_msg_req)
+                    .await;
+                self.tss_local_state.msgs_signature_pending.remove(&msg_hash);
+            }
+        } else {
+            log::warn!("Msg already in pool");
+        }
+
+        //creating signers list for msg
+        if self.tss_local_state.is_node_aggregator {
+            self.request_partial_signatures(local_peer_id, msg_hash)
+                .await;
+        }
+    }
+
+    /// Aggregator picks the signers of a msg in msg pool, signs it itself and asks the
+    /// other signers for their partial signatures. Msgs arriving before the commitments
+    /// wait in msg pool for `request_pending_signatures`.
+    pub async fn request_partial_signatures(
+        self: &mut Self,
+        local_peer_id: String,
+        msg_hash: [u8; 64],
+    ) {
+        if self.tss_local_state.tss_process_state != TSSLocalStateType::CommitmentsReceived
+            || self.tss_local_state.msgs_signers.contains_key(&msg_hash)
+        {
+            return;
+        }
+        let msg = match self.tss_local_state.msg_pool.get(&msg_hash).cloned() {
+            Some(msg) => msg,
+            None => return,
+        };
+
+        //this signers list will be used by other nodes to verify themselves.
+        let signers = match self.tss_local_state.ciphersuite.signers(&msg) {
+            Ok(signers) => signers,
+            Err(CiphersuiteError::NoncesExhausted) => {
+                self.refresh_commitments(local_peer_id).await;
+                return;
+            }
+            Err(e) => {
+                log::error!("TSS::Unable to make signers list: {}", e);
+                return;
+            }
+        };
+
+        //aggregator is always one of the signers
+        let partial_signature = match self
+            .tss_local_state
+            .ciphersuite
+            .partial_sign(&msg, &signers)
+        {
+            Ok(partial_signature) => partial_signature,
+            Err(e) => {
+                log::error!("TSS::error occured while signing: {}", e);
+                return;
+            }
+        };
+        self.tss_local_state
+            .others_partial_signature
+            .insert(msg_hash, vec![partial_signature]);
+        self.tss_local_state
+            .msgs_signers
+            .insert(msg_hash, signers.clone());
+
+        let sign_msg_req = PartialMessageSign { msg_hash, signers };
+
+        self.publish_to_network(
+            local_peer_id,
+            sign_msg_req,
+            TSSEventType::PartialSignatureGenerateReq,
+        )
+        .await;
+    }
+
+    /// Aggregator requests partial signatures for msgs in msg pool which have no signers
+    /// yet, e.g. msgs which arrived before the commitments or while they were refreshed.
+    pub async fn request_pending_signatures(self: &mut Self) {
+        if !self.tss_local_state.is_node_aggregator
+            || self.tss_local_state.tss_process_state != TSSLocalStateType::CommitmentsReceived
+        {
+            return;
+        }
+        let local_peer_id = match self.tss_local_state.local_peer_id.clone() {
+            Some(local_peer_id) => local_peer_id,
+            None => return,
+        };
+
+        let pending: Vec<[u8; 64]> = self
+            .tss_local_state
+            .msg_pool
+            .keys()
+            .into_iter()
+            .filter(|msg_hash| !self.tss_local_state.msgs_signers.contains_key(msg_hash))
+            .collect();
+        for msg_hash in pending {
+            self.request_partial_signatures(local_peer_id.clone(), msg_hash)
+                .await;
+            //nonces ran out, the rest waits for the new commitments
+            if self.tss_local_state.tss_process_state != TSSLocalStateType::CommitmentsReceived {
+                break;
+            }
+        }
+    }
+
+    /// Aggregator ran out of signing nonces, it drops the commitments of all nodes and asks
+    /// them for new ones. Msgs being signed are requested again once the commitments arrive.
+    pub async fn refresh_commitments(self: &mut Self, local_peer_id: String) {
+        log::warn!("TSS::signing nonces exhausted, refreshing commitments");
+        if let Err(e) = self.tss_local_state.ciphersuite.refresh_commitments() {
+            log::error!("TSS::Unable to refresh commitments: {}", e);
+            return;
+        }
+        self.tss_local_state.tss_process_state = TSSLocalStateType::StateFinished;
+        self.tss_local_state.msgs_signers.clear();
+        self.tss_local_state.others_partial_signature.clear();
+
+        let refresh_data = get_refresh_commitments_msg().and_then(|refresh_call| {
+            make_gossip_tss_data(local_peer_id, refresh_call, TSSEventType::RefreshCommitments)
+        });
+        match refresh_data {
+            Ok(data) => {
+                if let Err(e) = self
+                    .tss_to_gossip_sender
+                    .send(OutboundMessage::Broadcast(data))
+                    .await
+                {
+                    log::error!("TSS::error sending refresh commitments request: {:?}", e);
+                }
+            }
+            Err(e) => log::error!("TSS::Unable to make refresh commitments request: {}", e),
+        }
+    }
+
+    /// Shares the value this node observed for a round. The aggregator proposes once enough
+    /// nodes observed it, other nodes check a proposal that arrived before their observation.
+    pub async fn observe_event(self: &mut Self, local_peer_id: String, observed: ObservedEvent) {
+        let round_id = observed.round_id.clone();
+        log::info!("TSS::observed {} for round {}", observed.value, round_id);
+
+        let observation = ObservationCall {
+            peer_id: local_peer_id.clone(),
+            round_id: round_id.clone(),
+            value: observed.value.to_string(),
+        };
+        self.tss_local_state.observations.observe_own(observed);
+        self.send_to_aggregator(local_peer_id, observation, TSSEventType::ReceiveObservation)
+            .await;
+
+        if self.tss_local_state.is_node_aggregator {
+            self.propose_round(&round_id).await;
+        } else {
+            self.check_round(&round_id).await;
+        }
+    }
+
+    /// aggregator publishes the agreed event of a round and signs it like any other event
+    pub async fn propose_round(self: &mut Self, round_id: &str) {
+        let local_peer_id = match self.tss_local_state.local_peer_id.clone() {
+            Some(local_peer_id) => local_peer_id,
+            None => return,
+        };
+        let min_observations = self.tss_local_state.tss_params.t as usize;
+        let event = match self
+            .tss_local_state
+            .observations
+            .propose(round_id, min_observations)
+        {
+            Some(event) => event,
+            None => return,
+        };
+
+        log::info!("TSS::proposing {} for round {}", event, round_id);
+        let proposal = ProposalCall {
+            round_id: round_id.to_string(),
+            event: event.clone(),
+        };
+        self.publish_to_network(
+            local_peer_id.clone(),
+            proposal,
+            TSSEventType::ReceiveProposal,
+        )
+        .await;
+        self.submit_event(local_peer_id, event).await;
+    }
+
+    /// adds proposal of a round to msg pool if it is within tolerance of the own observation
+    pub async fn check_round(self: &mut Self, round_id: &str) {
+        let local_peer_id = match self.tss_local_state.local_peer_id.clone() {
+            Some(local_peer_id) => local_peer_id,
+            None => return,
+        };
+        let tolerance_bps = self.tss_local_state.observation_tolerance_bps;
+        match self
+            .tss_local_state
+            .observations
+            .check_proposal(round_id, tolerance_bps)
+        {
+            Some(Ok(event)) => self.submit_event(local_peer_id, event).await,
+            Some(Err(reason)) => {
+                log::warn!("TSS::not signing proposal of round {}: {}", round_id, reason)
+            }
+            None => {}
+        }
+    }
+
+    /// drops cancelled event from msg pool and signing state so it is never signed
+    pub fn cancel_event(self: &mut Self, event: &str) {
+        let context = self.tss_local_state.context;
+        let msg_hash = compute_message_hash(&context, event.as_bytes());
+
+        let in_pool = self.tss_local_state.msg_pool.remove(&msg_hash).is_some();
+        self.tss_local_state.msgs_signature_pending.remove(&msg_hash);
+        self.tss_local_state.msgs_signers.remove(&msg_hash);
+        self.tss_local_state.others_partial_signature.remove(&msg_hash);
+
+        if in_pool {
+            log::info!("TSS::Cancelled event {}", hex::encode(msg_hash));
+        } else {
+            log::warn!("TSS::Cancelled event not in msg pool {}", hex::encode(msg_hash));
+        }
+    }
+
     pub async fn process_pending_msg_req(
         self: &mut Self,
         msg_hash: [u8; 64],
-        signer_for_msg: Vec<Signer>,
+        signer_for_msg: Vec<u8>,
     ) {
         //create req object
         let req = PartialMessageSign {
@@ -1,14 +1,14 @@
-use std::collections::HashMap;
-
 use borsh::BorshSerialize;
 use events::events::{Event, EventType};
 use frost_dalek::{
-    keygen::{Coefficients, RoundOne, SecretShare},
+    keygen::{Coefficients, RoundOne},
     DistributedKeyGeneration, Parameters, Participant,
 };
 
-use crate::tss_event_model::{PublishPeerIDCall, TSSData, TSSEventType, ReceiveParamsWithPeerCall, ResetTSSCall};
+use crate::ciphersuite::CiphersuiteId;
+use crate::tss_event_model::{PublishPeerIDCall, TSSData, TSSEventType, ReceiveParamsWithPeerCall, RefreshCommitmentsCall, ResetTSSCall};
 
+use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn make_gossip_tss_data(
@@ -33,15 +33,6 @@ pub fn make_gossip_tss_data(
     }
 }
 
-pub async fn make_hashmap_for_secret_share(
-    secret_shares: &Vec<SecretShare>,
-) -> HashMap<u32, SecretShare> {
-    secret_shares
-        .iter()
-        .map(|x| (x.index, x.clone()))
-        .collect::<HashMap<u32, SecretShare>>()
-}
-
 pub fn make_participant(params: Parameters, index: u32) -> (Participant, Coefficients) {
     Participant::new(&params, index)
 }
@@ -59,12 +50,50 @@ pub fn get_participant_index(peer_id: String, other_peer_id: &Vec<String>) -> u3
     return (list_length + 1) as u32;
 }
 
-pub fn get_publish_peer_id_msg(local_peer: String) -> Result<Vec<u8>, String> {
+/// Peer of every other participant by index, the inverse of `get_participant_index`.
+pub fn get_participant_peers(local_peer: &str, other_peer_id: &Vec<String>) -> HashMap<u32, String> {
+    let mut all_peers = other_peer_id.clone();
+    all_peers.push(local_peer.to_string());
+
+    other_peer_id
+        .iter()
+        .map(|peer_id| {
+            let others: Vec<String> = all_peers
+                .iter()
+                .filter(|other| *other != peer_id)
+                .cloned()
+                .collect();
+            (get_participant_index(peer_id.clone(), &others), peer_id.clone())
+        })
+        .collect()
+}
+
+pub fn get_publish_peer_id_msg(
+    local_peer: String,
+    registration: String,
+) -> Result<Vec<u8>, String> {
     let start = SystemTime::now();
     if let Ok(since_the_epoch) = start.duration_since(UNIX_EPOCH) {
         let data = PublishPeerIDCall {
             peer_id: local_peer,
             random: since_the_epoch.as_millis().to_string(),
+            registration,
+        };
+
+        match data.try_to_vec() {
+            Ok(data) => Ok(data),
+            Err(_) => Err("Unable to convert data into vec".into()),
+        }
+    } else {
+        Err("Unable to get time difference".into())
+    }
+}
+
+pub fn get_refresh_commitments_msg() -> Result<Vec<u8>, String> {
+    let start = SystemTime::now();
+    if let Ok(since_the_epoch) = start.duration_since(UNIX_EPOCH) {
+        let data = RefreshCommitmentsCall {
+            random: since_the_epoch.as_millis().to_string(),
         };
 
         match data.try_to_vec() {
@@ -93,13 +122,20 @@ pub fn get_reset_tss_msg(reason: String) -> Result<Vec<u8>, String> {
     }
 }
 
-pub fn get_receive_params_msg(local_peer: String, params: Parameters) -> Result<Vec<u8>, String> {
+pub fn get_receive_params_msg(
+    local_peer: String,
+    params: Parameters,
+    ciphersuite: CiphersuiteId,
+    registration: String,
+) -> Result<Vec<u8>, String> {
     let start = SystemTime::now();
     if let Ok(since_the_epoch) = start.duration_since(UNIX_EPOCH) {
         let data = ReceiveParamsWithPeerCall {
             peer_id: local_peer,
             random: since_the_epoch.as_millis().to_string(),
-            params
+            params,
+            ciphersuite,
+            registration,
         };
 
         match data.try_to_vec() {